@@ -16,8 +16,8 @@ const GRANULAR_DRAG_MULTIPLIER: f32 = 0.1;
 pub struct ParamSlider {
     param_base: ParamWidgetBase,
 
-    /// Will be set to `true` when the field gets Alt+Click'ed which will replace the label with a
-    /// text box.
+    /// Will be set to `true` when the field gets Alt+Click'ed or double clicked which will replace
+    /// the label with a text box.
     text_input_active: bool,
     /// Will be set to `true` if we're dragging the parameter. Resetting the parameter or entering a
     /// text value should not initiate a drag.
@@ -469,8 +469,8 @@ impl View for ParamSlider {
                     self.text_input_active = true;
                     cx.set_active(true);
                 } else if cx.modifiers.command() {
-                    // Ctrl+Click, double click, and right clicks should reset the parameter instead
-                    // of initiating a drag operation
+                    // Ctrl+Click and right clicks should reset the parameter instead of initiating
+                    // a drag operation
                     self.param_base.begin_set_parameter(cx);
                     self.param_base
                         .set_normalized_value(cx, self.param_base.default_normalized_value());
@@ -501,12 +501,20 @@ impl View for ParamSlider {
 
                 meta.consume();
             }
-            WindowEvent::MouseDoubleClick(MouseButton::Left)
-            | WindowEvent::MouseDown(MouseButton::Right)
+            WindowEvent::MouseDoubleClick(MouseButton::Left) => {
+                // Double click brings up the same text entry dialog as Alt+Click, so exact values
+                // can be typed in. Invalid input is ignored and parsed values get clamped to the
+                // parameter's range by `string_to_normalized_value()`.
+                self.text_input_active = true;
+                cx.set_active(true);
+
+                meta.consume();
+            }
+            WindowEvent::MouseDown(MouseButton::Right)
             | WindowEvent::MouseDoubleClick(MouseButton::Right)
             | WindowEvent::MouseTripleClick(MouseButton::Right) => {
-                // Ctrl+Click, double click, and right clicks should reset the parameter instead of
-                // initiating a drag operation
+                // Ctrl+Click and right clicks should reset the parameter instead of initiating a
+                // drag operation
                 self.param_base.begin_set_parameter(cx);
                 self.param_base
                     .set_normalized_value(cx, self.param_base.default_normalized_value());
@@ -0,0 +1,109 @@
+use crate::{filter::BiquadFilter, random::Xorshift};
+
+/// stage counts of the common bucket-brigade chips, from the MN3009 to the MN3005
+pub const MIN_STAGES: usize = 256;
+pub const MAX_STAGES: usize = 4096;
+pub const DEFAULT_STAGES: usize = 1024;
+// the anti-aliasing and reconstruction filters sit at this fraction of the clock rate, a bit
+// below the chip's Nyquist frequency of half the clock like in the classic designs
+const BANDWIDTH_CLOCK_RATIO: f32 = 0.3;
+const FILTER_Q: f32 = 0.707;
+// level of the hiss at `DEFAULT_STAGES`, every stage adds a bit of it
+const NOISE_LEVEL: f32 = 0.0003;
+// input gain of the soft compression, the level stays about the same for quiet signals
+const COMPRESSION_DRIVE: f32 = 1.5;
+
+/// the analog voicing of a bucket-brigade delay chip around a digital delay line. The signal is
+/// softly compressed, band limited and sampled at the chip's clock rate on the way in, and picks
+/// up hiss and goes through the reconstruction filter on the way out. The clock follows the delay
+/// time, `stages / (2 * delay)`, so longer delays get darker, noisier and alias more
+#[derive(Clone)]
+pub struct Bbd {
+    stages: usize,
+    sample_rate: f32,
+    delay_ms: f32,
+    clock_hz: f32,
+    input_filter: BiquadFilter,
+    output_filter: BiquadFilter,
+    // the sample held between clock ticks and how far the clock is to the next one, 0 to 1
+    held: f32,
+    clock_phase: f32,
+    // source of the hiss
+    noise: Xorshift,
+}
+
+impl Bbd {
+    /// `seed` picks the noise sequence, so two channels don't hiss the same
+    pub fn new(sample_rate: f32, seed: u32) -> Self {
+        let mut bbd = Self {
+            stages: DEFAULT_STAGES,
+            sample_rate,
+            delay_ms: 0.0,
+            clock_hz: 0.0,
+            input_filter: BiquadFilter::new(),
+            output_filter: BiquadFilter::new(),
+            held: 0.0,
+            clock_phase: 0.0,
+            noise: Xorshift::new(seed),
+        };
+        bbd.update_clock();
+        bbd
+    }
+
+    /// number of buckets the chip has, `MIN_STAGES` to `MAX_STAGES`
+    pub fn set_stages(&mut self, stages: usize) {
+        let stages = stages.clamp(MIN_STAGES, MAX_STAGES);
+        if stages == self.stages {
+            return;
+        }
+        self.stages = stages;
+        self.update_clock();
+    }
+
+    /// the delay time the chip is clocked for and the host sample rate
+    pub fn set_delay(&mut self, sample_rate: f32, delay_ms: f32) {
+        if sample_rate == self.sample_rate && delay_ms == self.delay_ms {
+            return;
+        }
+        self.sample_rate = sample_rate;
+        self.delay_ms = delay_ms;
+        self.update_clock();
+    }
+
+    fn update_clock(&mut self) {
+        let delay_s = (self.delay_ms / 1000.0).max(1e-4);
+        self.clock_hz = self.stages as f32 / (2.0 * delay_s);
+
+        let cutoff = (self.clock_hz * BANDWIDTH_CLOCK_RATIO).min(self.sample_rate * 0.45);
+        self.input_filter.second_order_lpf_coefficients(self.sample_rate, cutoff, FILTER_Q);
+        self.output_filter.second_order_lpf_coefficients(self.sample_rate, cutoff, FILTER_Q);
+    }
+
+    pub fn clear(&mut self) {
+        self.input_filter.reset_filter();
+        self.output_filter.reset_filter();
+        self.held = 0.0;
+        self.clock_phase = 0.0;
+    }
+
+    /// the signal going into the chip
+    pub fn process_input(&mut self, x: f32) -> f32 {
+        let compressed = (x * COMPRESSION_DRIVE).tanh() / COMPRESSION_DRIVE;
+        let filtered = self.input_filter.process_left(compressed);
+
+        // a clock at or above the sample rate samples every frame, slower clocks hold the value
+        // and fold everything above half the clock back down
+        self.clock_phase += self.clock_hz / self.sample_rate;
+        if self.clock_phase >= 1.0 {
+            self.clock_phase = self.clock_phase.fract();
+            self.held = filtered;
+        }
+        self.held
+    }
+
+    /// the signal coming out of the chip
+    pub fn process_output(&mut self, x: f32) -> f32 {
+        let noise = NOISE_LEVEL * (self.stages as f32 / DEFAULT_STAGES as f32).sqrt() * self.noise.next_value();
+        self.output_filter.process_left(x + noise)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,88 @@
+// the classic Lorenz parameters, the attractor is chaotic with these
+const SIGMA: f64 = 10.0;
+const RHO: f64 = 28.0;
+const BETA: f64 = 8.0 / 3.0;
+// where the attractor starts, anywhere off the fixed points works, this one is on the attractor
+// already
+const START: (f64, f64, f64) = (1.0, 1.0, 20.0);
+// rough extents of the attractor, for scaling the outputs to -1..1
+const XY_RANGE: f64 = 25.0;
+const Z_CENTER: f64 = 25.0;
+// step for running ahead to an offset start, fine enough for Euler to stay on the attractor
+const OFFSET_STEP: f64 = 1e-3;
+
+/// Lorenz attractor as a modulation source. It never settles into a repeating cycle, so the
+/// modulation stays organic no matter how long it runs. Integrated with small Euler steps, one
+/// per sample, which is plenty at audio rates.
+#[derive(Clone)]
+pub struct Lorenz {
+    x: f64,
+    y: f64,
+    z: f64,
+    // where `restart` goes back to
+    start: (f64, f64, f64),
+    // attractor time advanced per second
+    speed: f32,
+    sample_rate: f32,
+}
+
+impl Lorenz {
+    pub fn new(sample_rate: f32, speed: f32) -> Self {
+        Self::new_with_offset(sample_rate, speed, 0.0)
+    }
+
+    /// starts `offset` units of attractor time further along the path than `new`. Attractors
+    /// started a unit or more apart move independently of each other
+    pub fn new_with_offset(sample_rate: f32, speed: f32, offset: f64) -> Self {
+        let mut lorenz = Self {
+            x: START.0,
+            y: START.1,
+            z: START.2,
+            start: START,
+            speed,
+            sample_rate,
+        };
+        for _ in 0..(offset / OFFSET_STEP).round() as usize {
+            lorenz.advance(OFFSET_STEP);
+        }
+        lorenz.start = (lorenz.x, lorenz.y, lorenz.z);
+        lorenz
+    }
+
+    /// goes back to the starting point, from where it traces the same path again
+    pub fn restart(&mut self) {
+        (self.x, self.y, self.z) = self.start;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// advances the attractor by one sample
+    pub fn step(&mut self) {
+        self.advance((self.speed / self.sample_rate) as f64);
+    }
+
+    fn advance(&mut self, dt: f64) {
+        let dx = SIGMA * (self.y - self.x);
+        let dy = self.x * (RHO - self.z) - self.y;
+        let dz = self.x * self.y - BETA * self.z;
+
+        self.x += dx * dt;
+        self.y += dy * dt;
+        self.z += dz * dt;
+    }
+
+    /// the three coordinates scaled to roughly -1..1, each one moving differently
+    pub fn outputs(&self) -> [f32; 3] {
+        [
+            (self.x / XY_RANGE).clamp(-1.0, 1.0) as f32,
+            (self.y / XY_RANGE).clamp(-1.0, 1.0) as f32,
+            ((self.z - Z_CENTER) / Z_CENTER).clamp(-1.0, 1.0) as f32,
+        ]
+    }
+}
\ No newline at end of file
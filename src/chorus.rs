@@ -1,58 +1,530 @@
-use std::collections::VecDeque;
-
 use rand::distributions::uniform::SampleRange;
 
-use crate::{delay::Delay, lfo};
+use crate::{bbd::Bbd, chaos::Lorenz, delay::{Delay, Interpolation, Tap}, diffuser::Diffuser, envelope::{EnvelopeFollower, TransientDetector}, filter::{BiquadFilter, LinkwitzRiley}, lfo, oversample::{self, FilterOrder, Oversampler}, random::Xorshift, ring_buffer::RingBuffer, stepseq::StepMode, stereo};
+
+// delay time at which "rate follows delay" leaves the rate unchanged, the default delay
+const RATE_FOLLOW_REFERENCE_MS: f32 = 15.0;
+// averaging time of the correlation measurement
+const CORRELATION_TIME_S: f32 = 0.3;
+// mono protect starts narrowing the voices once their correlation drops below this
+const MONO_PROTECT_THRESHOLD: f32 = 0.0;
+const MONO_PROTECT_TIME_S: f32 = 0.2;
+/// widest the wet width goes, twice the voices' own side signal
+pub const MAX_WIDTH: f32 = 2.0;
+// averaging time of the feedback loop energy meter
+const FEEDBACK_ENERGY_TIME_S: f32 = 0.1;
+// the feedback loops have to stay below unity gain
+const MAX_FEEDBACK: f32 = 0.999;
+// longest base delay the feedback and dry buffers hold, the plugin's delay goes up to 50 ms
+const MAX_BASE_DELAY_MS: f32 = 60.0;
+// the auto gain never boosts by more than this, about 12 dB
+const MAX_AUTO_GAIN: f32 = 4.0;
+// the feedback tone filters are bypassed at these cutoffs
+pub const FEEDBACK_LOWPASS_OFF_HZ: f32 = 20000.0;
+pub const FEEDBACK_HIGHPASS_OFF_HZ: f32 = 20.0;
+// the wet high-pass is bypassed at this cutoff
+pub const WET_HIGHPASS_OFF_HZ: f32 = 20.0;
+const WET_HIGHPASS_Q: f32 = 0.707;
+// the bass preserve crossover is bypassed at this frequency
+pub const BASS_PRESERVE_OFF_HZ: f32 = 20.0;
+// input gain of the feedback saturation at full drive
+const MAX_FEEDBACK_DRIVE_GAIN: f64 = 10.0;
+// the rate modulation LFO is slow and at an odd rate, so the patterns it creates take long to
+// repeat. At full amount it bends the main rate by an octave either way
+const RATE_MOD_LFO_HZ: f32 = 0.073;
+const RATE_MOD_OCTAVES: f32 = 1.0;
+const DEFAULT_CHAOS_SPEED: f32 = 0.5;
+// how far along the attractor's path each voice's chaos starts from the previous one's, in
+// attractor time. About one loop around a wing, so no two voices move together
+const CHAOS_VOICE_OFFSET: f64 = 0.8;
+// phase offset of the right LFOs until `set_stereo_phase` is called, a quarter cycle
+const DEFAULT_STEREO_PHASE: f32 = core::f32::consts::FRAC_PI_2;
+/// most voices per channel `set_voice_count` accepts, all of them are allocated up front
+pub const MAX_VOICES: usize = 8;
+const DEFAULT_VOICE_COUNT: usize = 3;
+const DEFAULT_VOICE_SPREAD: f32 = 1.0;
+// share of the delay spread each voice's base delay is offset by. Irregular, so the voices don't
+// settle into evenly spaced combs, and the first three come out at about +0/+3/+7 ms of a 7 ms
+// spread
+const VOICE_DELAY_OFFSETS: [f32; MAX_VOICES] = [0.0, 0.43, 1.0, 0.21, 0.71, 0.12, 0.86, 0.57];
+// share of the rate spread each voice's LFOs are detuned by, either way. The first voice stays at
+// the set rate, so a synced rate and the persisted and retrigger phases keep following it
+const VOICE_RATE_OFFSETS: [f32; MAX_VOICES] = [0.0, 0.73, -0.61, 0.29, -1.0, 0.92, -0.37, 0.51];
+// length of the output fade-in after a reset
+const FADE_IN_MS: f32 = 5.0;
+/// the dry delay of the through-zero flanger, the top of the flanger's delay range. Fixed, so
+/// the latency doesn't follow the delay knob
+pub const THROUGH_ZERO_DELAY_MS: f32 = 5.0;
+// how long the dry path crossfades when its delay changes, instead of jumping
+const DRY_CROSSFADE_MS: f32 = 10.0;
+// how long voices take to fade in and out when the voice count or taper changes
+const VOICE_FADE_MS: f32 = 20.0;
+// the ensemble's fast LFO bank, a vibrato on top of the slow sweep the rate sets. Weighted so
+// the sum of both banks stays within -1..1
+const ENSEMBLE_FAST_HZ: f32 = 6.0;
+const ENSEMBLE_SLOW_WEIGHT: f32 = 0.7;
+const ENSEMBLE_FAST_WEIGHT: f32 = 0.3;
+// how much of each dimension voice is mixed, inverted, into the opposite channel
+const DIMENSION_CROSS_MIX: f32 = 0.3;
+// the Juno's BBD lines sweep between about 1.7 and 5.3 ms in modes I and II, and only
+// 3.3 to 3.7 ms but much faster in I+II. The right channel runs on the inverted LFO
+const JUNO_DELAY_MS: f32 = 3.5;
+const JUNO_STEREO_PHASE: f32 = core::f32::consts::PI;
+// the tape's flutter runs on the fast LFO bank with full humanize, so it never quite repeats, on
+// top of the wow of the drifting main LFOs
+const TAPE_FLUTTER_HZ: f32 = 9.0;
+const TAPE_WOW_WEIGHT: f32 = 0.85;
+const TAPE_FLUTTER_WEIGHT: f32 = 0.15;
+// input gain of the tape saturation
+const TAPE_DRIVE: f32 = 2.0;
+// length of the granular algorithm's grains, long enough to keep the grain rate below the pitch
+// range of most sources, short enough not to smear transients into an audible echo
+const GRAIN_MS: f32 = 40.0;
+/// widest pitch offset of a granular voice, either way
+pub const MAX_GRAIN_DETUNE_CENTS: f32 = 50.0;
+const DEFAULT_GRAIN_DETUNE_CENTS: f32 = 10.0;
+// level of the hiss at full noise, about -46 dB
+const MAX_NOISE_LEVEL: f32 = 0.005;
+// input envelope at which the hiss is fully open, it fades out below it
+const NOISE_GATE_LEVEL: f32 = 0.01;
+// the tone tilts the voices around this frequency, by up to this much either way at the ends
+const TILT_PIVOT_HZ: f32 = 800.0;
+const MAX_TILT_DB: f32 = 6.0;
+
+/// LFO phase offset of voice `index` out of `count`, at full spread the voices are spread evenly
+/// across the cycle
+fn voice_phase_offset(index: usize, count: usize, spread: f32) -> f32 {
+    spread * index as f32 * 2.0 * core::f32::consts::PI / count as f32
+}
+
+/// relative level of voice `index` out of `count` for a -1 to 1 taper, see `set_voice_taper`.
+/// The voices are ranked by their share of the delay spread, so the one with the shortest delay
+/// is at one end and the longest at the other
+fn voice_level(index: usize, count: usize, taper: f32) -> f32 {
+    let longest = VOICE_DELAY_OFFSETS[..count].iter().fold(0.0f32, |longest, offset| longest.max(*offset));
+    if longest == 0.0 {
+        return 1.0;
+    }
+    let t = VOICE_DELAY_OFFSETS[index] / longest;
+    (1.0 + taper * (2.0 * t - 1.0)).max(0.0)
+}
+
+/// pan position of voice `index` out of `count` at full pan width, spread evenly from hard left
+/// (-1) to hard right (1)
+fn voice_pan_position(index: usize, count: usize) -> f32 {
+    if count < 2 {
+        0.0
+    } else {
+        2.0 * index as f32 / (count - 1) as f32 - 1.0
+    }
+}
+
+/// share of the grain detune voice `index` out of `count` is shifted by, spread evenly from -1 to
+/// 1. A single voice is shifted up, the right channel mirrors it down
+fn grain_detune_offset(index: usize, count: usize) -> f32 {
+    if count < 2 {
+        1.0
+    } else {
+        voice_pan_position(index, count)
+    }
+}
+
+/// reads two grains of `tap` from `delay_line` half a window apart. Each drifts through `window` samples
+/// above `delay`, which shifts its pitch by `ratio`, and fades in and out on a sine squared
+/// window, so the two always add up to unity gain and the jump back at the end of a grain is
+/// silent. Advances `phase`, 0 to 1. Returns the output and the first grain's read position
+fn process_grains(delay_line: &Delay, tap: &mut Tap, phase: &mut f32, delay: f32, window: f32, ratio: f32) -> (f64, f32) {
+    let phases = [*phase, (*phase + 0.5).fract()];
+    let delays = phases.map(|phase| delay.max(1.0) + phase * window);
+    let gains = phases.map(|phase| (core::f32::consts::PI * phase).sin().powi(2));
+    // a higher pitch reads faster than the input is written, so the delay shrinks
+    *phase = (*phase + (1.0 - ratio) / window).rem_euclid(1.0);
+    (delay_line.read_taps(tap, delays, gains), delays[0])
+}
+
+/// the soft saturation of the tape algorithm, close to unity gain for quiet signals
+fn tape_saturate(x: f32) -> f32 {
+    (x * TAPE_DRIVE).tanh() / TAPE_DRIVE
+}
+
+/// replaces NaN/infinite values with `fallback` and clamps the rest to `min..=max`
+fn sanitize(value: f32, min: f32, max: f32, fallback: f32) -> f32 {
+    if value.is_finite() {
+        value.clamp(min, max)
+    } else {
+        fallback
+    }
+}
+// envelope follower times for the dynamic depth
+const DYNAMIC_DEPTH_ATTACK_MS: f32 = 10.0;
+const DYNAMIC_DEPTH_RELEASE_MS: f32 = 200.0;
+// envelope follower times for the sidechain, quick enough to follow a kick drum
+const SIDECHAIN_ATTACK_MS: f32 = 5.0;
+const SIDECHAIN_RELEASE_MS: f32 = 150.0;
+
+/// length of the buffers read at the base delay, see `MAX_BASE_DELAY_MS`
+fn base_buffer_len(sample_rate: f32) -> usize {
+    (MAX_BASE_DELAY_MS / 1000.0 * sample_rate).ceil() as usize + 1
+}
+
+/// length of the dry buffers, the longest delay of the dry path, see `Chorus::dry_delay`
+pub fn dry_buffer_len(sample_rate: f32) -> usize {
+    through_zero_delay(sample_rate) + oversample::MAX_LATENCY + 1
+}
+
+/// `THROUGH_ZERO_DELAY_MS` in whole samples, so the dry path and the voice line up exactly
+fn through_zero_delay(sample_rate: f32) -> usize {
+    (THROUGH_ZERO_DELAY_MS / 1000.0 * sample_rate).round() as usize
+}
+
+/// gain an envelope applies to something with a -1 to 1 modulation amount. Positive amounts scale
+/// it with the envelope (silence closes it, full scale leaves it fully open), negative amounts the
+/// other way around (full scale closes it, silence leaves it fully open)
+fn envelope_gain(amount: f32, envelope: f32) -> f32 {
+    let envelope = envelope.min(1.0);
+    if amount >= 0.0 {
+        1.0 - amount + amount * envelope
+    } else {
+        1.0 + amount * envelope
+    }
+}
+// transient duck detector: the duck hits within about a millisecond and the wet swells back in
+// as the slow envelope catches up
+const TRANSIENT_FAST_ATTACK_MS: f32 = 0.5;
+const TRANSIENT_SLOW_ATTACK_MS: f32 = 40.0;
+const TRANSIENT_RELEASE_MS: f32 = 150.0;
+
+/// the processing algorithm, see `set_algorithm`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    // the regular multi-voice chorus
+    Chorus,
+    // a single voice, fully wet and without feedback, for pure pitch vibrato
+    Vibrato,
+    // a single voice sweeping above a short delay
+    Flanger,
+    // string machine ensemble, every voice is swept by a slow and a fast LFO bank summed
+    Ensemble,
+    // two voices swept in opposite phase, one per side and cross-mixed inverted into the other
+    Dimension,
+    // a Juno BBD chorus setting, a single triangle swept voice with the delay, depth, rate and
+    // stereo phase fixed
+    Juno(JunoMode),
+    // a single voice wandering with tape wow and flutter, recorded with a gentle saturation
+    Tape,
+    // voices pitch shifted by a constant detune with overlapping grains instead of swept by the
+    // LFOs, a micro shift without the cyclic wobble
+    Granular,
+}
+
+/// how the wet and dry levels combine in the output, see `set_mix_law`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MixLaw {
+    // the levels are used as they are, scaled down together when they add up to more than 1
+    Linear,
+    // the balance between them crossfades on a quarter sine, the louder one sets the level
+    EqualPower,
+}
+
+/// where the wet filters sit, see `set_filter_position`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterPosition {
+    // on the input of the voices, shaping what gets modulated, saturated and fed back
+    Pre,
+    // on the voices' output, shaping the result
+    Post,
+}
+
+/// the three chorus buttons of the Juno
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JunoMode {
+    I,
+    II,
+    // both buttons pressed together
+    Both,
+}
+
+impl JunoMode {
+    /// (delay in ms, depth in ms, rate in Hz) of this setting
+    fn settings(self) -> (f32, f32, f32) {
+        match self {
+            JunoMode::I => (JUNO_DELAY_MS, 1.85, 0.513),
+            JunoMode::II => (JUNO_DELAY_MS, 1.85, 0.863),
+            JunoMode::Both => (JUNO_DELAY_MS, 0.2, 9.75),
+        }
+    }
+}
+
+/// one chorus voice, a tap of each channel's delay line and the LFOs sweeping them
+#[derive(Clone)]
+struct Voice {
+    left_tap: Tap,
+    right_tap: Tap,
+    left_lfo: lfo::LFO,
+    right_lfo: lfo::LFO,
+    // the fast bank of the ensemble and the flutter of the tape, only advanced while they run
+    left_fast_lfo: lfo::LFO,
+    right_fast_lfo: lfo::LFO,
+    // LFO values of the current frame, see `advance_lfos`
+    left_lfo_value: f32,
+    right_lfo_value: f32,
+    // the voice's own chaotic modulation, the left channel follows its first output and the
+    // right one its second, so the channels move differently
+    chaos: Lorenz,
+    left_chaos_value: f32,
+    right_chaos_value: f32,
+    // delay line outputs of the current frame, for panning the voice in `pan_voices`
+    left_output: f32,
+    right_output: f32,
+    // analog voicing of the delay lines, see `set_bbd`
+    left_bbd: Bbd,
+    right_bbd: Bbd,
+    // position of the granular algorithm's grains in their window, 0 to 1
+    left_grain_phase: f32,
+    right_grain_phase: f32,
+}
+
+impl Voice {
+    fn new(index: usize, sample_rate: f32, rate: f32, phase: f32) -> Self {
+        let mut voice = Self {
+            left_tap: Tap::new(),
+            right_tap: Tap::new(),
+            left_lfo: lfo::LFO::new_with_phase(sample_rate, rate, phase),
+            right_lfo: lfo::LFO::new_with_phase(sample_rate, rate, phase + DEFAULT_STEREO_PHASE),
+            left_fast_lfo: lfo::LFO::new_with_phase(sample_rate, ENSEMBLE_FAST_HZ, phase),
+            right_fast_lfo: lfo::LFO::new_with_phase(sample_rate, ENSEMBLE_FAST_HZ, phase + DEFAULT_STEREO_PHASE),
+            left_lfo_value: 0.0,
+            right_lfo_value: 0.0,
+            chaos: Lorenz::new_with_offset(sample_rate, DEFAULT_CHAOS_SPEED, index as f64 * CHAOS_VOICE_OFFSET),
+            left_chaos_value: 0.0,
+            right_chaos_value: 0.0,
+            left_output: 0.0,
+            right_output: 0.0,
+            left_bbd: Bbd::new(sample_rate, 2 * index as u32 + 1),
+            right_bbd: Bbd::new(sample_rate, 2 * index as u32 + 2),
+            left_grain_phase: 0.0,
+            right_grain_phase: 0.0,
+        };
+        voice.reseed_lfos(index);
+        voice
+    }
+
+    /// gives each LFO of voice `index` a seed of its own, the same one every time
+    fn reseed_lfos(&mut self, index: usize) {
+        let lfos = [&mut self.left_lfo, &mut self.right_lfo, &mut self.left_fast_lfo, &mut self.right_fast_lfo];
+        for (i, lfo) in lfos.into_iter().enumerate() {
+            // spread over the whole range by the golden ratio, so neighbouring seeds differ in
+            // all their bits
+            lfo.reseed(((4 * index + i + 1) as u32).wrapping_mul(0x9e37_79b9));
+        }
+    }
 
+    /// puts the grains back to the start of their window, see `Chorus::restart_modulation`
+    fn restart_grains(&mut self) {
+        self.left_grain_phase = 0.0;
+        self.right_grain_phase = 0.0;
+    }
+
+    fn lfos_mut(&mut self) -> [&mut lfo::LFO; 2] {
+        [&mut self.left_lfo, &mut self.right_lfo]
+    }
+
+    fn fast_lfos_mut(&mut self) -> [&mut lfo::LFO; 2] {
+        [&mut self.left_fast_lfo, &mut self.right_fast_lfo]
+    }
+
+    fn taps_mut(&mut self) -> [&mut Tap; 2] {
+        [&mut self.left_tap, &mut self.right_tap]
+    }
+
+    fn bbds_mut(&mut self) -> [&mut Bbd; 2] {
+        [&mut self.left_bbd, &mut self.right_bbd]
+    }
+}
+
+#[derive(Clone)]
 pub struct Chorus {
-    left_delays: Vec<Delay>,
-    right_delays: Vec<Delay>,
-    left_lfos: Vec<lfo::LFO>,
-    right_lfos: Vec<lfo::LFO>,
-    left_feedback_buffer: Box<VecDeque<f32>>,
-    right_feedback_buffer: Box<VecDeque<f32>>,
+    // always `MAX_VOICES` long, only the first `voice_count` are processed
+    voices: Vec<Voice>,
+    voice_count: usize,
+    // the voice count asked for with `set_voice_count`, the algorithm may use fewer
+    requested_voice_count: usize,
+    // voices mixed into the output, `voice_count` plus any dropped ones that are still fading out
+    mixed_voice_count: usize,
+    algorithm: Algorithm,
+    // the waveform asked for with `set_waveform`, the Juno modes always use a triangle
+    waveform: lfo::Waveform,
+    // the input of each channel, all voices read their taps from it
+    left_delay: Delay,
+    right_delay: Delay,
+    // the feedback state is kept in f64, see `set_high_precision`
+    left_feedback_buffer: RingBuffer<f64>,
+    right_feedback_buffer: RingBuffer<f64>,
+    // the (low, high) split input of the last `THROUGH_ZERO_DELAY_MS` and the oversampling
+    // latency, the dry path reads from it, see `dry_delay`
+    left_dry_buffer: RingBuffer<(f32, f32)>,
+    right_dry_buffer: RingBuffer<(f32, f32)>,
+    // the dry delay the dry path reads at, and the one it is crossfading from after a change
+    dry_read_delay: usize,
+    dry_fade_from: usize,
+    dry_fade_remaining: usize,
+    dry_fade_length: usize,
+    through_zero: bool,
     delay_ms: f32,
     delay_samples: usize,
+    // the delay in samples before rounding, the base of the voices' read positions
+    calc_delay: f32,
     feedback: f32,
     depth: f32,
     sample_rate: f32,
     calc_depth: f32,
     wet: f32,
     dry: f32,
+    exceeds_buffers: bool,
+    feedback_makeup: bool,
+    auto_gain: bool,
+    mix_law: MixLaw,
+    // the voices of the last frame as they went into the mix, see `wet_output`
+    wet_output: (f32, f32),
+    calc_motion_3d: f32,
+    // largest base delay offset of a voice, in samples
+    calc_delay_spread: f32,
+    // 0 to 1, see `set_pan_width`
+    pan_width: f32,
+    // 0 to `MAX_WIDTH`, see `set_width`
+    width: f32,
+    // -1 to 1, see `set_voice_taper`, and the voice levels it results in, 0 past the voice count
+    voice_taper: f32,
+    voice_levels: [f32; MAX_VOICES],
+    // the levels the voices are mixed at, fading to `voice_levels` after a change, from
+    // `voice_fade_from`
+    voice_gains: [f32; MAX_VOICES],
+    voice_fade_from: [f32; MAX_VOICES],
+    voice_fade_remaining: usize,
+    voice_fade_length: usize,
+    // sum of the voice gains, the voice sums are divided by it
+    voice_gain_sum: f32,
+    // gains `voices_left` and `voices_right` applied to their channel's voices this frame
+    left_voices_gain: f32,
+    right_voices_gain: f32,
+    // phase offset of the right LFOs against the left ones, in radians
+    stereo_phase: f32,
+    // 0 to 1, how far the voices' LFO phases are spread across the cycle
+    voice_spread: f32,
+    // secondary LFO modulating the rate of all the others
+    rate_mod_lfo: lfo::LFO,
+    rate_mod_amount: f32,
+    // largest detune of a voice's LFO rate, as a fraction of the rate
+    rate_spread: f32,
+    // crossfade of the LFOs with the voices' chaotic modulation
+    chaos_amount: f32,
+    unipolar: bool,
+    // the step sequencer's current value, used in place of or on top of the LFOs
+    step_mode: Option<StepMode>,
+    step_value: f32,
+    rate_follows_delay: bool,
+    per_voice_feedback: bool,
+    // 0 to 1, how much of each channel's feedback comes from the other channel
+    cross_feedback: f32,
+    invert_feedback: bool,
+    // 0 to 1, see `set_feedback_drive`
+    feedback_drive: f32,
+    // all-pass chains smearing the voices, see `set_diffusion`
+    left_diffuser: Diffuser,
+    right_diffuser: Diffuser,
+    bbd: bool,
+    // pitch offset of the outermost granular voices in cents, see `set_grain_detune`
+    grain_detune: f32,
+    // one-pole tone filters in the channel feedback loops, see `set_feedback_tone`. The cutoffs
+    // and sample rate the coefficients were last computed for
+    feedback_lowpass: BiquadFilter,
+    feedback_highpass: BiquadFilter,
+    feedback_lowpass_hz: f32,
+    feedback_highpass_hz: f32,
+    feedback_tone_sample_rate: f32,
+    // one-pole low-pass on the part of the feedback crossing over to the other channel, see
+    // `set_cross_feedback_lowpass`, with its cutoff and sample rate like the tone filters
+    cross_feedback_lowpass: BiquadFilter,
+    cross_feedback_lowpass_hz: f32,
+    cross_feedback_lowpass_sample_rate: f32,
+    // 0 to 1, see `set_noise`
+    noise: f32,
+    // source of the hiss
+    noise_source: Xorshift,
+    // opposite shelves around `TILT_PIVOT_HZ`, see `set_tone`. The tone and sample rate the
+    // coefficients were last computed for
+    tilt_low: BiquadFilter,
+    tilt_high: BiquadFilter,
+    tone: f32,
+    tone_sample_rate: f32,
+    // keeps the lows out of the voices, see `set_wet_highpass`
+    wet_highpass: BiquadFilter,
+    wet_highpass_hz: f32,
+    wet_highpass_sample_rate: f32,
+    filter_position: FilterPosition,
+    // splits off the lows that bypass the chorus, see `set_bass_preserve`
+    crossover: LinkwitzRiley,
+    crossover_hz: f32,
+    crossover_sample_rate: f32,
+    high_precision: bool,
+    // runs the tape saturation at twice the sample rate, see `set_oversampling`
+    oversampling: Option<FilterOrder>,
+    left_oversampler: Oversampler,
+    right_oversampler: Oversampler,
+    mono_protect: bool,
+    // see `set_invert_right_wet`
+    invert_right_wet: bool,
+    correlation_lr: f32,
+    correlation_ll: f32,
+    correlation_rr: f32,
+    correlation: f32,
+    protect_width: f32,
+    left_envelope: EnvelopeFollower,
+    right_envelope: EnvelopeFollower,
+    dynamic_depth: f32,
+    sidechain_connected: bool,
+    sidechain_envelope: EnvelopeFollower,
+    sidechain_depth: f32,
+    sidechain_wet: f32,
+    left_transient: TransientDetector,
+    right_transient: TransientDetector,
+    transient_duck: f32,
+    feedback_mean_square: f32,
+    // samples left of the fade-in started by `reset`
+    fade_in_remaining: usize,
+    fade_in_length: usize,
+    // fractional read delays of the voices as of the last processed sample, the left voices
+    // followed by the right ones, `2 * voice_count` of them are in use
+    voice_read_positions: [f32; 2 * MAX_VOICES],
 }
 
 impl Chorus {
     pub fn new(sample_rate: f32, delay_ms: f32, feedback: f32, depth: f32, rate: f32, wet: f32, dry: f32) -> Self {
-        let mut left_delays: Vec<Delay> = Vec::with_capacity(5);
-        let mut right_delays: Vec<Delay> = Vec::with_capacity(5);
-        let mut left_lfos: Vec<lfo::LFO> = Vec::with_capacity(5);
-        let mut right_lfos: Vec<lfo::LFO> = Vec::with_capacity(5);
-
         let delay_samples: usize = ((delay_ms as f32 / 1000.0) * sample_rate).round() as usize;
 
-        for i in 0..5 {
-            left_delays.push(Delay::new(sample_rate as usize, delay_samples, 0.0));
-            right_delays.push(Delay::new(sample_rate as usize, delay_samples, 0.0));
-            left_lfos.push(lfo::LFO::new_random_phase(sample_rate, rate));
-            right_lfos.push(lfo::LFO::new_random_phase(sample_rate, rate));
-        }
-
-        let mut left_feedback_buffer: Box<VecDeque<f32>> 
-            = Box::new(VecDeque::with_capacity(sample_rate as usize));
-        let mut right_feedback_buffer: Box<VecDeque<f32>> 
-            = Box::new(VecDeque::with_capacity(sample_rate as usize));
-        for _ in 0..(sample_rate as usize) {
-            left_feedback_buffer.push_front(0.0);
-            right_feedback_buffer.push_front(0.0);
-        }
-
-        Self {
-            left_delays,
-            right_delays,
-            left_lfos,
-            right_lfos,
-            left_feedback_buffer,
-            right_feedback_buffer,
+        // the voices are spread by the voice phase spread, the channels by the stereo phase
+        let voices = (0..MAX_VOICES)
+            .map(|i| Voice::new(i, sample_rate, rate, voice_phase_offset(i, DEFAULT_VOICE_COUNT, DEFAULT_VOICE_SPREAD)))
+            .collect();
+
+        let mut chorus = Self {
+            voices,
+            voice_count: DEFAULT_VOICE_COUNT,
+            requested_voice_count: DEFAULT_VOICE_COUNT,
+            mixed_voice_count: DEFAULT_VOICE_COUNT,
+            algorithm: Algorithm::Chorus,
+            waveform: lfo::Waveform::Sine,
+            left_delay: Delay::new(sample_rate as usize, delay_samples, 0.0),
+            right_delay: Delay::new(sample_rate as usize, delay_samples, 0.0),
+            left_feedback_buffer: RingBuffer::new(base_buffer_len(sample_rate)),
+            right_feedback_buffer: RingBuffer::new(base_buffer_len(sample_rate)),
+            left_dry_buffer: RingBuffer::new(dry_buffer_len(sample_rate)),
+            right_dry_buffer: RingBuffer::new(dry_buffer_len(sample_rate)),
+            dry_read_delay: 0,
+            dry_fade_from: 0,
+            dry_fade_remaining: 0,
+            dry_fade_length: 0,
+            through_zero: false,
             sample_rate,
             feedback: feedback,
             depth: depth,
@@ -61,26 +533,137 @@ impl Chorus {
             dry: dry,
             delay_ms,
             delay_samples: delay_samples,
-        }
+            calc_delay: delay_samples as f32,
+            exceeds_buffers: false,
+            feedback_makeup: false,
+            auto_gain: false,
+            mix_law: MixLaw::Linear,
+            filter_position: FilterPosition::Post,
+            wet_output: (0.0, 0.0),
+            calc_motion_3d: 0.0,
+            calc_delay_spread: 0.0,
+            pan_width: 0.0,
+            width: 1.0,
+            voice_taper: 0.0,
+            voice_levels: [0.0; MAX_VOICES],
+            voice_gains: [0.0; MAX_VOICES],
+            voice_fade_from: [0.0; MAX_VOICES],
+            voice_fade_remaining: 0,
+            voice_fade_length: 0,
+            voice_gain_sum: 0.0,
+            left_voices_gain: 1.0,
+            right_voices_gain: 1.0,
+            stereo_phase: DEFAULT_STEREO_PHASE,
+            voice_spread: DEFAULT_VOICE_SPREAD,
+            rate_mod_lfo: lfo::LFO::new(sample_rate, RATE_MOD_LFO_HZ),
+            rate_mod_amount: 0.0,
+            rate_spread: 0.0,
+            chaos_amount: 0.0,
+            unipolar: false,
+            step_mode: None,
+            step_value: 0.0,
+            rate_follows_delay: false,
+            per_voice_feedback: false,
+            cross_feedback: 0.0,
+            invert_feedback: false,
+            feedback_drive: 0.0,
+            left_diffuser: Diffuser::new(sample_rate, 0.0),
+            right_diffuser: Diffuser::new(sample_rate, core::f32::consts::FRAC_PI_2),
+            bbd: false,
+            grain_detune: DEFAULT_GRAIN_DETUNE_CENTS,
+            feedback_lowpass: BiquadFilter::new(),
+            feedback_highpass: BiquadFilter::new(),
+            feedback_lowpass_hz: FEEDBACK_LOWPASS_OFF_HZ,
+            feedback_highpass_hz: FEEDBACK_HIGHPASS_OFF_HZ,
+            feedback_tone_sample_rate: 0.0,
+            cross_feedback_lowpass: BiquadFilter::new(),
+            cross_feedback_lowpass_hz: FEEDBACK_LOWPASS_OFF_HZ,
+            cross_feedback_lowpass_sample_rate: 0.0,
+            noise: 0.0,
+            noise_source: Xorshift::new(0x2545_f491),
+            tilt_low: BiquadFilter::new(),
+            tilt_high: BiquadFilter::new(),
+            tone: 0.0,
+            tone_sample_rate: 0.0,
+            wet_highpass: BiquadFilter::new(),
+            wet_highpass_hz: WET_HIGHPASS_OFF_HZ,
+            wet_highpass_sample_rate: 0.0,
+            crossover: LinkwitzRiley::new(),
+            crossover_hz: BASS_PRESERVE_OFF_HZ,
+            crossover_sample_rate: 0.0,
+            high_precision: false,
+            oversampling: None,
+            left_oversampler: Oversampler::new(FilterOrder::Short),
+            right_oversampler: Oversampler::new(FilterOrder::Short),
+            mono_protect: false,
+            invert_right_wet: false,
+            correlation_lr: 0.0,
+            correlation_ll: 0.0,
+            correlation_rr: 0.0,
+            correlation: 1.0,
+            protect_width: 1.0,
+            left_envelope: EnvelopeFollower::new(sample_rate, DYNAMIC_DEPTH_ATTACK_MS, DYNAMIC_DEPTH_RELEASE_MS),
+            right_envelope: EnvelopeFollower::new(sample_rate, DYNAMIC_DEPTH_ATTACK_MS, DYNAMIC_DEPTH_RELEASE_MS),
+            dynamic_depth: 0.0,
+            sidechain_connected: false,
+            sidechain_envelope: EnvelopeFollower::new(sample_rate, SIDECHAIN_ATTACK_MS, SIDECHAIN_RELEASE_MS),
+            sidechain_depth: 0.0,
+            sidechain_wet: 0.0,
+            left_transient: TransientDetector::new(sample_rate, TRANSIENT_FAST_ATTACK_MS, TRANSIENT_SLOW_ATTACK_MS, TRANSIENT_RELEASE_MS),
+            right_transient: TransientDetector::new(sample_rate, TRANSIENT_FAST_ATTACK_MS, TRANSIENT_SLOW_ATTACK_MS, TRANSIENT_RELEASE_MS),
+            transient_duck: 0.0,
+            feedback_mean_square: 0.0,
+            fade_in_remaining: 0,
+            fade_in_length: 0,
+            voice_read_positions: [0.0; 2 * MAX_VOICES],
+        };
+        chorus.update_voice_levels();
+        chorus.finish_voice_fade();
+        chorus
     }
 
     pub fn set_params(&mut self, sample_rate: f32, delay: f32, feedback: f32, depth: f32, rate: f32, wet: f32, dry: f32) {
+        // the plugin's parameter ranges keep these in bounds, but the engine can be used on its own
+        // as well, so anything out of range or non-finite is sanitized before it reaches the DSP
+        let sample_rate = if sample_rate.is_finite() && sample_rate > 0.0 { sample_rate } else { self.sample_rate };
+        let delay = sanitize(delay, 0.0, f32::MAX, self.delay_ms);
+        let feedback = sanitize(feedback, 0.0, MAX_FEEDBACK, 0.0);
+        let depth = sanitize(depth, 0.0, f32::MAX, 0.0);
+        let rate = sanitize(rate, 0.0, f32::MAX, 0.0);
+        let wet = sanitize(wet, 0.0, 1.0, 0.0);
+        let dry = sanitize(dry, 0.0, 1.0, 1.0);
+
+        let juno_mode = match self.algorithm {
+            Algorithm::Juno(mode) => Some(mode),
+            _ => None,
+        };
+        let (delay, depth, rate) = juno_mode.map_or((delay, depth, rate), JunoMode::settings);
+
         // resize all buffers relying on sample rate
         self.sample_rate = sample_rate;
+        self.update_dry_delay();
     
-        for (lfol, lfor) in self.left_lfos.iter_mut().zip(self.right_lfos.iter_mut()) {
-            lfol.sample_rate = sample_rate;
-            lfor.sample_rate = sample_rate;
+        for voice in self.voices.iter_mut() {
+            for lfo in voice.lfos_mut() {
+                lfo.sample_rate = sample_rate;
+            }
+            for lfo in voice.fast_lfos_mut() {
+                lfo.sample_rate = sample_rate;
+            }
+            voice.chaos.set_sample_rate(sample_rate);
         }
+        self.rate_mod_lfo.sample_rate = sample_rate;
 
-        let delay_samples: usize = ((delay as f32 / 1000.0) * self.sample_rate).round() as usize;
+        let calc_delay = delay / 1000.0 * self.sample_rate;
+        let delay_samples: usize = calc_delay.round() as usize;
 
-        for d in self.left_delays.iter_mut() {
-            d.delay = delay_samples;
-        }
-        for d in self.right_delays.iter_mut() {
-            d.delay = delay_samples;
-        }
+        // the feedback buffer is the shortest one, so it limits the longest usable delay
+        let max_delay_samples = self.left_feedback_buffer.len() - 1;
+        self.exceeds_buffers = delay_samples > max_delay_samples;
+        let delay_samples = delay_samples.min(max_delay_samples);
+        let calc_delay = calc_delay.min(max_delay_samples as f32);
+        self.calc_delay = calc_delay;
+        self.update_base_delay();
 
         self.feedback = feedback;
 
@@ -90,89 +673,2220 @@ impl Chorus {
         //     self.calc_depth = self.delay_samples as f32;
         // }
 
-        for (lfol, lfor) in self.left_lfos.iter_mut().zip(self.right_lfos.iter_mut()) {
-            lfol.rate = rate;
-            lfor.rate = rate;
+        // rate' = rate * sqrt(15ms / delay), limited to 1/4..4 times the set rate. The square root
+        // keeps the coupling partial, so halving the delay speeds the LFO up by ~41% and not 2x
+        let rate = if self.rate_follows_delay && juno_mode.is_none() {
+            rate * (RATE_FOLLOW_REFERENCE_MS / delay.max(0.1)).sqrt().clamp(0.25, 4.0)
+        } else {
+            rate
+        };
+
+        for voice in self.voices.iter_mut() {
+            for lfo in voice.lfos_mut() {
+                lfo.rate = rate;
+            }
         }
 
         self.wet = wet;
         self.dry = dry;
         self.delay_ms = delay;
         self.delay_samples = delay_samples;
+
+        // with per-voice feedback every delay line regenerates its own output through its
+        // internal loop and the shared channel loop is skipped. Each loop is below unity and the
+        // voices don't feed each other, so the sum stays bounded
+        let voice_feedback = if self.per_voice_feedback { self.loop_gain() } else { 0.0 };
+        for d in self.delays_mut() {
+            d.feedback = voice_feedback;
+        }
     }
 
-    pub fn resize_buffers(&mut self, sample_rate: f32) {
-        for (dl, dr) in self.left_delays.iter_mut().zip(self.right_delays.iter_mut()) {
-            dl.resize_buffers(sample_rate as usize);
-            dr.resize_buffers(sample_rate as usize);
+    /// true if the current settings make the voices read further back than the buffers hold, so
+    /// the reads get clamped. Either the base delay of the last `set_params` call was too long
+    /// for the feedback buffers, or the delay with the spread, depth, 3D motion or grain window
+    /// on top is too long for the delay lines
+    pub fn exceeds_buffers(&self) -> bool {
+        self.exceeds_buffers || self.longest_read() > self.left_delay.longest_delay()
+    }
+
+    /// longest delay in samples any active voice can read at with the current settings
+    fn longest_read(&self) -> f32 {
+        (0..self.voice_count)
+            .map(|i| {
+                let delay = self.voice_delay(i);
+                if self.algorithm == Algorithm::Granular {
+                    return delay.max(1.0) + GRAIN_MS / 1000.0 * self.sample_rate;
+                }
+                let modulation = self.sweep(1.0, self.calc_depth) + self.motion_3d(1.0);
+                self.read_position(delay, modulation)
+            })
+            .fold(0.0, f32::max)
+    }
+
+    /// how much the input level controls the depth, -1 to 1. 0 keeps the depth static, 1 makes
+    /// it fully follow the input envelope (silence closes it, full scale opens it completely) and
+    /// -1 the other way around (full scale closes it, silence leaves the full depth)
+    pub fn set_dynamic_depth(&mut self, amount: f32) {
+        self.dynamic_depth = sanitize(amount, -1.0, 1.0, 0.0);
+    }
+
+    fn dynamic_depth_gain(&self, envelope: f32) -> f32 {
+        envelope_gain(self.dynamic_depth, envelope)
+    }
+
+    /// whether a sidechain signal is being fed to `process_sidechain`, without one the sidechain
+    /// amounts have no effect
+    pub fn set_sidechain_connected(&mut self, connected: bool) {
+        self.sidechain_connected = connected;
+    }
+
+    /// feeds one sample of the sidechain signal to its envelope follower
+    pub fn process_sidechain(&mut self, x: f32) {
+        self.sidechain_envelope.process(x);
+    }
+
+    /// how much the sidechain level controls the depth, -1 to 1 like `set_dynamic_depth`
+    pub fn set_sidechain_depth(&mut self, amount: f32) {
+        self.sidechain_depth = sanitize(amount, -1.0, 1.0, 0.0);
+    }
+
+    /// how much the sidechain level controls the level of the voices, -1 to 1. Negative amounts
+    /// duck the chorus while the sidechain is loud
+    pub fn set_sidechain_wet(&mut self, amount: f32) {
+        self.sidechain_wet = sanitize(amount, -1.0, 1.0, 0.0);
+    }
+
+    fn sidechain_gain(&self, amount: f32) -> f32 {
+        if self.sidechain_connected {
+            envelope_gain(amount, self.sidechain_envelope.value())
+        } else {
+            1.0
         }
+    }
+
+    /// how far the voices duck on transients, 0 leaves them alone and 1 mutes them at the peak
+    /// of an attack so the dry transient comes through clean
+    pub fn set_transient_duck(&mut self, amount: f32) {
+        self.transient_duck = sanitize(amount, 0.0, 1.0, 0.0);
+    }
+
+    fn transient_duck_gain(&self, transient: f32) -> f32 {
+        1.0 - self.transient_duck * transient
+    }
 
-        self.left_feedback_buffer = Box::new(VecDeque::with_capacity(sample_rate as usize));
-        self.right_feedback_buffer = Box::new(VecDeque::with_capacity(sample_rate as usize));
-        for _ in 0..(sample_rate as usize) {
-            self.left_feedback_buffer.push_front(0.0);
-            self.right_feedback_buffer.push_front(0.0);
+    /// phase offset of the right channel LFOs against the left ones, 0 to 180 degrees. 0 moves
+    /// both channels together, 180 moves them in opposite directions for the widest image
+    pub fn set_stereo_phase(&mut self, degrees: f32) {
+        let stereo_phase = sanitize(degrees, 0.0, 180.0, 90.0).to_radians();
+        if stereo_phase == self.stereo_phase {
+            return;
         }
+        self.stereo_phase = stereo_phase;
+        self.align_lfos();
     }
 
+    /// number of voices per channel, 1 to `MAX_VOICES`. Voices coming in read the input already in
+    /// the shared delay lines but start from an empty feedback loop, the output level stays the
+    /// same as the voices are averaged. Voices coming in or dropping out fade over
+    /// `VOICE_FADE_MS`
+    pub fn set_voice_count(&mut self, count: usize) {
+        self.requested_voice_count = count.clamp(1, MAX_VOICES);
+        self.apply_voice_count();
+    }
 
+    /// switches the processing algorithm. The vibrato ignores the voice count, wet, dry and
+    /// feedback settings, it always runs a single fully wet voice without feedback. The flanger
+    /// runs a single voice too and sweeps unipolar, unless flanging through zero. The ensemble sums
+    /// a fast LFO bank at `ENSEMBLE_FAST_HZ` into the slow sweep of every voice, the rate sets the
+    /// slow bank. The dimension runs two voices with inverted LFOs, ignoring the voice spread,
+    /// stereo phase and pan width. The first voice goes left and the second right, each mixed
+    /// inverted into the other side, which widens the sound while the opposite pitch wobbles mask
+    /// each other. The Juno modes run a single triangle swept voice and replace the delay, depth
+    /// and rate given to `set_params` and the stereo phase with the settings of the chosen button.
+    /// The tape runs a single voice, the main LFOs drift for the wow and the fast bank adds a
+    /// flutter at `TAPE_FLUTTER_HZ`, and the voice's input is softly saturated. The granular
+    /// algorithm ignores the LFOs and depth, its voices are pitch shifted by a constant detune with
+    /// overlapping grains, see `set_grain_detune`
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+        if algorithm == self.algorithm {
+            return;
+        }
+        self.algorithm = algorithm;
+        self.update_base_delay();
+        self.update_dry_delay();
+        self.apply_voice_count();
+        self.align_lfos();
+        self.apply_waveform();
+        self.apply_fast_lfos();
+    }
 
-    pub fn process_left(&mut self, x: f32) -> f32 {
-        let xx = x + self.wet * self.feedback * self.left_feedback_buffer.get(self.delay_samples).unwrap();
+    /// rate and humanize of the fast LFO bank for the current algorithm
+    fn apply_fast_lfos(&mut self) {
+        let (rate, humanize) = match self.algorithm {
+            Algorithm::Tape => (TAPE_FLUTTER_HZ, 1.0),
+            _ => (ENSEMBLE_FAST_HZ, 0.0),
+        };
+        for voice in self.voices.iter_mut() {
+            for lfo in voice.fast_lfos_mut() {
+                lfo.rate = rate;
+                lfo.set_humanize(humanize);
+            }
+        }
+    }
+
+    /// the voice count the current algorithm runs with
+    fn apply_voice_count(&mut self) {
+        let count = match self.algorithm {
+            Algorithm::Vibrato | Algorithm::Flanger | Algorithm::Juno(_) | Algorithm::Tape => 1,
+            Algorithm::Dimension => 2,
+            _ => self.requested_voice_count,
+        };
+        if count == self.voice_count {
+            return;
+        }
+        // voices still fading out come back from where they are, only silent ones start over.
+        // The voices already playing keep their phases too, a jump would click, the even spread
+        // for the new count comes back with the next retrigger
+        for voice in self.voices[self.mixed_voice_count.min(count)..count].iter_mut() {
+            for tap in voice.taps_mut() {
+                tap.clear();
+            }
+        }
+        self.voice_count = count;
+        self.align_lfos_from(self.mixed_voice_count);
+        self.update_voice_levels();
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.voice_count
+    }
+
+    /// how evenly the voices' LFO phases are spread, 0 runs them in unison and 1 spreads them
+    /// evenly across the cycle (0/120/240 degrees with three voices)
+    pub fn set_voice_spread(&mut self, amount: f32) {
+        let voice_spread = sanitize(amount, 0.0, 1.0, DEFAULT_VOICE_SPREAD);
+        if voice_spread == self.voice_spread {
+            return;
+        }
+        self.voice_spread = voice_spread;
+        self.align_lfos();
+    }
+
+    /// phase of the first left LFO in degrees, the others follow from it through the voice spread
+    /// and stereo phase
+    pub fn lfo_phase(&self) -> f32 {
+        self.voices[0].left_lfo.phase().to_degrees()
+    }
+
+    /// puts every modulation source back where it starts: the LFOs at `degrees` as with
+    /// `retrigger_lfos`, with their random shapes, drift and humanize on their seeds, the rate
+    /// modulation LFO, the voices' chaos attractors, the grains and the diffuser's sweeps. From
+    /// here the modulation plays out the same every time, so a bounce started after it comes out
+    /// the same
+    pub fn restart_modulation(&mut self, degrees: f32) {
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            voice.reseed_lfos(i);
+            voice.restart_grains();
+            voice.chaos.restart();
+        }
+        self.voices[0].left_fast_lfo.set_phase(0.0);
+        self.rate_mod_lfo.set_phase(0.0);
+        self.rate_mod_lfo.reseed(lfo::DEFAULT_SEED);
+        self.left_diffuser.restart_modulation();
+        self.right_diffuser.restart_modulation();
+        self.retrigger_lfos(degrees);
+    }
+
+    /// restarts all LFOs, the first left one at `degrees` and the others at their voice spread
+    /// and stereo phase offsets from it
+    pub fn retrigger_lfos(&mut self, degrees: f32) {
+        let phase = sanitize(degrees, 0.0, 360.0, 0.0).to_radians();
+        self.voices[0].left_lfo.set_phase(phase);
+        self.align_lfos();
+    }
+
+    /// re-aligns all LFOs to the first left one, following the voice spread and stereo phase.
+    /// All LFOs always advance together, so aligning them once keeps the offsets. The fast bank
+    /// is always spread evenly across the cycle
+    fn align_lfos(&mut self) {
+        self.align_lfos_from(0);
+    }
+
+    /// `align_lfos` for the voices from `first` on, the ones before it are left alone
+    fn align_lfos_from(&mut self, first: usize) {
+        let (voice_spread, stereo_phase) = match self.algorithm {
+            Algorithm::Dimension => (1.0, 0.0),
+            Algorithm::Juno(_) => (self.voice_spread, JUNO_STEREO_PHASE),
+            _ => (self.voice_spread, self.stereo_phase),
+        };
+        let base_phase = self.voices[0].left_lfo.phase();
+        let fast_base_phase = self.voices[0].left_fast_lfo.phase();
+        for (i, voice) in self.voices.iter_mut().enumerate().skip(first) {
+            let phase = base_phase + voice_phase_offset(i, self.voice_count, voice_spread);
+            voice.left_lfo.set_phase(phase);
+            voice.right_lfo.set_phase(phase + stereo_phase);
 
-        let offset1 = ((self.left_lfos[0].next_value() * self.calc_depth / 2.0).round() as i32).clamp(-(self.delay_samples as i32) + 1 , self.delay_samples as i32 - 1);
-        let offset2 = ((self.left_lfos[1].next_value() * self.calc_depth / 2.0).round() as i32).clamp(-(self.delay_samples as i32) + 1 , self.delay_samples as i32 - 1);
-        let offset3 = ((self.left_lfos[2].next_value() * self.calc_depth / 2.0).round() as i32).clamp(-(self.delay_samples as i32) + 1 , self.delay_samples as i32 - 1);
+            let fast_phase = fast_base_phase + voice_phase_offset(i, self.voice_count, 1.0);
+            voice.left_fast_lfo.set_phase(fast_phase);
+            voice.right_fast_lfo.set_phase(fast_phase + stereo_phase);
+        }
+    }
+
+    pub fn set_waveform(&mut self, waveform: lfo::Waveform) {
+        self.waveform = waveform;
+        self.apply_waveform();
+    }
 
-        self.left_lfos[0].update_lfo();
-        self.left_lfos[1].update_lfo();
-        self.left_lfos[2].update_lfo();
+    /// the waveform the current algorithm runs with
+    fn apply_waveform(&mut self) {
+        let waveform = match self.algorithm {
+            Algorithm::Juno(_) => lfo::Waveform::Triangle,
+            Algorithm::Tape => lfo::Waveform::Drift,
+            _ => self.waveform,
+        };
+        for voice in self.voices.iter_mut() {
+            for lfo in voice.lfos_mut() {
+                lfo.set_waveform(waveform);
+            }
+        }
+    }
 
-        let mut delayed_signal = 0.0;
-        delayed_signal += self.left_delays[0].process_sample(xx, (self.delay_samples as i32 + offset1) as usize);
-        delayed_signal += self.left_delays[1].process_sample(xx, (self.delay_samples as i32 + offset2) as usize);
-        delayed_signal += self.left_delays[2].process_sample(xx, (self.delay_samples as i32 + offset3) as usize);
+    /// how the step sequencer modulates the voices, `None` leaves them to the LFOs
+    pub fn set_step_mode(&mut self, mode: Option<StepMode>) {
+        self.step_mode = mode;
+    }
 
-        self.left_feedback_buffer.rotate_right(1);
-        self.left_feedback_buffer[0] = delayed_signal / 3.0;
+    /// current value of the step sequencer, -1 to 1
+    pub fn set_step_value(&mut self, value: f32) {
+        self.step_value = sanitize(value, -1.0, 1.0, 0.0);
+    }
 
-        let mut left_out = 
-        self.dry * x 
-        + self.wet * 1.0/3.0 * delayed_signal;
+    /// makes the modulation sweep only above the base delay instead of around it
+    pub fn set_unipolar(&mut self, enabled: bool) {
+        self.unipolar = enabled;
+    }
 
-        if self.wet + self.dry > 1.0 {
-            left_out /= self.wet + self.dry;
+    /// delay offset in samples a -1..1 modulation value sweeps to with the given depth. Bipolar
+    /// sweeps `depth` samples centered on the base delay, unipolar the same range above it
+    fn sweep(&self, value: f32, depth: f32) -> f32 {
+        if self.sweeps_unipolar() {
+            (value + 1.0) / 2.0 * depth
+        } else {
+            value * depth / 2.0
         }
+    }
 
-        left_out
+    fn sweeps_unipolar(&self) -> bool {
+        self.unipolar || (self.algorithm == Algorithm::Flanger && !self.through_zero)
     }
 
-    pub fn process_right(&mut self, x: f32) -> f32 {
-        let xx = x + self.wet * self.feedback * self.right_feedback_buffer.get(self.delay_samples).unwrap();
+    fn flanges_through_zero(&self) -> bool {
+        self.through_zero && self.algorithm == Algorithm::Flanger
+    }
 
-        let offset1 = ((self.right_lfos[0].next_value() * self.calc_depth / 2.0).round() as i32).clamp(-(self.delay_samples as i32) + 1 , self.delay_samples as i32 - 1);
-        let offset2 = ((self.right_lfos[1].next_value() * self.calc_depth / 2.0).round() as i32).clamp(-(self.delay_samples as i32) + 1 , self.delay_samples as i32 - 1);
-        let offset3 = ((self.right_lfos[2].next_value() * self.calc_depth / 2.0).round() as i32).clamp(-(self.delay_samples as i32) + 1 , self.delay_samples as i32 - 1);
+    /// delay the 3D motion adds to a channel for `lfo_value`, which the right channel passes
+    /// negated so it moves the opposite way. Unipolar sweeps shift it up by half its range, so
+    /// it never reads below the base delay either, while the difference between the channels
+    /// stays the same
+    fn motion_3d(&self, lfo_value: f32) -> f32 {
+        let offset = lfo_value * self.calc_motion_3d / 2.0;
+        if self.sweeps_unipolar() {
+            offset + self.calc_motion_3d / 2.0
+        } else {
+            offset
+        }
+    }
+
+    /// through-zero flanging, only applies to the flanger. The dry path is delayed by
+    /// `THROUGH_ZERO_DELAY_MS`, and the voice sweeps around it instead of above the delay set
+    /// with `set_params`, so it passes the dry signal and the comb's notches sweep out to
+    /// infinity and back. The delay knob then only sets the feedback loop's delay. The output is
+    /// late by the dry delay, which the host has to be told about as latency
+    pub fn set_through_zero(&mut self, enabled: bool) {
+        self.through_zero = enabled;
+        self.update_base_delay();
+        self.update_dry_delay();
+    }
+
+    /// the base delay in samples the voices sweep around, see `set_through_zero`
+    fn base_delay(&self) -> f32 {
+        if self.flanges_through_zero() {
+            through_zero_delay(self.sample_rate) as f32
+        } else {
+            self.calc_delay
+        }
+    }
+
+    /// hands the base delay to the delay lines, which crossfade to it when it changed
+    fn update_base_delay(&mut self) {
+        let base_delay = self.base_delay();
+        for d in self.delays_mut() {
+            d.set_delay(base_delay);
+        }
+    }
+
+    /// the delay the dry path has in samples. It waits for the oversampler, so the dry signal
+    /// stays lined up with the voices, and while flanging through zero for
+    /// `THROUGH_ZERO_DELAY_MS` on top. Only changes with those settings, not with any knob
+    pub fn dry_delay(&self) -> usize {
+        let through_zero = if self.flanges_through_zero() { through_zero_delay(self.sample_rate) } else { 0 };
+        through_zero + self.oversampling_latency()
+    }
+
+    /// starts a crossfade of the dry path to a changed `dry_delay`, from wherever it reads now
+    fn update_dry_delay(&mut self) {
+        let dry_delay = self.dry_delay();
+        if dry_delay == self.dry_read_delay {
+            return;
+        }
+        self.dry_fade_from = self.dry_read_delay;
+        self.dry_read_delay = dry_delay;
+        self.dry_fade_length = ((DRY_CROSSFADE_MS / 1000.0) * self.sample_rate).round().max(1.0) as usize;
+        self.dry_fade_remaining = self.dry_fade_length;
+    }
+
+    /// advances the crossfade of the dry path by one frame
+    fn advance_dry_fade(&mut self) {
+        self.dry_fade_remaining = self.dry_fade_remaining.saturating_sub(1);
+    }
+
+    /// reads `buffer` at the dry delay, crossfading from the old one after a change
+    fn read_dry(&self, buffer: &RingBuffer<(f32, f32)>) -> (f32, f32) {
+        let (low, x) = buffer.get(self.dry_read_delay);
+        if self.dry_fade_remaining == 0 {
+            return (low, x);
+        }
+        let (old_low, old_x) = buffer.get(self.dry_fade_from);
+        let fade = self.dry_fade_remaining as f32 / self.dry_fade_length as f32;
+        (low + (old_low - low) * fade, x + (old_x - x) * fade)
+    }
+
+    /// the split left input as the dry path plays it, see `dry_delay`. The buffer is always
+    /// written, so switching through-zero on doesn't start from silence
+    fn dry_left(&mut self, low: f32, x: f32) -> (f32, f32) {
+        self.left_dry_buffer.push((low, x));
+        self.read_dry(&self.left_dry_buffer)
+    }
+
+    fn dry_right(&mut self, low: f32, x: f32) -> (f32, f32) {
+        self.right_dry_buffer.push((low, x));
+        self.read_dry(&self.right_dry_buffer)
+    }
+
+    /// how fast the chaotic modulation moves, in attractor time per second
+    pub fn set_chaos_speed(&mut self, speed: f32) {
+        let speed = sanitize(speed, 0.0, 100.0, DEFAULT_CHAOS_SPEED);
+        for voice in self.voices.iter_mut() {
+            voice.chaos.set_speed(speed);
+        }
+    }
+
+    /// crossfade between the LFOs (0) and the chaotic modulation (1)
+    pub fn set_chaos_amount(&mut self, amount: f32) {
+        self.chaos_amount = sanitize(amount, 0.0, 1.0, 0.0);
+    }
+
+    /// an LFO value with the chaos source and the step sequencer applied. `chaos_value` is the
+    /// chaos output this voice follows
+    fn modulation_source(&self, lfo_value: f32, chaos_value: f32) -> f32 {
+        let lfo_value = lfo_value + (chaos_value - lfo_value) * self.chaos_amount;
+        match self.step_mode {
+            None => lfo_value,
+            Some(StepMode::Replace) => self.step_value,
+            Some(StepMode::Sum) => (lfo_value + self.step_value).clamp(-1.0, 1.0),
+        }
+    }
+
+    /// how much the slow secondary LFO bends the rate of the main LFOs, 0 to 1 (an octave up and
+    /// down at full amount)
+    pub fn set_rate_mod_amount(&mut self, amount: f32) {
+        self.rate_mod_amount = sanitize(amount, 0.0, 1.0, 0.0);
+    }
+
+    /// detunes the voices' LFO rates by up to `spread` (a fraction of the rate, 0 to 0.5) either
+    /// way, so the voices don't sway in lockstep. The first voice always keeps the set rate.
+    pub fn set_rate_spread(&mut self, spread: f32) {
+        self.rate_spread = sanitize(spread, 0.0, 0.5, 0.0);
+    }
+
+    /// the table all LFOs read in the custom waveform
+    pub fn set_lfo_table(&mut self, table: &[f32]) {
+        for voice in self.voices.iter_mut() {
+            for lfo in voice.lfos_mut() {
+                lfo.set_table(table);
+            }
+        }
+    }
+
+    /// how much each LFO's phase and rate wander on their own, 0 to 1
+    pub fn set_lfo_humanize(&mut self, amount: f32) {
+        let amount = sanitize(amount, 0.0, 1.0, 0.0);
+        for voice in self.voices.iter_mut() {
+            for lfo in voice.lfos_mut() {
+                lfo.set_humanize(amount);
+            }
+        }
+    }
+
+    /// skews the LFO shape between saw down (-1), the unchanged shape (0) and saw up (1)
+    pub fn set_lfo_skew(&mut self, skew: f32) {
+        let skew = sanitize(skew, -1.0, 1.0, 0.0);
+        for voice in self.voices.iter_mut() {
+            for lfo in voice.lfos_mut() {
+                lfo.set_skew(skew);
+            }
+        }
+    }
+
+    /// feeds every voice back on itself instead of through the shared channel loops. The voices'
+    /// loops need `allocate_voice_feedback` to have been called first
+    pub fn set_per_voice_feedback(&mut self, enabled: bool) {
+        debug_assert!(!enabled || self.voice_feedback_allocated(), "per-voice feedback without allocated buffers");
+        if enabled && !self.per_voice_feedback {
+            // the loops were left as they were when last switched off
+            for voice in self.voices.iter_mut() {
+                for tap in voice.taps_mut() {
+                    tap.clear();
+                }
+            }
+        }
+        self.per_voice_feedback = enabled;
+    }
+
+    /// allocates the buffers of the voices' own feedback loops, this is not realtime safe. Only
+    /// needed with per-voice feedback, so the taps don't hold a second delay line each otherwise
+    pub fn allocate_voice_feedback(&mut self, sample_rate: f32) {
+        for voice in self.voices.iter_mut() {
+            for tap in voice.taps_mut() {
+                tap.allocate_feedback(sample_rate as usize);
+            }
+        }
+    }
+
+    pub fn voice_feedback_allocated(&self) -> bool {
+        self.voices.iter().all(|voice| voice.left_tap.has_feedback() && voice.right_tap.has_feedback())
+    }
+
+    /// routes the channel feedback loops across, 0 to 1. 0 feeds each channel's voices back into
+    /// the same channel, 1 feeds the left voices into the right delays and the right voices into
+    /// the left ones (ping-pong). Only applies to `process_stereo` and the shared channel loops,
+    /// not the per-voice feedback.
+    pub fn set_cross_feedback(&mut self, amount: f32) {
+        self.cross_feedback = sanitize(amount, 0.0, 1.0, 0.0);
+    }
+
+    /// flips the polarity of the feedback, which moves the comb filter's peaks to where its notches
+    /// were. Gives short delays a hollower, flanger-like character
+    pub fn set_invert_feedback(&mut self, enabled: bool) {
+        self.invert_feedback = enabled;
+    }
+
+    /// drives a tanh soft clipper inside the channel feedback loops, 0 to 1. Quiet repeats pass
+    /// unchanged, loud ones get rounded off, so high feedback blooms into saturation instead of
+    /// building up harshly. 0 bypasses it. Doesn't apply to the per-voice feedback.
+    pub fn set_feedback_drive(&mut self, drive: f32) {
+        self.feedback_drive = sanitize(drive, 0.0, 1.0, 0.0);
+    }
+
+    /// the feedback saturation, scaled back down by the drive gain so its small-signal gain
+    /// stays at unity
+    fn saturate_feedback(&self, x: f64) -> f64 {
+        if self.feedback_drive == 0.0 {
+            return x;
+        }
+        let gain = 1.0 + self.feedback_drive as f64 * (MAX_FEEDBACK_DRIVE_GAIN - 1.0);
+        (x * gain).tanh() / gain
+    }
+
+    /// gain of a pass around the feedback loop, negative with inverted feedback
+    fn loop_gain(&self) -> f32 {
+        if self.algorithm == Algorithm::Vibrato {
+            return 0.0;
+        }
+        let gain = self.wet * self.feedback;
+        if self.invert_feedback { -gain } else { gain }
+    }
+
+    /// one-pole low-pass and high-pass filters inside the channel feedback loops, so every
+    /// regeneration comes back a bit darker or thinner and high feedback doesn't build up
+    /// harshly. The first pass through the voices is left alone. Each filter is bypassed at its
+    /// `FEEDBACK_*_OFF_HZ` cutoff, while active they run in f32 even with high precision
+    /// feedback. Doesn't apply to the per-voice feedback. Has to be called after `set_params`,
+    /// as it depends on the sample rate.
+    pub fn set_feedback_tone(&mut self, lowpass_hz: f32, highpass_hz: f32) {
+        let nyquist = self.sample_rate / 2.0;
+        let lowpass_hz = sanitize(lowpass_hz, FEEDBACK_HIGHPASS_OFF_HZ, FEEDBACK_LOWPASS_OFF_HZ, FEEDBACK_LOWPASS_OFF_HZ);
+        let highpass_hz = sanitize(highpass_hz, FEEDBACK_HIGHPASS_OFF_HZ, FEEDBACK_LOWPASS_OFF_HZ, FEEDBACK_HIGHPASS_OFF_HZ);
+        if lowpass_hz == self.feedback_lowpass_hz
+            && highpass_hz == self.feedback_highpass_hz
+            && self.sample_rate == self.feedback_tone_sample_rate
+        {
+            return;
+        }
+
+        self.feedback_lowpass.first_order_lpf_coefficients(self.sample_rate, lowpass_hz.min(nyquist * 0.9));
+        self.feedback_highpass.first_order_hpf_coefficients(self.sample_rate, highpass_hz.min(nyquist * 0.9));
+        self.feedback_lowpass_hz = lowpass_hz;
+        self.feedback_highpass_hz = highpass_hz;
+        self.feedback_tone_sample_rate = self.sample_rate;
+    }
+
+    /// darkens the feedback crossing over to the other channel on top of the tone filters, so
+    /// ping-pong repeats lose their highs faster than the ones staying in their channel. Bypassed
+    /// at `FEEDBACK_LOWPASS_OFF_HZ`, where the crossed repeats are damped just like the others by
+    /// `set_feedback_tone`. Has to be called after `set_params`, as it depends on the sample rate.
+    pub fn set_cross_feedback_lowpass(&mut self, lowpass_hz: f32) {
+        let lowpass_hz = sanitize(lowpass_hz, FEEDBACK_HIGHPASS_OFF_HZ, FEEDBACK_LOWPASS_OFF_HZ, FEEDBACK_LOWPASS_OFF_HZ);
+        if lowpass_hz == self.cross_feedback_lowpass_hz && self.sample_rate == self.cross_feedback_lowpass_sample_rate {
+            return;
+        }
+
+        let nyquist = self.sample_rate / 2.0;
+        self.cross_feedback_lowpass.first_order_lpf_coefficients(self.sample_rate, lowpass_hz.min(nyquist * 0.9));
+        self.cross_feedback_lowpass_hz = lowpass_hz;
+        self.cross_feedback_lowpass_sample_rate = self.sample_rate;
+    }
+
+    /// runs the left channel feedback signal through the tone filters that are active
+    fn feedback_tone_left(&mut self, x: f64) -> f64 {
+        let mut x = x;
+        if self.feedback_lowpass_hz < FEEDBACK_LOWPASS_OFF_HZ {
+            x = self.feedback_lowpass.process_left(x as f32) as f64;
+        }
+        if self.feedback_highpass_hz > FEEDBACK_HIGHPASS_OFF_HZ {
+            x = self.feedback_highpass.process_left(x as f32) as f64;
+        }
+        x
+    }
+
+    /// runs the right channel feedback signal through the tone filters that are active
+    fn feedback_tone_right(&mut self, x: f64) -> f64 {
+        let mut x = x;
+        if self.feedback_lowpass_hz < FEEDBACK_LOWPASS_OFF_HZ {
+            x = self.feedback_lowpass.process_right(x as f32) as f64;
+        }
+        if self.feedback_highpass_hz > FEEDBACK_HIGHPASS_OFF_HZ {
+            x = self.feedback_highpass.process_right(x as f32) as f64;
+        }
+        x
+    }
+
+    /// tilts the voices' spectrum around `TILT_PIVOT_HZ`, -1 (dark) to 1 (bright). The lows
+    /// and highs move by up to `MAX_TILT_DB` in opposite directions, the dry signal is left
+    /// alone. Has to be called after `set_params`
+    pub fn set_tone(&mut self, tone: f32) {
+        let tone = sanitize(tone, -1.0, 1.0, 0.0);
+        if tone == self.tone && self.sample_rate == self.tone_sample_rate {
+            return;
+        }
+
+        let gain_db = tone * MAX_TILT_DB;
+        self.tilt_low.low_shelf_coefficients(self.sample_rate, TILT_PIVOT_HZ, -gain_db);
+        self.tilt_high.high_shelf_coefficients(self.sample_rate, TILT_PIVOT_HZ, gain_db);
+        self.tone = tone;
+        self.tone_sample_rate = self.sample_rate;
+    }
+
+    /// runs the tape saturation at twice the sample rate with anti-aliasing filters of the given
+    /// order, `None` runs it at the sample rate. The oversampler delays the voices by its latency
+    /// whatever the algorithm, so switching to tape doesn't move them in time. The dry path is
+    /// delayed to match and the channel feedback is read that much sooner, so the loop keeps its
+    /// length
+    pub fn set_oversampling(&mut self, order: Option<FilterOrder>) {
+        if let Some(order) = order {
+            self.left_oversampler.set_order(order);
+            self.right_oversampler.set_order(order);
+        }
+        self.oversampling = order;
+        self.update_dry_delay();
+    }
+
+    /// latency in samples the oversampling adds to the voices, 0 when it is off
+    fn oversampling_latency(&self) -> usize {
+        self.oversampling.map_or(0, FilterOrder::latency)
+    }
+
+    /// latency in samples of the whole chorus the host has to be told about, the dry path's
+    /// delay. Right after a change the dry path is still crossfading to it
+    pub fn latency(&self) -> usize {
+        self.dry_delay()
+    }
+
+    /// the delay line input of the left channel through the tape saturation, oversampled if set
+    fn saturate_input_left(&mut self, x: f64) -> f64 {
+        let tape = self.algorithm == Algorithm::Tape;
+        let saturate = |x: f64| if tape { tape_saturate(x as f32) as f64 } else { x };
+        match self.oversampling {
+            Some(_) => self.left_oversampler.process(x, saturate),
+            None => saturate(x),
+        }
+    }
+
+    fn saturate_input_right(&mut self, x: f64) -> f64 {
+        let tape = self.algorithm == Algorithm::Tape;
+        let saturate = |x: f64| if tape { tape_saturate(x as f32) as f64 } else { x };
+        match self.oversampling {
+            Some(_) => self.right_oversampler.process(x, saturate),
+            None => saturate(x),
+        }
+    }
+
+    /// how far back the channel feedback is read, the delay less the oversampling latency
+    fn feedback_delay(&self) -> usize {
+        self.delay_samples.saturating_sub(self.oversampling_latency())
+    }
+
+    fn tilt_left(&mut self, x: f32) -> f32 {
+        if self.tone == 0.0 {
+            return x;
+        }
+        self.tilt_high.process_left(self.tilt_low.process_left(x))
+    }
+
+    fn tilt_right(&mut self, x: f32) -> f32 {
+        if self.tone == 0.0 {
+            return x;
+        }
+        self.tilt_high.process_right(self.tilt_low.process_right(x))
+    }
+
+    /// cutoff of a 12 dB/oct high-pass on the voices, so the lows stay in the dry signal only,
+    /// solid and mono. Off at `WET_HIGHPASS_OFF_HZ`. Has to be called after `set_params`
+    pub fn set_wet_highpass(&mut self, cutoff_hz: f32) {
+        let cutoff_hz = sanitize(cutoff_hz, WET_HIGHPASS_OFF_HZ, self.sample_rate * 0.45, WET_HIGHPASS_OFF_HZ);
+        if cutoff_hz == self.wet_highpass_hz && self.sample_rate == self.wet_highpass_sample_rate {
+            return;
+        }
+        if self.wet_highpass_hz == WET_HIGHPASS_OFF_HZ {
+            self.wet_highpass.reset_filter();
+        }
+
+        self.wet_highpass.second_order_hpf_coefficients(self.sample_rate, cutoff_hz, WET_HIGHPASS_Q);
+        self.wet_highpass_hz = cutoff_hz;
+        self.wet_highpass_sample_rate = self.sample_rate;
+    }
+
+    fn wet_highpass_left(&mut self, x: f32) -> f32 {
+        if self.wet_highpass_hz == WET_HIGHPASS_OFF_HZ {
+            return x;
+        }
+        self.wet_highpass.process_left(x)
+    }
+
+    fn wet_highpass_right(&mut self, x: f32) -> f32 {
+        if self.wet_highpass_hz == WET_HIGHPASS_OFF_HZ {
+            return x;
+        }
+        self.wet_highpass.process_right(x)
+    }
+
+    /// whether the tone tilt and the wet high-pass filter the input of the voices or their
+    /// output. Only the voices are filtered either way, the dry signal is left alone. The
+    /// filters start over when they move, the signal they held doesn't belong to the new spot
+    pub fn set_filter_position(&mut self, position: FilterPosition) {
+        if position == self.filter_position {
+            return;
+        }
+        self.filter_position = position;
+        self.tilt_low.reset_filter();
+        self.tilt_high.reset_filter();
+        self.wet_highpass.reset_filter();
+    }
 
-        self.right_lfos[0].update_lfo();
-        self.right_lfos[1].update_lfo();
-        self.right_lfos[2].update_lfo();
+    /// the tone tilt and the wet high-pass of the left channel
+    fn wet_filters_left(&mut self, x: f32) -> f32 {
+        let x = self.tilt_left(x);
+        self.wet_highpass_left(x)
+    }
 
-        let mut delayed_signal = 0.0;
-        delayed_signal += self.right_delays[0].process_sample(xx, (self.delay_samples as i32 + offset1) as usize);
-        delayed_signal += self.right_delays[1].process_sample(xx, (self.delay_samples as i32 + offset2) as usize);
-        delayed_signal += self.right_delays[2].process_sample(xx, (self.delay_samples as i32 + offset3) as usize);
+    fn wet_filters_right(&mut self, x: f32) -> f32 {
+        let x = self.tilt_right(x);
+        self.wet_highpass_right(x)
+    }
 
-        self.right_feedback_buffer.rotate_right(1);
-        self.right_feedback_buffer[0] = delayed_signal / 3.0;
+    /// `x` through the wet filters of the left channel if they sit at `position`
+    fn wet_filters_left_at(&mut self, position: FilterPosition, x: f32) -> f32 {
+        if self.filter_position == position { self.wet_filters_left(x) } else { x }
+    }
 
-        let mut right_out = self.dry * x 
-        + self.wet * 1.0/3.0 * delayed_signal;
+    fn wet_filters_right_at(&mut self, position: FilterPosition, x: f32) -> f32 {
+        if self.filter_position == position { self.wet_filters_right(x) } else { x }
+    }
 
-        if self.wet + self.dry > 1.0 {
-            right_out /= self.wet + self.dry;
+    /// crossover frequency below which the input bypasses the chorus altogether, dry and wet
+    /// controls included. The bands are split and recombined with a Linkwitz-Riley crossover,
+    /// so the lows come back in phase with the rest. Off at `BASS_PRESERVE_OFF_HZ`. Has to be
+    /// called after `set_params`
+    pub fn set_bass_preserve(&mut self, crossover_hz: f32) {
+        let crossover_hz = sanitize(crossover_hz, BASS_PRESERVE_OFF_HZ, self.sample_rate * 0.45, BASS_PRESERVE_OFF_HZ);
+        if crossover_hz == self.crossover_hz && self.sample_rate == self.crossover_sample_rate {
+            return;
         }
+        if self.crossover_hz == BASS_PRESERVE_OFF_HZ {
+            self.crossover.reset_filter();
+        }
+
+        self.crossover.set_cutoff(self.sample_rate, crossover_hz);
+        self.crossover_hz = crossover_hz;
+        self.crossover_sample_rate = self.sample_rate;
+    }
+
+    /// (low, high) bands of a left channel sample, the lows bypass the chorus
+    fn split_left(&mut self, x: f32) -> (f32, f32) {
+        if self.crossover_hz == BASS_PRESERVE_OFF_HZ {
+            return (0.0, x);
+        }
+        self.crossover.split_left(x)
+    }
+
+    fn split_right(&mut self, x: f32) -> (f32, f32) {
+        if self.crossover_hz == BASS_PRESERVE_OFF_HZ {
+            return (0.0, x);
+        }
+        self.crossover.split_right(x)
+    }
+
+    pub fn set_rate_follows_delay(&mut self, enabled: bool) {
+        self.rate_follows_delay = enabled;
+    }
+
+    /// runs the plain feedback loop in f64: the per-channel feedback accumulator (the voice sum
+    /// and the feedback state), the delay lines and the LFO phases. In f32 the rounding error of
+    /// each pass around the loop gets fed back along with the signal, at high feedback it builds
+    /// up into a noise floor under the decaying repeats. With f64 each rounding step is about 29
+    /// bits smaller: 6 s into the decay of a 10 ms comb at 0.99 feedback the rounding noise in
+    /// the loop measures 6.5e-7 of the signal (-124 dB) in f32 and 1.9e-15 (-294 dB) in f64.
+    /// Whatever else sits in the loop still rounds to f32 on every pass: the feedback tone and
+    /// cross feedback filters, the tape saturation and the BBD, so with any of them engaged the
+    /// f32 floor comes back. The state is always kept in f64 and only rounded while this is off,
+    /// so switching doesn't interrupt the sound, at the cost of twice the memory of f32 delay
+    /// and feedback buffers
+    pub fn set_high_precision(&mut self, enabled: bool) {
+        self.high_precision = enabled;
+        for d in self.delays_mut() {
+            d.set_high_precision(enabled);
+        }
+        for voice in self.voices.iter_mut() {
+            for lfo in voice.lfos_mut() {
+                lfo.set_high_precision(enabled);
+            }
+            for lfo in voice.fast_lfos_mut() {
+                lfo.set_high_precision(enabled);
+            }
+        }
+        self.rate_mod_lfo.set_high_precision(enabled);
+    }
+
+    pub fn set_feedback_makeup(&mut self, enabled: bool) {
+        self.feedback_makeup = enabled;
+    }
+
+    /// amount (in ms) of LFO-driven delay difference between the channels of each voice. Unlike a
+    /// static width this moves the voices around the stereo field together with the modulation.
+    /// Has to be called after `set_params`, as it depends on the sample rate.
+    pub fn set_motion_3d(&mut self, amount_ms: f32) {
+        self.calc_motion_3d = sanitize(amount_ms, 0.0, f32::MAX, 0.0) / 1000.0 * self.sample_rate;
+    }
+
+    /// staggers the voices' base delays by up to `amount_ms` on top of the shared delay, which
+    /// decorrelates the voices. Has to be called after `set_params`, as it depends on the sample
+    /// rate.
+    pub fn set_delay_spread(&mut self, amount_ms: f32) {
+        self.calc_delay_spread = sanitize(amount_ms, 0.0, f32::MAX, 0.0) / 1000.0 * self.sample_rate;
+    }
+
+    /// tapers the voice levels, -1 to 1. 0 mixes all voices equally, negative values emphasize
+    /// the voice with the shortest base delay (for a doubling effect, -1 mutes the longest) and
+    /// positive values the longest one (for diffusion, 1 mutes the shortest). The ranking comes
+    /// from the delay spread, see `set_delay_spread`. The overall level stays the same.
+    pub fn set_voice_taper(&mut self, taper: f32) {
+        let taper = sanitize(taper, -1.0, 1.0, 0.0);
+        if taper == self.voice_taper {
+            return;
+        }
+        self.voice_taper = taper;
+        self.update_voice_levels();
+    }
+
+    /// sets the voice levels for the voice count and taper, the voices fade to them over
+    /// `VOICE_FADE_MS` from wherever they are, so voices coming in or dropping out don't click
+    fn update_voice_levels(&mut self) {
+        for (i, level) in self.voice_levels.iter_mut().enumerate() {
+            *level = if i < self.voice_count { voice_level(i, self.voice_count, self.voice_taper) } else { 0.0 };
+        }
+        self.voice_fade_from = self.voice_gains;
+        self.voice_fade_length = ((VOICE_FADE_MS / 1000.0) * self.sample_rate).round().max(1.0) as usize;
+        self.voice_fade_remaining = self.voice_fade_length;
+        self.mixed_voice_count = self.mixed_voice_count.max(self.voice_count);
+    }
+
+    /// advances the voice fade by one frame
+    fn advance_voice_fade(&mut self) {
+        if self.voice_fade_remaining == 0 {
+            return;
+        }
+        self.voice_fade_remaining -= 1;
+        if self.voice_fade_remaining == 0 {
+            self.finish_voice_fade();
+            return;
+        }
+        let progress = 1.0 - self.voice_fade_remaining as f32 / self.voice_fade_length as f32;
+        for ((gain, from), level) in self.voice_gains.iter_mut().zip(self.voice_fade_from).zip(self.voice_levels) {
+            *gain = from + (level - from) * progress;
+        }
+        self.voice_gain_sum = self.voice_gains[..self.mixed_voice_count].iter().sum();
+    }
+
+    /// jumps to the end of the voice fade, the dropped voices stop being mixed
+    fn finish_voice_fade(&mut self) {
+        self.voice_fade_remaining = 0;
+        self.voice_gains = self.voice_levels;
+        self.mixed_voice_count = self.voice_count;
+        self.voice_gain_sum = self.voice_gains[..self.voice_count].iter().sum();
+    }
+
+    /// smears the voices through a short chain of modulated all-pass filters, 0 to 1, towards a
+    /// reverb-like ensemble. Sits after the feedback taps, so it doesn't feed back
+    pub fn set_diffusion(&mut self, amount: f32) {
+        let amount = sanitize(amount, 0.0, 1.0, 0.0);
+        self.left_diffuser.set_amount(amount);
+        self.right_diffuser.set_amount(amount);
+    }
+
+    /// adds a hiss to the voices, 0 to 1. The hiss follows the input level, so it fades out
+    /// with the signal and silence stays silent
+    pub fn set_noise(&mut self, amount: f32) {
+        self.noise = sanitize(amount, 0.0, 1.0, 0.0);
+    }
+
+    /// one sample of the hiss, gated by the channel's input `envelope`
+    fn noise(&mut self, envelope: f32) -> f32 {
+        if self.noise == 0.0 {
+            return 0.0;
+        }
+        let white = self.noise_source.next_value();
+
+        let gate = (envelope / NOISE_GATE_LEVEL).min(1.0);
+        white * self.noise * MAX_NOISE_LEVEL * gate
+    }
+
+    /// pitch offset of the granular voices in cents, 0 to `MAX_GRAIN_DETUNE_CENTS`. The voices
+    /// spread evenly from `-cents` to `cents`, a single voice is shifted up, and the right
+    /// channel's voices are shifted the opposite way of the left ones
+    pub fn set_grain_detune(&mut self, cents: f32) {
+        self.grain_detune = sanitize(cents, 0.0, MAX_GRAIN_DETUNE_CENTS, DEFAULT_GRAIN_DETUNE_CENTS);
+    }
+
+    /// pitch ratio of the left channel's granular voice `index`, the right one uses the inverse
+    fn grain_ratio(&self, index: usize) -> f32 {
+        (self.grain_detune * grain_detune_offset(index, self.voice_count) / 1200.0).exp2()
+    }
+
+    /// how the voices' delay lines read between samples, see `Interpolation`
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        for d in self.delays_mut() {
+            d.set_interpolation(interpolation);
+        }
+    }
+
+    /// runs every delay line through a bucket-brigade chip emulation, see `Bbd`. Switching it on
+    /// clears the emulation, so nothing left over from before comes back
+    pub fn set_bbd(&mut self, enabled: bool) {
+        if enabled && !self.bbd {
+            for voice in self.voices.iter_mut() {
+                for bbd in voice.bbds_mut() {
+                    bbd.clear();
+                }
+            }
+        }
+        self.bbd = enabled;
+    }
+
+    /// number of stages of the emulated bucket-brigade chips, `bbd::MIN_STAGES` to
+    /// `bbd::MAX_STAGES`. More stages clock faster at the same delay, so they sound brighter
+    /// and cleaner, but hiss a bit more
+    pub fn set_bbd_stages(&mut self, stages: usize) {
+        for voice in self.voices.iter_mut() {
+            for bbd in voice.bbds_mut() {
+                bbd.set_stages(stages);
+            }
+        }
+    }
+
+    /// pans the voices across the stereo field, 0 to 1. At 0 every voice plays its left delay
+    /// line on the left and its right one on the right, higher values fold each voice to mono
+    /// and move it towards its own position, at 1 they are spread from hard left to hard right.
+    /// Only applies to `process_stereo`.
+    pub fn set_pan_width(&mut self, width: f32) {
+        self.pan_width = sanitize(width, 0.0, 1.0, 0.0);
+    }
+
+    /// scales the side signal of the voices, 0 to `MAX_WIDTH`. 0 collapses the wet signal to
+    /// mono, 1 leaves it as it is and above 1 exaggerates it, the dry signal is left alone.
+    /// Mono protect comes after it, so it still reins in too wide a setting
+    pub fn set_width(&mut self, width: f32) {
+        self.width = sanitize(width, 0.0, MAX_WIDTH, 1.0);
+    }
+
+    /// flips the polarity of the right channel's voices, which pushes them to the far sides of
+    /// the stereo field but cancels them in mono. Comes after mono protect, which would
+    /// otherwise undo it
+    pub fn set_invert_right_wet(&mut self, enabled: bool) {
+        self.invert_right_wet = enabled;
+    }
+
+    fn right_wet_polarity(&self) -> f32 {
+        if self.invert_right_wet { -1.0 } else { 1.0 }
+    }
+
+    fn widen(&self, left: f32, right: f32) -> (f32, f32) {
+        if self.width == 1.0 {
+            return (left, right);
+        }
+        let (mid, side) = stereo::encode_mid_side(left, right);
+        stereo::decode_mid_side(mid, side * self.width)
+    }
+
+    /// base delay in samples of voice `index`, the shared delay plus its share of the spread
+    fn voice_delay(&self, index: usize) -> f32 {
+        self.base_delay() + VOICE_DELAY_OFFSETS[index] * self.calc_delay_spread
+    }
+
+    /// gain applied to the delayed signal to make up for the feedback buildup. A feedback comb
+    /// raises the average power by 1 / (1 - fb^2), so the makeup is the inverse of its square root
+    fn feedback_makeup_gain(&self) -> f32 {
+        if self.feedback_makeup {
+            (1.0 - self.feedback * self.feedback).sqrt()
+        } else {
+            1.0
+        }
+    }
+
+    fn delays_mut(&mut self) -> [&mut Delay; 2] {
+        [&mut self.left_delay, &mut self.right_delay]
+    }
+
+    pub fn resize_buffers(&mut self, sample_rate: f32) {
+        self.left_envelope.set_sample_rate(sample_rate);
+        self.right_envelope.set_sample_rate(sample_rate);
+        self.sidechain_envelope.set_sample_rate(sample_rate);
+        self.left_transient.set_sample_rate(sample_rate);
+        self.right_transient.set_sample_rate(sample_rate);
+
+        for d in self.delays_mut() {
+            d.resize_buffers(sample_rate as usize);
+        }
+        for voice in self.voices.iter_mut() {
+            for tap in voice.taps_mut() {
+                tap.resize_buffers(sample_rate as usize);
+            }
+        }
+
+        self.left_feedback_buffer.resize(base_buffer_len(sample_rate));
+        self.right_feedback_buffer.resize(base_buffer_len(sample_rate));
+        self.left_dry_buffer.resize(dry_buffer_len(sample_rate));
+        self.right_dry_buffer.resize(dry_buffer_len(sample_rate));
+        self.left_diffuser.resize_buffers(sample_rate);
+        self.right_diffuser.resize_buffers(sample_rate);
+    }
+
+    /// clears all delay lines, feedback paths and followers, and starts a short fade-in of the
+    /// voices so whatever comes first after the reset ramps up instead of starting abruptly. The
+    /// dry signal is left alone, so resetting in the middle of playback doesn't cut it off.
+    pub fn reset(&mut self) {
+        for d in self.delays_mut() {
+            d.clear();
+        }
+        for voice in self.voices.iter_mut() {
+            for tap in voice.taps_mut() {
+                tap.clear();
+            }
+            for bbd in voice.bbds_mut() {
+                bbd.clear();
+            }
+        }
+        self.left_feedback_buffer.clear();
+        self.right_feedback_buffer.clear();
+        self.left_dry_buffer.clear();
+        self.right_dry_buffer.clear();
+        self.feedback_lowpass.reset_filter();
+        self.tilt_low.reset_filter();
+        self.tilt_high.reset_filter();
+        self.wet_highpass.reset_filter();
+        self.crossover.reset_filter();
+        self.feedback_highpass.reset_filter();
+        self.cross_feedback_lowpass.reset_filter();
+        self.left_diffuser.clear();
+        self.right_diffuser.clear();
+        self.left_oversampler.clear();
+        self.right_oversampler.clear();
+        self.left_envelope.reset();
+        self.right_envelope.reset();
+        self.sidechain_envelope.reset();
+        self.left_transient.reset();
+        self.right_transient.reset();
+
+        self.correlation_lr = 0.0;
+        self.correlation_ll = 0.0;
+        self.correlation_rr = 0.0;
+        self.correlation = 1.0;
+        self.protect_width = 1.0;
+        self.feedback_mean_square = 0.0;
+
+        self.finish_voice_fade();
+        self.dry_fade_remaining = 0;
+        self.fade_in_length = ((FADE_IN_MS / 1000.0) * self.sample_rate).round().max(1.0) as usize;
+        self.fade_in_remaining = self.fade_in_length;
+    }
+
+    /// gain of the fade-in for the current frame, advances the ramp by one frame
+    fn fade_in_gain(&mut self) -> f32 {
+        if self.fade_in_remaining == 0 {
+            return 1.0;
+        }
+        self.fade_in_remaining -= 1;
+        1.0 - self.fade_in_remaining as f32 / self.fade_in_length as f32
+    }
+
+
+    /// delay in samples a voice with the base delay `delay` (see `voice_delay`) reads at for the
+    /// given modulation. Kept within 1..2 * delay - 1 (0 for a zero delay), so deep modulation of
+    /// a short delay can never produce a negative read position that wraps around to a huge index.
+    /// Unipolar sweeps only move up from the delay, so they aren't capped at twice the delay.
+    /// Not rounded, the delay lines read between samples
+    fn read_position(&self, delay: f32, modulation: f32) -> f32 {
+        if self.sweeps_unipolar() {
+            return (delay + modulation).max(1.0);
+        }
+        let max_position = (2.0 * delay - 1.0).max(0.0);
+        (delay + modulation).max(1.0).min(max_position)
+    }
+
+    /// current fractional read delay in samples of each voice, the left channel voices followed
+    /// by the right channel ones. Reflects the last processed sample, so the current parameters
+    /// and LFO positions. Doesn't allocate, cheap enough to poll every block:
+    ///
+    /// ```ignore
+    /// let positions = chorus.voice_read_positions();
+    /// let (left, right) = positions.split_at(chorus.voice_count());
+    /// ```
+    pub fn voice_read_positions(&self) -> &[f32] {
+        &self.voice_read_positions[..2 * self.voice_count]
+    }
+
+    /// moves everything that runs once per frame on by one frame, whichever channels are
+    /// processed
+    fn advance_frame(&mut self) {
+        self.advance_lfos();
+        self.advance_voice_fade();
+        self.advance_dry_fade();
+    }
+
+    /// reads this frame's values of all LFOs and advances them. Both channels advance even when
+    /// only one is processed, so the stereo phase between them holds in every processing mode.
+    fn advance_lfos(&mut self) {
+        let rate_mod = self.rate_mod_lfo.next_value();
+        self.rate_mod_lfo.update_lfo();
+        let rate_multiplier = (self.rate_mod_amount * RATE_MOD_OCTAVES * rate_mod).exp2();
+
+        // (slow, fast) weights of the LFO banks when the fast one is in use
+        let bank_weights = match self.algorithm {
+            Algorithm::Ensemble => Some((ENSEMBLE_SLOW_WEIGHT, ENSEMBLE_FAST_WEIGHT)),
+            Algorithm::Tape => Some((TAPE_WOW_WEIGHT, TAPE_FLUTTER_WEIGHT)),
+            _ => None,
+        };
+        // the inactive voices advance too, so they come in at their phase offsets
+        for (voice, rate_offset) in self.voices.iter_mut().zip(VOICE_RATE_OFFSETS) {
+            let detune = 1.0 + self.rate_spread * rate_offset;
+            for lfo in voice.lfos_mut() {
+                lfo.set_rate_multiplier(rate_multiplier * detune);
+            }
+            voice.left_lfo_value = voice.left_lfo.next_value();
+            voice.left_lfo.update_lfo();
+            voice.right_lfo_value = voice.right_lfo.next_value();
+            voice.right_lfo.update_lfo();
+
+            if self.chaos_amount > 0.0 {
+                voice.chaos.step();
+                [voice.left_chaos_value, voice.right_chaos_value, _] = voice.chaos.outputs();
+            }
+
+            if let Some((slow_weight, fast_weight)) = bank_weights {
+                voice.left_lfo_value = slow_weight * voice.left_lfo_value + fast_weight * voice.left_fast_lfo.next_value();
+                voice.left_fast_lfo.update_lfo();
+                voice.right_lfo_value = slow_weight * voice.right_lfo_value + fast_weight * voice.right_fast_lfo.next_value();
+                voice.right_fast_lfo.update_lfo();
+            }
+        }
+    }
+
+    /// the signals the channel feedback loops feed back this frame, (left, right), crossed over
+    /// by the cross feedback. Both are read before either channel pushes its new output, so the
+    /// two sides see the same frame of each other
+    fn feedback_taps(&mut self) -> (f64, f64) {
+        let left = self.left_feedback_buffer.get(self.feedback_delay());
+        let right = self.right_feedback_buffer.get(self.feedback_delay());
+        let cross = self.cross_feedback as f64;
+        // what crosses over comes from the other channel, so the left filter state follows the
+        // right loop and the other way around
+        let (to_left, to_right) = if self.cross_feedback_lowpass_hz < FEEDBACK_LOWPASS_OFF_HZ {
+            (
+                self.cross_feedback_lowpass.process_left(right as f32) as f64,
+                self.cross_feedback_lowpass.process_right(left as f32) as f64,
+            )
+        } else {
+            (right, left)
+        };
+        (left + (to_left - left) * cross, right + (to_right - right) * cross)
+    }
+
+    /// read position of the left channel's voice `index` for this frame's LFO values, with the
+    /// sweep scaled to `depth`
+    fn left_read_position(&self, index: usize, depth: f32) -> f32 {
+        let voice = &self.voices[index];
+        let lfo_value = voice.left_lfo_value;
+        let source = self.modulation_source(lfo_value, voice.left_chaos_value);
+        // the 3D term always follows the LFO, so it stays opposite to the right channel's
+        let modulation = self.sweep(source, depth) + self.motion_3d(lfo_value);
+        self.read_position(self.voice_delay(index), modulation)
+    }
+
+    fn right_read_position(&self, index: usize, depth: f32) -> f32 {
+        let voice = &self.voices[index];
+        let source = self.modulation_source(voice.right_lfo_value, voice.right_chaos_value);
+        // the 3D term follows the left LFO the opposite way, so the voice sweeps across the
+        // stereo field
+        let modulation = self.sweep(source, depth) + self.motion_3d(-voice.left_lfo_value);
+        self.read_position(self.voice_delay(index), modulation)
+    }
+
+    /// the voices of the left channel, before they are scaled by the wet amount. `feedback` is
+    /// the signal the channel loop feeds back, see `feedback_taps`
+    fn voices_left(&mut self, x: f32, feedback: f64) -> f32 {
+        let channel_feedback = if self.per_voice_feedback { 0.0 } else { self.loop_gain() };
+        let xx = x as f64 + channel_feedback as f64 * feedback;
+        let xx = self.saturate_input_left(xx);
+        let envelope = self.left_envelope.process(x);
+        let transient = self.left_transient.process(x);
+        let depth = self.calc_depth * self.dynamic_depth_gain(envelope) * self.sidechain_gain(self.sidechain_depth);
+        let grain_window = GRAIN_MS / 1000.0 * self.sample_rate;
+        self.left_delay.push(xx);
+
+        let mut delayed_signal = 0.0f64;
+        for i in 0..self.mixed_voice_count {
+            let delay = self.voice_delay(i);
+            let mut position = self.left_read_position(i, depth);
+            let output = match self.algorithm {
+                Algorithm::Granular => {
+                    let ratio = self.grain_ratio(i);
+                    let voice = &mut self.voices[i];
+                    let (output, grain_position) = process_grains(&self.left_delay, &mut voice.left_tap, &mut voice.left_grain_phase, delay, grain_window, ratio);
+                    position = grain_position;
+                    output
+                }
+                _ => self.left_delay.read_tap(&mut self.voices[i].left_tap, position),
+            };
+            // voices fading out aren't reported, they are on their way out
+            if i < self.voice_count {
+                self.voice_read_positions[i] = position;
+            }
+            // the delay line is shared, so the chip's input stage runs on the tap's output instead
+            // of ahead of the delay. Neither its tanh compression nor its sample and hold is
+            // linear, so this isn't the chip's order: the compression acts on the already swept
+            // signal and the held steps are taken after the sweep. With the slow sweeps of a
+            // chorus the difference is slight
+            let output = if self.bbd {
+                let delay_ms = delay / self.sample_rate * 1000.0;
+                let bbd = &mut self.voices[i].left_bbd;
+                bbd.set_delay(self.sample_rate, delay_ms);
+                let sampled = bbd.process_input(output as f32);
+                bbd.process_output(sampled) as f64
+            } else {
+                output
+            };
+            self.voices[i].left_output = output as f32;
+            delayed_signal += output * self.voice_gains[i] as f64;
+        }
+
+        let feedback_signal = self.feedback_precision(delayed_signal / self.voice_gain_sum as f64);
+        let filtered = self.feedback_tone_left(feedback_signal);
+        self.left_feedback_buffer.push(self.saturate_feedback(filtered));
+        let voices = feedback_signal as f32;
+        self.track_feedback_energy(voices);
+
+        self.left_voices_gain = self.feedback_makeup_gain() * self.transient_duck_gain(transient) * self.sidechain_gain(self.sidechain_wet);
+        voices * self.left_voices_gain
+    }
+
+    /// the voices of the right channel, before they are scaled by the wet amount. `feedback` is
+    /// the signal the channel loop feeds back, see `feedback_taps`
+    fn voices_right(&mut self, x: f32, feedback: f64) -> f32 {
+        let channel_feedback = if self.per_voice_feedback { 0.0 } else { self.loop_gain() };
+        let xx = x as f64 + channel_feedback as f64 * feedback;
+        let xx = self.saturate_input_right(xx);
+        let envelope = self.right_envelope.process(x);
+        let transient = self.right_transient.process(x);
+        let depth = self.calc_depth * self.dynamic_depth_gain(envelope) * self.sidechain_gain(self.sidechain_depth);
+        let grain_window = GRAIN_MS / 1000.0 * self.sample_rate;
+        self.right_delay.push(xx);
+
+        let mut delayed_signal = 0.0f64;
+        for i in 0..self.mixed_voice_count {
+            let delay = self.voice_delay(i);
+            let mut position = self.right_read_position(i, depth);
+            let output = match self.algorithm {
+                Algorithm::Granular => {
+                    let ratio = 1.0 / self.grain_ratio(i);
+                    let voice = &mut self.voices[i];
+                    let (output, grain_position) = process_grains(&self.right_delay, &mut voice.right_tap, &mut voice.right_grain_phase, delay, grain_window, ratio);
+                    position = grain_position;
+                    output
+                }
+                _ => self.right_delay.read_tap(&mut self.voices[i].right_tap, position),
+            };
+            // voices fading out aren't reported, they are on their way out
+            if i < self.voice_count {
+                self.voice_read_positions[self.voice_count + i] = position;
+            }
+            let output = if self.bbd {
+                let delay_ms = delay / self.sample_rate * 1000.0;
+                let bbd = &mut self.voices[i].right_bbd;
+                bbd.set_delay(self.sample_rate, delay_ms);
+                let sampled = bbd.process_input(output as f32);
+                bbd.process_output(sampled) as f64
+            } else {
+                output
+            };
+            self.voices[i].right_output = output as f32;
+            delayed_signal += output * self.voice_gains[i] as f64;
+        }
+
+        let feedback_signal = self.feedback_precision(delayed_signal / self.voice_gain_sum as f64);
+        let filtered = self.feedback_tone_right(feedback_signal);
+        self.right_feedback_buffer.push(self.saturate_feedback(filtered));
+        let voices = feedback_signal as f32;
+        self.track_feedback_energy(voices);
+
+        self.right_voices_gain = self.feedback_makeup_gain() * self.transient_duck_gain(transient) * self.sidechain_gain(self.sidechain_wet);
+        voices * self.right_voices_gain
+    }
+
+    /// the dimension's stereo image, (left, right). Replaces the averaged voices of both channels
+    /// with the first voice on the left and the second on the right, each with the other
+    /// subtracted. Has to be called after both channels' voices were processed
+    fn cross_mix_voices(&self) -> (f32, f32) {
+        let first_left = self.voices[0].left_output * self.left_voices_gain;
+        let second_left = self.voices[1].left_output * self.left_voices_gain;
+        let first_right = self.voices[0].right_output * self.right_voices_gain;
+        let second_right = self.voices[1].right_output * self.right_voices_gain;
+        (
+            first_left - DIMENSION_CROSS_MIX * second_left,
+            second_right - DIMENSION_CROSS_MIX * first_right,
+        )
+    }
+
+    /// the stereo voice mixer, pans the voices of this frame by the pan width. `left` and `right`
+    /// are the channel sums `voices_left` and `voices_right` returned, which are left as they
+    /// are at a pan width of 0. The feedback loops keep taking the unpanned sums, so panning
+    /// doesn't change how the feedback builds up
+    fn pan_voices(&self, left: f32, right: f32) -> (f32, f32) {
+        if self.pan_width == 0.0 {
+            return (left, right);
+        }
+
+        let mut left_offset = 0.0;
+        let mut right_offset = 0.0;
+        // voices fading out stay where the unpanned sums have them
+        for (i, voice) in self.voices.iter().enumerate().take(self.voice_count) {
+            let l = voice.left_output * self.voice_gains[i] * self.left_voices_gain;
+            let r = voice.right_output * self.voice_gains[i] * self.right_voices_gain;
+            let mono = (l + r) / 2.0;
+            // equal power, scaled so a centered voice keeps its level on both sides
+            let angle = (voice_pan_position(i, self.voice_count) * self.pan_width + 1.0) * core::f32::consts::FRAC_PI_4;
+            left_offset += mono * angle.cos() * core::f32::consts::SQRT_2 - l;
+            right_offset += mono * angle.sin() * core::f32::consts::SQRT_2 - r;
+        }
+
+        let scale = self.pan_width / self.voice_gain_sum;
+        (left + left_offset * scale, right + right_offset * scale)
+    }
+
+    /// rounds the feedback accumulator to f32 precision unless high precision is on
+    fn feedback_precision(&self, x: f64) -> f64 {
+        if self.high_precision {
+            x
+        } else {
+            x as f32 as f64
+        }
+    }
+
+    /// averages the energy of the signal going around the feedback loop, both channels share
+    /// the average
+    fn track_feedback_energy(&mut self, voices: f32) {
+        let loop_signal = voices * self.wet * self.feedback;
+        let coeff = 1.0 / (FEEDBACK_ENERGY_TIME_S * self.sample_rate);
+        self.feedback_mean_square += coeff * (loop_signal * loop_signal - self.feedback_mean_square);
+    }
+
+    /// RMS of the feedback loop signal, grows quickly when the feedback starts running away
+    pub fn feedback_rms(&self) -> f32 {
+        self.feedback_mean_square.sqrt()
+    }
+
+    /// keeps the output level close to the input level whatever the wet, dry and feedback
+    /// settings, so comparing settings isn't biased by loudness. The level is estimated from
+    /// the settings, taking the voices as uncorrelated with the dry signal
+    pub fn set_auto_gain(&mut self, enabled: bool) {
+        self.auto_gain = enabled;
+    }
+
+    /// the linear law sums the wet and dry signals at their levels, which dips by 3 dB at a
+    /// 50/50 mix once the voices decorrelate from the dry signal. The equal-power law keeps the
+    /// power constant across the balance instead
+    pub fn set_mix_law(&mut self, mix_law: MixLaw) {
+        self.mix_law = mix_law;
+    }
+
+    /// (dry, wet) gains of the output mix
+    fn mix_gains(&self) -> (f32, f32) {
+        if self.algorithm == Algorithm::Vibrato {
+            return (0.0, 1.0);
+        }
+
+        let sum = self.wet + self.dry;
+        match self.mix_law {
+            MixLaw::Linear => {
+                if sum > 1.0 {
+                    (self.dry / sum, self.wet / sum)
+                } else {
+                    (self.dry, self.wet)
+                }
+            }
+            MixLaw::EqualPower => {
+                if sum == 0.0 {
+                    return (0.0, 0.0);
+                }
+                let angle = self.wet / sum * core::f32::consts::FRAC_PI_2;
+                let level = self.wet.max(self.dry);
+                (level * angle.cos(), level * angle.sin())
+            }
+        }
+    }
+
+    /// gain making up for the level change of the mix, see `set_auto_gain`. A feedback comb
+    /// raises the power of the voices by 1 / (1 - g^2) for a loop gain g
+    fn auto_gain_compensation(&self, dry_gain: f32, wet_gain: f32) -> f32 {
+        if !self.auto_gain {
+            return 1.0;
+        }
+
+        let loop_gain = self.loop_gain();
+        let makeup = self.feedback_makeup_gain();
+        let voices_power = makeup * makeup / (1.0 - loop_gain * loop_gain);
+        let power = dry_gain * dry_gain + wet_gain * wet_gain * voices_power;
+        if power > 1e-6 {
+            (1.0 / power.sqrt()).min(MAX_AUTO_GAIN)
+        } else {
+            1.0
+        }
+    }
+
+    fn mix(&self, x: f32, voices: f32) -> f32 {
+        let (dry_gain, wet_gain) = self.mix_gains();
+        (dry_gain * x + wet_gain * voices) * self.auto_gain_compensation(dry_gain, wet_gain)
+    }
+
+    /// tracks the correlation of the voices and, with mono protect enabled, narrows them while it
+    /// is below MONO_PROTECT_THRESHOLD. The correlation is measured before narrowing, so the
+    /// width does not chase its own result.
+    fn mono_protect(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let coeff = 1.0 / (CORRELATION_TIME_S * self.sample_rate);
+        self.correlation_lr += coeff * (left * right - self.correlation_lr);
+        self.correlation_ll += coeff * (left * left - self.correlation_ll);
+        self.correlation_rr += coeff * (right * right - self.correlation_rr);
+
+        let energy = (self.correlation_ll * self.correlation_rr).sqrt();
+        self.correlation = if energy > 1e-9 { self.correlation_lr / energy } else { 1.0 };
+
+        let target_width = if self.mono_protect && self.correlation < MONO_PROTECT_THRESHOLD {
+            ((self.correlation + 1.0) / (MONO_PROTECT_THRESHOLD + 1.0)).max(0.0)
+        } else {
+            1.0
+        };
+        // slow enough to not pump audibly
+        let width_coeff = 1.0 / (MONO_PROTECT_TIME_S * self.sample_rate);
+        self.protect_width += width_coeff * (target_width - self.protect_width);
+
+        let (mid, side) = stereo::encode_mid_side(left, right);
+        stereo::decode_mid_side(mid, side * self.protect_width)
+    }
+
+    /// correlation of the left and right voices, from -1 (out of phase) to 1 (mono)
+    pub fn correlation(&self) -> f32 {
+        self.correlation
+    }
+
+    /// effective delay times (in ms) of voice `voice`, (left, right), for the next `duration_s`
+    /// seconds, read the way live processing with the current settings reads them. Meant for
+    /// exporting the modulation as automation. Runs the modulation of a copy of the chorus, so
+    /// the live one isn't advanced. Takes the depth as set, without the dynamic depth and
+    /// sidechain, and leaves out the grains of the granular algorithm
+    pub fn render_modulation(&self, voice: usize, duration_s: f32) -> (Vec<f32>, Vec<f32>) {
+        let mut chorus = self.clone();
+        let samples = (duration_s * self.sample_rate) as usize;
+        (0..samples)
+            .map(|_| {
+                chorus.advance_lfos();
+                (
+                    chorus.left_read_position(voice, chorus.calc_depth) / self.sample_rate * 1000.0,
+                    chorus.right_read_position(voice, chorus.calc_depth) / self.sample_rate * 1000.0,
+                )
+            })
+            .unzip()
+    }
+
+    /// current modulation of each voice, -1 to 1, with chaos and the step sequencer mixed in.
+    /// The left channel voices and the right channel ones, the first `voice_count` of each are
+    /// in use
+    pub fn modulation_values(&self) -> ([f32; MAX_VOICES], [f32; MAX_VOICES]) {
+        let mut left = [0.0; MAX_VOICES];
+        let mut right = [0.0; MAX_VOICES];
+        for (i, voice) in self.voices.iter().enumerate().take(self.voice_count) {
+            left[i] = self.modulation_source(voice.left_lfo_value, voice.left_chaos_value);
+            right[i] = self.modulation_source(voice.right_lfo_value, voice.right_chaos_value);
+        }
+        (left, right)
+    }
+
+    /// current value of the first left LFO, -1 to 1
+    pub fn lfo_value(&self) -> f32 {
+        self.voices[0].left_lfo.current_value()
+    }
+
+    pub fn set_mono_protect(&mut self, enabled: bool) {
+        self.mono_protect = enabled;
+    }
+
+    /// the voices of the last processed frame, (left, right), before the wet and dry levels and
+    /// without the lows the bass preserve kept out of them. For sending the effect on its own
+    pub fn wet_output(&self) -> (f32, f32) {
+        self.wet_output
+    }
+
+    /// processes one frame of the left channel alone, use `process_stereo` for both channels
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        self.advance_frame();
+        let gain = self.fade_in_gain();
+        self.channel_left(x, gain)
+    }
+
+    /// processes one frame of the right channel alone, use `process_stereo` for both channels
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        self.advance_frame();
+        let gain = self.fade_in_gain();
+        self.channel_right(x, gain)
+    }
+
+    /// processes both channels as two independent mono chains. Unlike `process_stereo` the
+    /// channels don't feed back into each other and the voices aren't panned, widened or cross
+    /// mixed, only the LFOs stay linked by the stereo phase
+    pub fn process_dual_mono(&mut self, left: f32, right: f32) -> (f32, f32) {
+        self.advance_frame();
+        let gain = self.fade_in_gain();
+        (self.channel_left(left, gain), self.channel_right(right, gain))
+    }
+
+    /// the left channel's chain on its own, with the LFOs already advanced and the fade-in `gain`
+    /// of this frame
+    fn channel_left(&mut self, x: f32, gain: f32) -> f32 {
+        let (low, x) = self.split_left(x);
+        let feedback = self.left_feedback_buffer.get(self.feedback_delay());
+        let voices_input = self.wet_filters_left_at(FilterPosition::Pre, x);
+        let voices = self.voices_left(voices_input, feedback);
+        let voices = self.left_diffuser.process(voices);
+        let voices = self.wet_filters_left_at(FilterPosition::Post, voices) + self.noise(self.left_envelope.value());
+        let voices = voices * gain;
+        self.wet_output.0 = voices;
+        let (low, x) = self.dry_left(low, x);
+        low + self.mix(x, voices)
+    }
+
+    fn channel_right(&mut self, x: f32, gain: f32) -> f32 {
+        let (low, x) = self.split_right(x);
+        let feedback = self.right_feedback_buffer.get(self.feedback_delay());
+        let voices_input = self.wet_filters_right_at(FilterPosition::Pre, x);
+        let voices = self.voices_right(voices_input, feedback);
+        let voices = self.right_diffuser.process(voices);
+        let voices = self.wet_filters_right_at(FilterPosition::Post, voices) + self.noise(self.right_envelope.value());
+        let voices = voices * self.right_wet_polarity() * gain;
+        self.wet_output.1 = voices;
+        let (low, x) = self.dry_right(low, x);
+        low + self.mix(x, voices)
+    }
+
+    pub fn process_stereo(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let (left_low, left) = self.split_left(left);
+        let (right_low, right) = self.split_right(right);
+        self.advance_frame();
+        let (feedback_left, feedback_right) = self.feedback_taps();
+        let voices_input_left = self.wet_filters_left_at(FilterPosition::Pre, left);
+        let voices_input_right = self.wet_filters_right_at(FilterPosition::Pre, right);
+        let voices_left = self.voices_left(voices_input_left, feedback_left);
+        let voices_right = self.voices_right(voices_input_right, feedback_right);
+        let (voices_left, voices_right) = if self.algorithm == Algorithm::Dimension {
+            self.cross_mix_voices()
+        } else {
+            self.pan_voices(voices_left, voices_right)
+        };
+        let voices_left = self.left_diffuser.process(voices_left);
+        let voices_right = self.right_diffuser.process(voices_right);
+        let voices_left = self.wet_filters_left_at(FilterPosition::Post, voices_left) + self.noise(self.left_envelope.value());
+        let voices_right = self.wet_filters_right_at(FilterPosition::Post, voices_right) + self.noise(self.right_envelope.value());
+        let (voices_left, voices_right) = self.widen(voices_left, voices_right);
+        let (voices_left, voices_right) = self.mono_protect(voices_left, voices_right);
+
+        let gain = self.fade_in_gain();
+        let voices_right = voices_right * self.right_wet_polarity();
+        self.wet_output = (voices_left * gain, voices_right * gain);
+        let (left_low, left) = self.dry_left(left_low, left);
+        let (right_low, right) = self.dry_right(right_low, right);
+        (
+            left_low + self.mix(left, voices_left * gain),
+            right_low + self.mix(right, voices_right * gain),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a chorus set up like a preset, with every setting in ms
+    fn preset_chorus(sample_rate: f32, delay_ms: f32, depth_ms: f32) -> Chorus {
+        let mut chorus = Chorus::new(sample_rate, delay_ms, 0.3, depth_ms, 0.5, 0.5, 0.5);
+        chorus.set_params(sample_rate, delay_ms, 0.3, depth_ms, 0.5, 0.5, 0.5);
+        chorus.set_delay_spread(10.0);
+        chorus.set_motion_3d(5.0);
+        chorus
+    }
+
+    /// the voices' read positions in ms after `duration_ms` of silence
+    fn read_positions_ms(chorus: &mut Chorus, sample_rate: f32, duration_ms: f32) -> Vec<f32> {
+        for _ in 0..(duration_ms / 1000.0 * sample_rate).round() as usize {
+            chorus.process_stereo(0.0, 0.0);
+        }
+        chorus.voice_read_positions().iter().map(|position| position / sample_rate * 1000.0).collect()
+    }
+
+    #[test]
+    fn presets_read_the_same_delays_at_any_sample_rate() {
+        let reference = read_positions_ms(&mut preset_chorus(44100.0, 20.0, 8.0), 44100.0, 10.0);
+        for sample_rate in [48000.0, 96000.0, 192000.0] {
+            let mut chorus = preset_chorus(sample_rate, 20.0, 8.0);
+            assert!(!chorus.exceeds_buffers());
+            let positions = read_positions_ms(&mut chorus, sample_rate, 10.0);
+            for (position, expected) in positions.iter().zip(&reference) {
+                assert!((position - expected).abs() < 0.01, "{sample_rate} Hz: {position} ms, expected {expected} ms");
+            }
+        }
+    }
+
+    #[test]
+    fn longest_plugin_settings_fit_the_buffers() {
+        for sample_rate in [44100.0, 96000.0, 192000.0] {
+            let mut chorus = preset_chorus(sample_rate, 50.0, 50.0);
+            chorus.set_unipolar(true);
+            assert!(!chorus.exceeds_buffers(), "{sample_rate} Hz");
+        }
+    }
+
+    #[test]
+    fn reads_past_the_delay_lines_are_flagged() {
+        let mut chorus = preset_chorus(96000.0, 50.0, 300.0);
+        chorus.set_unipolar(true);
+        assert!(chorus.exceeds_buffers());
+
+        let mut chorus = preset_chorus(96000.0, 50.0, 0.0);
+        chorus.set_algorithm(Algorithm::Granular);
+        assert!(!chorus.exceeds_buffers());
+        // the grain window reaches past the modulated reads
+        assert!(chorus.longest_read() > chorus.voice_delay(2) + GRAIN_MS / 1000.0 * 96000.0 - 1.0);
+    }
+
+    /// index of the loudest sample of the wet impulse response, with the given oversampling
+    fn impulse_peak(oversampling: Option<FilterOrder>) -> usize {
+        let mut chorus = Chorus::new(48000.0, 10.0, 0.0, 0.0, 0.5, 1.0, 0.0);
+        chorus.set_oversampling(oversampling);
+        let response: Vec<f32> = (0..1000).map(|n| chorus.process_left(if n == 0 { 1.0 } else { 0.0 })).collect();
+        (0..response.len()).fold(0, |peak, n| if response[n].abs() > response[peak].abs() { n } else { peak })
+    }
+
+    #[test]
+    fn oversampling_delays_the_voices_by_its_latency() {
+        let peak = impulse_peak(None);
+        assert_eq!(peak, 480);
+        for order in [FilterOrder::Short, FilterOrder::Long] {
+            assert_eq!(impulse_peak(Some(order)), peak + order.latency());
+        }
+
+        let mut chorus = Chorus::new(48000.0, 10.0, 0.0, 0.0, 0.5, 1.0, 0.0);
+        assert_eq!(chorus.latency(), 0);
+        chorus.set_oversampling(Some(FilterOrder::Long));
+        assert_eq!(chorus.latency(), FilterOrder::Long.latency());
+    }
+
+    /// RMS of the wet output of a tape chorus fed a small 1 kHz tone on a large DC offset, with
+    /// the wet high-pass at `position`
+    fn tape_tone_level(position: FilterPosition) -> f32 {
+        let sample_rate = 48000.0;
+        let mut chorus = Chorus::new(sample_rate, 10.0, 0.0, 0.0, 0.5, 1.0, 0.0);
+        chorus.set_algorithm(Algorithm::Tape);
+        chorus.set_wet_highpass(200.0);
+        chorus.set_filter_position(position);
+        let output: Vec<f32> = (0..48000)
+            .map(|n| {
+                let x = 0.8 + 0.05 * (core::f32::consts::TAU * 1000.0 * n as f32 / sample_rate).sin();
+                chorus.process_left(x)
+            })
+            .collect();
+        let tail = &output[24000..];
+        (tail.iter().map(|x| x * x).sum::<f32>() / tail.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn pre_filters_shape_what_gets_saturated() {
+        // after the saturation the offset has already squashed the tone, before it the high-pass
+        // takes the offset out and the tone passes the saturation cleanly
+        let post = tape_tone_level(FilterPosition::Post);
+        let pre = tape_tone_level(FilterPosition::Pre);
+        assert!(pre > 2.0 * post, "pre {pre}, post {post}");
+        assert!((pre / (0.05 / core::f32::consts::SQRT_2) - 1.0).abs() < 0.2, "pre {pre}");
+    }
+
+    #[test]
+    fn deep_sweeps_of_short_delays_stay_in_the_buffers() {
+        let sample_rate = 48000.0;
+        for unipolar in [false, true] {
+            let mut chorus = Chorus::new(sample_rate, 0.0, 0.5, 50.0, 10.0, 1.0, 0.0);
+            chorus.set_params(sample_rate, 0.0, 0.5, 50.0, 10.0, 1.0, 0.0);
+            chorus.set_unipolar(unipolar);
+            chorus.set_voice_count(MAX_VOICES);
+            chorus.set_motion_3d(5.0);
+            for n in 0..sample_rate as usize {
+                let x = (n as f32 * 0.05).sin();
+                let (left, right) = chorus.process_stereo(x, x);
+                assert!(left.is_finite() && right.is_finite());
+                for position in chorus.voice_read_positions() {
+                    assert!(*position >= 0.0 && *position <= chorus.left_delay.longest_delay(), "read at {position}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn drifting_grains_wrap_without_clicks() {
+        let sample_rate = 48000.0;
+        let mut chorus = Chorus::new(sample_rate, 15.0, 0.0, 0.0, 0.5, 1.0, 0.0);
+        chorus.set_algorithm(Algorithm::Granular);
+        chorus.set_grain_detune(MAX_GRAIN_DETUNE_CENTS);
+        // at the widest detune the grains' read positions ramp through the whole window about
+        // every 1.4 s
+        let output: Vec<f32> = (0..3 * sample_rate as usize)
+            .map(|n| chorus.process_left(0.5 * (core::f32::consts::TAU * 440.0 * n as f32 / sample_rate).sin()))
+            .collect();
+        // a steady tone bends smoothly, a wrap without the crossfade would kink it by about
+        // its amplitude
+        let step = core::f32::consts::TAU * 440.0 / sample_rate;
+        for (n, window) in output[4800..].windows(3).enumerate() {
+            let curvature = (window[0] - 2.0 * window[1] + window[2]).abs();
+            assert!(curvature < 0.5 * step * step * 4.0, "kink of {curvature} at sample {}", n + 4800);
+        }
+    }
+
+    /// RMS of a 500 Hz tone through an even mix with a 1 ms delay, which puts it in the comb's
+    /// first notch. The chorus' own output and the mix the dry input would make with its voices
+    /// without being delayed, (compensated, uncompensated)
+    fn comb_notch_levels(oversampling: Option<FilterOrder>) -> (f32, f32) {
+        let sample_rate = 48000.0;
+        let mut chorus = Chorus::new(sample_rate, 1.0, 0.0, 0.0, 0.5, 0.5, 0.5);
+        chorus.set_params(sample_rate, 1.0, 0.0, 0.0, 0.5, 0.5, 0.5);
+        chorus.set_oversampling(oversampling);
+        let (mut compensated, mut uncompensated) = (0.0, 0.0);
+        for n in 0..sample_rate as usize {
+            let x = (core::f32::consts::TAU * 500.0 * n as f32 / sample_rate).sin();
+            let output = chorus.process_left(x);
+            if n >= 4800 {
+                compensated += output * output;
+                let plain = 0.5 * x + 0.5 * chorus.wet_output().0;
+                uncompensated += plain * plain;
+            }
+        }
+        let len = sample_rate - 4800.0;
+        ((compensated / len).sqrt(), (uncompensated / len).sqrt())
+    }
+
+    #[test]
+    fn dry_delay_keeps_the_comb_notches_with_oversampling() {
+        let (plain, _) = comb_notch_levels(None);
+        let (compensated, uncompensated) = comb_notch_levels(Some(FilterOrder::Long));
+        assert!(plain < 0.01, "{plain}");
+        assert!(compensated < 0.01, "{compensated}");
+        // the latency moves the voices off the dry signal and fills the notch back in
+        assert!(uncompensated > 0.3, "{uncompensated}");
+
+        let mut chorus = Chorus::new(48000.0, 1.0, 0.0, 0.0, 0.5, 0.5, 0.5);
+        chorus.set_oversampling(Some(FilterOrder::Short));
+        assert_eq!(chorus.dry_delay(), FilterOrder::Short.latency());
+    }
+
+    #[test]
+    fn rendered_modulation_matches_live_processing() {
+        let sample_rate = 48000.0;
+        let mut chorus = preset_chorus(sample_rate, 2.0, 6.0);
+        chorus.set_voice_count(4);
+        chorus.set_chaos_amount(0.4);
+        chorus.set_rate_mod_amount(0.5);
+        let voice = 2;
+        let (left, right) = chorus.render_modulation(voice, 0.2);
+        assert_eq!(left.len(), 9600);
+
+        for (left, right) in left.iter().zip(&right) {
+            chorus.process_stereo(0.1, -0.1);
+            let positions = chorus.voice_read_positions();
+            assert_eq!(*left, positions[voice] / sample_rate * 1000.0);
+            assert_eq!(*right, positions[chorus.voice_count() + voice] / sample_rate * 1000.0);
+        }
+    }
+
+    #[test]
+    fn pathological_params_keep_the_output_finite() {
+        let sample_rate = 48000.0;
+        // sample rate, delay, feedback, depth, rate, wet, dry
+        let cases = [
+            (sample_rate, -20.0, 0.5, 5.0, 0.5, 0.5, 0.5),
+            (sample_rate, 15.0, 1.0, 5.0, 0.5, 1.0, 0.0),
+            (sample_rate, 15.0, 50.0, 5.0, 0.5, 1.0, 0.0),
+            (sample_rate, 15.0, -3.0, 5.0, 0.5, 0.5, 0.5),
+            (sample_rate, f32::NAN, f32::NAN, f32::NAN, f32::NAN, f32::NAN, f32::NAN),
+            (sample_rate, f32::INFINITY, f32::INFINITY, f32::INFINITY, f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            (sample_rate, f32::NEG_INFINITY, 0.9, f32::NEG_INFINITY, f32::NEG_INFINITY, -1.0, 2.0),
+            (sample_rate, 1e9, 0.9, 1e9, 1e9, 0.5, 0.5),
+            (0.0, 15.0, 0.5, 5.0, 0.5, 0.5, 0.5),
+            (f32::NAN, 15.0, 0.5, 5.0, 0.5, 0.5, 0.5),
+            (-44100.0, 15.0, 0.5, 5.0, 0.5, 0.5, 0.5),
+        ];
+        for (case, &(rate_hz, delay, feedback, depth, rate, wet, dry)) in cases.iter().enumerate() {
+            let mut chorus = preset_chorus(sample_rate, 15.0, 5.0);
+            chorus.allocate_voice_feedback(sample_rate);
+            for per_voice_feedback in [false, true] {
+                chorus.set_per_voice_feedback(per_voice_feedback);
+                chorus.set_params(rate_hz, delay, feedback, depth, rate, wet, dry);
+                for n in 0..sample_rate as usize {
+                    let x = (n as f32 * 0.03).sin() * 0.5;
+                    let (left, right) = chorus.process_stereo(x, -x);
+                    assert!(left.is_finite() && right.is_finite(), "case {case}, sample {n}: {left} {right}");
+                }
+            }
+
+            // and the engine carries on normally once the values are sane again
+            chorus.set_params(sample_rate, 15.0, 0.3, 5.0, 0.5, 0.5, 0.5);
+            let peak = (0..4800).fold(0.0f32, |peak, n| {
+                let x = (n as f32 * 0.03).sin() * 0.5;
+                let (left, right) = chorus.process_stereo(x, x);
+                peak.max(left.abs()).max(right.abs())
+            });
+            assert!(peak.is_finite() && peak > 0.1 && peak < 2.0, "case {case}: {peak}");
+        }
+    }
+
+    #[test]
+    fn switching_waveforms_mid_tone_glides_the_modulation() {
+        let sample_rate = 48000.0;
+        let depth_ms = 5.0;
+        let mut chorus = Chorus::new(sample_rate, 15.0, 0.0, depth_ms, 0.5, 0.5, 0.5);
+        chorus.set_params(sample_rate, 15.0, 0.0, depth_ms, 0.5, 0.5, 0.5);
+        chorus.set_voice_count(4);
+
+        // switches every quarter second, between shapes that don't jump on their own
+        let waveforms = [
+            lfo::Waveform::Triangle,
+            lfo::Waveform::Random,
+            lfo::Waveform::Drift,
+            lfo::Waveform::Custom,
+            lfo::Waveform::Random,
+            lfo::Waveform::Sine,
+        ];
+        chorus.process_stereo(0.0, 0.0);
+        let mut previous = chorus.voice_read_positions().to_vec();
+        let mut largest_step = 0.0f32;
+        for n in 0..(waveforms.len() + 1) * 12000 {
+            if n % 12000 == 0 && n > 0 {
+                chorus.set_waveform(waveforms[n / 12000 - 1]);
+            }
+            let x = (n as f32 * 0.03).sin() * 0.5;
+            chorus.process_stereo(x, x);
+            let positions = chorus.voice_read_positions();
+            for (position, previous) in positions.iter().zip(&previous) {
+                largest_step = largest_step.max((position - previous).abs());
+            }
+            previous = positions.to_vec();
+        }
+
+        // a jump between shapes moves a read by up to the full sweep, twice the depth, at once.
+        // The crossfade spreads that over its length
+        let sweep = 2.0 * depth_ms / 1000.0 * sample_rate;
+        let crossfade = lfo::WAVEFORM_CROSSFADE_MS / 1000.0 * sample_rate;
+        assert!(largest_step < 1.5 * sweep / crossfade, "{largest_step} samples against a sweep of {sweep}");
+    }
+
+    #[test]
+    fn voices_ramp_in_after_a_reset() {
+        let sample_rate = 48000.0;
+        // a delay well within the fade, so the voices come in while it is still running
+        let mut reference = Chorus::new(sample_rate, 1.0, 0.0, 0.0, 0.5, 1.0, 0.0);
+        reference.set_params(sample_rate, 1.0, 0.0, 0.0, 0.5, 1.0, 0.0);
+        let mut chorus = reference.clone();
+        chorus.reset();
+
+        let fade_length = (FADE_IN_MS / 1000.0 * sample_rate) as usize;
+        let mut previous_gain = 0.0;
+        let mut first_gain = None;
+        for n in 0..2 * fade_length {
+            let x = 0.5 + 0.2 * (n as f32 * 0.01).sin();
+            let (level, _) = reference.process_stereo(x, x);
+            let (faded, _) = chorus.process_stereo(x, x);
+            // silent until the voices arrive
+            if level.abs() < 0.1 {
+                assert_eq!(faded, 0.0, "sample {n}");
+                continue;
+            }
+            let gain = faded / level;
+            first_gain.get_or_insert(gain);
+            if n < fade_length {
+                // one even step per sample, no jump where the voices come in
+                assert!(gain > previous_gain, "sample {n}: {gain} after {previous_gain}");
+                assert!(gain < (n + 2) as f32 / fade_length as f32, "sample {n}: {gain}");
+            } else {
+                assert!((gain - 1.0).abs() < 1e-5, "sample {n}: {gain}");
+            }
+            previous_gain = gain;
+        }
+        assert!(first_gain.unwrap() < 0.5, "{first_gain:?}");
+    }
+
+    /// RMS of the right output while a 5 kHz tone plays on the left alone, with the feedback
+    /// crossing over by `cross`, and the left output's RMS
+    fn crossed_levels(cross: f32, cross_lowpass_hz: Option<f32>) -> (f32, f32) {
+        let sample_rate = 48000.0;
+        let mut chorus = Chorus::new(sample_rate, 5.0, 0.8, 0.0, 0.5, 1.0, 0.0);
+        chorus.set_params(sample_rate, 5.0, 0.8, 0.0, 0.5, 1.0, 0.0);
+        chorus.set_cross_feedback(cross);
+        if let Some(lowpass_hz) = cross_lowpass_hz {
+            chorus.set_cross_feedback_lowpass(lowpass_hz);
+        }
+        let (mut left_energy, mut right_energy) = (0.0, 0.0);
+        for n in 0..24000 {
+            let x = (core::f32::consts::TAU * 5000.0 * n as f32 / sample_rate).sin() * 0.5;
+            let (left, right) = chorus.process_stereo(x, 0.0);
+            left_energy += left * left;
+            right_energy += right * right;
+        }
+        ((left_energy / 24000.0).sqrt(), (right_energy / 24000.0).sqrt())
+    }
+
+    #[test]
+    fn cross_feedback_lowpass_darkens_only_the_crossed_repeats() {
+        // at the top of its range it leaves the crossed repeats to the main damping
+        let (_, crossed) = crossed_levels(1.0, None);
+        assert_eq!(crossed_levels(1.0, Some(FEEDBACK_LOWPASS_OFF_HZ)).1, crossed);
+
+        let (_, damped) = crossed_levels(1.0, Some(1000.0));
+        assert!(damped < crossed * 0.3, "{damped} against {crossed}");
+
+        // the feedback staying in its channel doesn't go through it
+        let (own, _) = crossed_levels(0.0, None);
+        assert_eq!(crossed_levels(0.0, Some(1000.0)).0, own);
+    }
+
+    /// the left feedback loop's state in a 10 ms comb at 0.99 feedback, ringing out for
+    /// `decay_s` after a short burst scaled by `level`. The burst is quantized, so scaling it by
+    /// 3 is exact and only the rounding inside the loop differs between levels
+    fn ring_out(high_precision: bool, level: f32, decay_s: f32) -> Vec<f64> {
+        let sample_rate = 48000.0;
+        let mut chorus = Chorus::new(sample_rate, 10.0, 0.99, 0.0, 0.5, 1.0, 0.0);
+        chorus.set_params(sample_rate, 10.0, 0.99, 0.0, 0.5, 1.0, 0.0);
+        chorus.set_high_precision(high_precision);
+        let burst = 4800;
+        (0..burst + (decay_s * sample_rate) as usize)
+            .map(|n| {
+                let x = if n < burst { ((n as f32 * 0.0731).sin() * 1024.0).round() / 1024.0 * level } else { 0.0 };
+                chorus.process_stereo(x, x);
+                chorus.left_feedback_buffer.get(0)
+            })
+            .collect()
+    }
+
+    /// rounding noise in the loop over the last 0.1 s of a 6 s decay, relative to the signal
+    fn loop_noise_floor(high_precision: bool) -> f64 {
+        let signal = ring_out(high_precision, 1.0, 6.0);
+        let scaled = ring_out(high_precision, 3.0, 6.0);
+        let tail = signal.len() - 4800..signal.len();
+        let energy = tail.clone().map(|n| signal[n] * signal[n]).sum::<f64>();
+        let noise = tail.map(|n| (scaled[n] / 3.0 - signal[n]).powi(2)).sum::<f64>();
+        (noise / energy).sqrt()
+    }
+
+    #[test]
+    fn high_precision_lowers_the_noise_floor_of_long_decays() {
+        let rounded = loop_noise_floor(false);
+        let precise = loop_noise_floor(true);
+        // measured 6.5e-7 (-124 dB) and 1.9e-15 (-294 dB)
+        assert!(rounded > 1e-7 && rounded < 1e-5, "{rounded}");
+        assert!(precise < 1e-13, "{precise}");
+    }
+
+    /// a drum-like hit every quarter second, a fast decaying burst of a bright tone
+    fn hits(n: usize) -> f32 {
+        let t = (n % 12000) as f32 / 48000.0;
+        (core::f32::consts::TAU * 3100.0 * t).sin() * (-t / 0.02).exp() * 0.8
+    }
+
+    /// how far the output strays from the dry signal alone in the first 5 ms of each hit
+    fn attack_deviation(transient_duck: f32) -> f32 {
+        let sample_rate = 48000.0;
+        let mut chorus = Chorus::new(sample_rate, 1.0, 0.0, 0.5, 0.5, 0.5, 0.5);
+        chorus.set_params(sample_rate, 1.0, 0.0, 0.5, 0.5, 0.5, 0.5);
+        chorus.set_transient_duck(transient_duck);
+        let mut dry_only = chorus.clone();
+        dry_only.set_params(sample_rate, 1.0, 0.0, 0.5, 0.5, 0.0, 0.5);
+
+        let mut deviation = 0.0;
+        for n in 0..48000 {
+            let (output, _) = chorus.process_stereo(hits(n), hits(n));
+            let (dry, _) = dry_only.process_stereo(hits(n), hits(n));
+            if n % 12000 < 240 {
+                deviation += (output - dry).powi(2);
+            }
+        }
+        deviation.sqrt()
+    }
+
+    #[test]
+    fn transient_duck_keeps_the_dry_attack() {
+        let washed = attack_deviation(0.0);
+        let ducked = attack_deviation(1.0);
+        assert!(ducked < washed * 0.3, "{ducked} against {washed}");
+    }
+
+    #[test]
+    fn unipolar_sweeps_keep_the_3d_motion_above_the_base_delay() {
+        let sample_rate = 48000.0;
+        let mut chorus = Chorus::new(sample_rate, 10.0, 0.0, 4.0, 2.0, 0.5, 0.5);
+        chorus.set_params(sample_rate, 10.0, 0.0, 4.0, 2.0, 0.5, 0.5);
+        chorus.set_motion_3d(5.0);
+        chorus.set_voice_count(3);
+        chorus.set_unipolar(true);
+        // so only the 3D motion tells the channels apart
+        chorus.set_stereo_phase(0.0);
+        let base_delay = 10.0 / 1000.0 * sample_rate;
+        let mut lowest = f32::MAX;
+        let mut widest = 0.0f32;
+        for _ in 0..24000 {
+            chorus.process_stereo(0.0, 0.0);
+            let positions = chorus.voice_read_positions();
+            let (left, right) = positions.split_at(chorus.voice_count());
+            lowest = positions.iter().fold(lowest, |lowest, &position| lowest.min(position));
+            widest = left.iter().zip(right).fold(widest, |widest, (left, right)| widest.max((left - right).abs()));
+        }
+        assert!(lowest >= base_delay - 1e-3, "{lowest} below {base_delay}");
+        // the channels still move apart by the full 3D amount
+        let motion = 5.0 / 1000.0 * sample_rate;
+        assert!(widest > 0.9 * motion && widest <= motion + 1e-3, "{widest} against {motion}");
+    }
+
+    #[test]
+    fn restarting_the_modulation_repeats_it_exactly() {
+        let sample_rate = 48000.0;
+        let modulated = || {
+            let mut chorus = Chorus::new(sample_rate, 10.0, 0.0, 4.0, 1.5, 0.5, 0.5);
+            chorus.set_params(sample_rate, 10.0, 0.0, 4.0, 1.5, 0.5, 0.5);
+            chorus.set_voice_count(3);
+            chorus.set_waveform(lfo::Waveform::Drift);
+            chorus.set_lfo_humanize(0.5);
+            chorus.set_rate_mod_amount(0.5);
+            chorus.set_chaos_amount(0.5);
+            chorus
+        };
+        // two plays that ran for different lengths before the transport started again
+        let mut first = modulated();
+        let mut second = modulated();
+        read_positions_ms(&mut first, sample_rate, 300.0);
+        read_positions_ms(&mut second, sample_rate, 1700.0);
+        first.restart_modulation(90.0);
+        second.restart_modulation(90.0);
+        for _ in 0..48000 {
+            first.process_stereo(0.0, 0.0);
+            second.process_stereo(0.0, 0.0);
+            assert_eq!(first.voice_read_positions(), second.voice_read_positions());
+        }
+    }
+
+    #[test]
+    fn restarting_at_the_saved_phase_resumes_the_lfos() {
+        let sample_rate = 48000.0;
+        let mut playing = preset_chorus(sample_rate, 10.0, 4.0);
+        playing.set_voice_count(3);
+        read_positions_ms(&mut playing, sample_rate, 700.0);
+        // a reopened project, restored from the phase saved with the last block
+        let mut reopened = preset_chorus(sample_rate, 10.0, 4.0);
+        reopened.set_voice_count(3);
+        reopened.restart_modulation(playing.lfo_phase());
+        // only the first voice's phase is saved, the others restart at their offsets from it
+        for _ in 0..4800 {
+            let playing = read_positions_ms(&mut playing, sample_rate, 0.1)[0];
+            let reopened = read_positions_ms(&mut reopened, sample_rate, 0.1)[0];
+            assert!((playing - reopened).abs() < 1e-3, "{playing} against {reopened}");
+        }
+    }
+
+    #[test]
+    fn every_voice_follows_its_own_chaos() {
+        let sample_rate = 48000.0;
+        let mut chorus = Chorus::new(sample_rate, 10.0, 0.0, 4.0, 1.0, 0.5, 0.5);
+        chorus.set_params(sample_rate, 10.0, 0.0, 4.0, 1.0, 0.5, 0.5);
+        chorus.set_voice_count(MAX_VOICES);
+        chorus.set_chaos_amount(1.0);
+        chorus.set_chaos_speed(5.0);
+        // the furthest each pair of the left and right modulation signals gets apart
+        let mut differences = [[0.0f32; 2 * MAX_VOICES]; 2 * MAX_VOICES];
+        for _ in 0..48000 {
+            chorus.process_stereo(0.0, 0.0);
+            let (left, right) = chorus.modulation_values();
+            let values: Vec<f32> = left.iter().chain(&right).copied().collect();
+            for (i, a) in values.iter().enumerate() {
+                for (j, b) in values.iter().enumerate() {
+                    differences[i][j] = differences[i][j].max((a - b).abs());
+                }
+            }
+        }
+        for (i, row) in differences.iter().enumerate() {
+            for (j, difference) in row.iter().enumerate().filter(|(j, _)| *j != i) {
+                assert!(*difference > 0.2, "signals {i} and {j} apart by at most {difference}");
+            }
+        }
+    }
+
+    /// largest second difference of a sine through the chorus, before and after switching from
+    /// `from` voices to `to`. A steady sine barely bends from one sample to the next, a voice
+    /// coming in or dropping out abruptly kinks it
+    fn voice_count_kinks(from: usize, to: usize) -> (f32, f32) {
+        let sample_rate = 48000.0;
+        let mut chorus = Chorus::new(sample_rate, 10.0, 0.0, 1.0, 0.5, 1.0, 0.0);
+        chorus.set_params(sample_rate, 10.0, 0.0, 1.0, 0.5, 1.0, 0.0);
+        chorus.set_delay_spread(10.0);
+        chorus.set_voice_count(from);
+        let mut previous = (0.0, 0.0);
+        let mut kinks = (0.0f32, 0.0f32);
+        for n in 0..24000 {
+            if n == 12000 {
+                chorus.set_voice_count(to);
+            }
+            let x = (core::f32::consts::TAU * 200.0 * n as f32 / sample_rate).sin();
+            let (y, _) = chorus.process_stereo(x, x);
+            // past the fade-in after the start and the sweep settling in
+            if n > 2400 {
+                let kink = (y - 2.0 * previous.0 + previous.1).abs();
+                if n < 12000 {
+                    kinks.0 = kinks.0.max(kink);
+                } else {
+                    kinks.1 = kinks.1.max(kink);
+                }
+            }
+            previous = (y, previous.0);
+        }
+        kinks
+    }
+
+    #[test]
+    fn changing_the_voice_count_fades_the_voices() {
+        for (from, to) in [(3, 5), (5, 2), (1, MAX_VOICES)] {
+            let (before, after) = voice_count_kinks(from, to);
+            // the level may rise or fall with the count, the kink of a click is far larger
+            assert!(after < 5.0 * before, "{from} to {to} voices: {after} against {before}");
+        }
+    }
+
+    #[test]
+    fn through_zero_latency_ignores_the_delay_knob() {
+        let sample_rate = 48000.0;
+        let latency = through_zero_delay(sample_rate);
+        for delay_ms in [0.5, 2.0, 5.0] {
+            let mut chorus = Chorus::new(sample_rate, delay_ms, 0.0, 0.0, 0.5, 0.5, 0.5);
+            chorus.set_algorithm(Algorithm::Flanger);
+            chorus.set_through_zero(true);
+            chorus.set_params(sample_rate, delay_ms, 0.0, 0.0, 0.5, 0.5, 0.5);
+            assert_eq!(chorus.latency(), latency, "{delay_ms} ms");
+            // past the dry path's crossfade to the new latency
+            read_positions_ms(&mut chorus, sample_rate, 2.0 * DRY_CROSSFADE_MS);
+
+            // without a sweep the voice sits right on the dry signal, so they add up to a single
+            // impulse instead of a comb
+            let output: Vec<f32> = (0..2 * latency).map(|n| chorus.process_stereo(if n == 0 { 1.0 } else { 0.0 }, 0.0).0).collect();
+            for (n, y) in output.iter().enumerate() {
+                let expected = if n == latency { 1.0 } else { 0.0 };
+                assert!((y - expected).abs() < 1e-3, "{delay_ms} ms, sample {n}: {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn switching_the_latency_crossfades_the_dry_path() {
+        let sample_rate = 48000.0;
+        let mut chorus = Chorus::new(sample_rate, 10.0, 0.0, 1.0, 0.5, 0.0, 1.0);
+        chorus.set_params(sample_rate, 10.0, 0.0, 1.0, 0.5, 0.0, 1.0);
+        let mut previous = (0.0, 0.0);
+        let mut kinks = (0.0f32, 0.0f32);
+        for n in 0..9600 {
+            if n == 4800 {
+                chorus.set_oversampling(Some(FilterOrder::Long));
+            }
+            let x = (core::f32::consts::TAU * 200.0 * n as f32 / sample_rate).sin();
+            let (y, _) = chorus.process_stereo(x, x);
+            if n > 2 {
+                let kink = (y - 2.0 * previous.0 + previous.1).abs();
+                if n < 4800 {
+                    kinks.0 = kinks.0.max(kink);
+                } else {
+                    kinks.1 = kinks.1.max(kink);
+                }
+            }
+            previous = (y, previous.0);
+        }
+        // the crossfade between the two reads bends the sine a little at either end, a jump
+        // kinks it by a hundred times more
+        assert!(kinks.1 < 5.0 * kinks.0, "{} against {}", kinks.1, kinks.0);
+    }
 
-        right_out
+    #[test]
+    fn voice_feedback_rings_once_allocated() {
+        let sample_rate = 48000.0;
+        // energy of an impulse's tail with per-voice feedback, long after the voices' first pass
+        let tail = |feedback: f32| {
+            let mut chorus = preset_chorus(sample_rate, 15.0, 2.0);
+            assert!(!chorus.voice_feedback_allocated());
+            chorus.allocate_voice_feedback(sample_rate);
+            assert!(chorus.voice_feedback_allocated());
+            chorus.set_per_voice_feedback(true);
+            chorus.set_params(sample_rate, 15.0, feedback, 2.0, 0.5, 1.0, 0.0);
+            (0..sample_rate as usize / 4)
+                .map(|n| chorus.process_stereo(if n == 0 { 1.0 } else { 0.0 }, 0.0))
+                .skip(sample_rate as usize / 10)
+                .map(|(l, r)| l * l + r * r)
+                .sum::<f32>()
+        };
+        let fed_back = tail(0.8);
+        let dry_tail = tail(0.0);
+        assert!(fed_back > 1e-4, "{fed_back}");
+        assert!(dry_tail < fed_back / 1000.0, "{dry_tail} against {fed_back}");
     }
 }
\ No newline at end of file
@@ -1,27 +1,176 @@
-use std::collections::VecDeque;
+use crate::ring_buffer::RingBuffer;
 
-const MAX_DELAY: usize = 3; // 3 seconds at 44100Hz
+// longest delay the lines hold. The chorus' base delay of up to 60 ms plus the 10 ms voice
+// spread, swept by a unipolar depth of up to twice the delay and the 3D motion, stays below it
+const MAX_DELAY_MS: f32 = 200.0;
+// length of the crossfade between the old and the new read position when the base delay changes
+const CROSSFADE_MS: f32 = 10.0;
+// length of the sinc interpolation kernel, half of it on either side of the read position
+const SINC_TAPS: usize = 8;
 
+/// `MAX_DELAY_MS` and the samples the interpolation reads past the read position
+fn buffer_len(sample_rate: usize) -> usize {
+    (MAX_DELAY_MS / 1000.0 * sample_rate as f32).ceil() as usize + SINC_TAPS
+}
+
+/// how the delay lines read between samples, see `Delay::set_interpolation`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    // the closest sample, steps audibly under slow sweeps but costs nothing
+    Nearest,
+    // a straight line between the two neighbors, cheap but dulls the highs as it sweeps
+    Linear,
+    // 4-point cubic Hermite, keeps much more of the highs and distorts less under deep sweeps
+    Hermite,
+    // first-order all-pass, about as cheap as linear but with a flat frequency response. Its
+    // state smears fast jumps of the delay, so it suits shallow sweeps best
+    AllPass,
+    // a Hann windowed sinc over `SINC_TAPS` samples, close to ideal band limited reads at the
+    // highest cost
+    Sinc,
+}
+
+/// the sample `delay` samples back in `buffer`, linearly interpolated between its neighbors
+fn read_linear(buffer: &RingBuffer<f64>, delay: f32) -> f64 {
+    let delay = delay.max(0.0);
+    let index = delay as usize;
+    let frac = (delay - index as f32) as f64;
+    buffer.get(index) + (buffer.get(index + 1) - buffer.get(index)) * frac
+}
+
+/// the sample `delay` samples back in `buffer`, band limited with a Hann windowed sinc kernel
+/// centered on the read position. The kernel is divided by the sum of its weights, so DC comes
+/// through at unity gain whatever the fraction. Taps newer than the newest sample repeat it
+fn read_sinc(buffer: &RingBuffer<f64>, delay: f32) -> f64 {
+    let delay = delay.max(0.0);
+    let index = delay as usize;
+    let frac = (delay - index as f32) as f64;
+    // the kernel is 1 at the read position and 0 at every other whole sample
+    if frac == 0.0 {
+        return buffer.get(index);
+    }
+
+    let half = (SINC_TAPS / 2) as isize;
+    let mut y = 0.0;
+    let mut weights = 0.0;
+    for offset in 1 - half..=half {
+        // how far the tap is from the read position, within -half..half
+        let distance = offset as f64 - frac;
+        let x = core::f64::consts::PI * distance;
+        let window = 0.5 + 0.5 * (x / half as f64).cos();
+        let weight = x.sin() / x * window;
+        let tap = (index as isize + offset).max(0) as usize;
+        y += buffer.get(tap) * weight;
+        weights += weight;
+    }
+    y / weights
+}
+
+/// the sample `delay` samples back in `buffer`, through a first-order all-pass delaying the
+/// integer part's sample by the fraction. `state` is the all-pass output of the previous read.
+/// The fraction is kept within 0.618..1.618 when the delay allows it, which keeps the
+/// coefficient small and the filter quick to settle
+fn read_allpass(buffer: &RingBuffer<f64>, delay: f32, state: &mut f64) -> f64 {
+    let delay = delay.max(0.0);
+    let mut index = delay as usize;
+    let mut frac = (delay - index as f32) as f64;
+    if frac < 0.618 && index > 0 {
+        index -= 1;
+        frac += 1.0;
+    }
+    let coefficient = (1.0 - frac) / (1.0 + frac);
+    let y = coefficient * (buffer.get(index) - *state) + buffer.get(index + 1);
+    *state = y;
+    y
+}
+
+/// the sample `delay` samples back in `buffer`, interpolated with a cubic Hermite spline through
+/// the two neighbors on each side
+fn read_hermite(buffer: &RingBuffer<f64>, delay: f32) -> f64 {
+    let delay = delay.max(0.0);
+    let index = delay as usize;
+    let t = (delay - index as f32) as f64;
+    // the newer neighbor is a sample less delayed, a zero delay has none and repeats itself
+    let x0 = buffer.get(index.saturating_sub(1));
+    let x1 = buffer.get(index);
+    let x2 = buffer.get(index + 1);
+    let x3 = buffer.get(index + 2);
+
+    let c1 = 0.5 * (x2 - x0);
+    let c2 = x0 - 2.5 * x1 + 2.0 * x2 - 0.5 * x3;
+    let c3 = 0.5 * (x3 - x0) + 1.5 * (x1 - x2);
+    ((c3 * t + c2) * t + c1) * t + x1
+}
+
+/// one read head of a `Delay`. The voices reading the same input share a delay line, which
+/// stores the input once, and keep a tap each for what is their own: the output their feedback
+/// loop regenerates from and the state of the all-pass interpolation
+#[derive(Clone)]
+pub struct Tap {
+    // the tap's past outputs, only allocated by `allocate_feedback` and only written while the
+    // delay line feeds back
+    y_buffer: RingBuffer<f64>,
+    // outputs of the all-pass reads of the input and of the tap's output, see `read_allpass`
+    x_allpass_state: f64,
+    y_allpass_state: f64,
+}
+
+impl Tap {
+    /// a tap without a feedback loop of its own, see `allocate_feedback`
+    pub fn new() -> Self {
+        Self {
+            y_buffer: RingBuffer::new(0),
+            x_allpass_state: 0.0,
+            y_allpass_state: 0.0,
+        }
+    }
+
+    /// allocates the buffer the tap's own feedback loop runs through, this is not realtime
+    /// safe. Until then the tap ignores the delay line's feedback
+    pub fn allocate_feedback(&mut self, sample_rate: usize) {
+        self.y_buffer.resize(buffer_len(sample_rate));
+    }
+
+    pub fn has_feedback(&self) -> bool {
+        self.y_buffer.len() > 1
+    }
+
+    /// reallocates the feedback buffer for the new sample rate if there is one
+    pub fn resize_buffers(&mut self, sample_rate: usize) {
+        if self.has_feedback() {
+            self.allocate_feedback(sample_rate);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.y_buffer.clear();
+        self.x_allpass_state = 0.0;
+        self.y_allpass_state = 0.0;
+    }
+}
+
+/// a delay line any number of `Tap`s read from. Every frame the input is `push`ed once, then
+/// each tap is read with `read_tap` or `read_taps`. The samples are kept in f64, see
+/// `set_high_precision`
 #[derive(Clone)]
 pub struct Delay {
-    x_buffer: Box<VecDeque<f32>>,
-    y_buffer: Box<VecDeque<f32>>,
-    
-    pub delay: usize,
+    x_buffer: RingBuffer<f64>,
+    high_precision: bool,
+
     pub feedback: f32,
+    // base delays in samples, see `set_delay`. The reads are shifted by how far the head's base
+    // is from the target, the old head's only while crossfading
+    delay: f32,
+    target_delay: f32,
+    previous_delay: f32,
+    crossfade_remaining: usize,
+    crossfade_length: usize,
+    interpolation: Interpolation,
 }
 
 impl Delay {
     pub fn new(sample_rate: usize, delay: usize, feedback: f32) -> Self {
 
-        let mut xbuf: Box<VecDeque<f32>> = Box::new(VecDeque::with_capacity(MAX_DELAY * sample_rate));
-        let mut ybuf: Box<VecDeque<f32>> = Box::new(VecDeque::with_capacity(MAX_DELAY * sample_rate));
-        // fill with zeroes
-        for _ in 0..MAX_DELAY * sample_rate {
-            xbuf.push_front(0.0);
-            ybuf.push_front(0.0);
-        }
-
         let feedback = if feedback > 1.0 {
             1.0
         } else if feedback < 0.0 {
@@ -31,34 +180,190 @@ impl Delay {
         };
 
         Self {
-            x_buffer: xbuf,
-            y_buffer: ybuf,
-            delay,
+            x_buffer: RingBuffer::new(buffer_len(sample_rate)),
+            high_precision: false,
             feedback: feedback,
+            delay: delay as f32,
+            target_delay: delay as f32,
+            previous_delay: delay as f32,
+            crossfade_remaining: 0,
+            crossfade_length: Self::crossfade_length(sample_rate),
+            interpolation: Interpolation::Linear,
         }
     }
 
+    fn crossfade_length(sample_rate: usize) -> usize {
+        ((CROSSFADE_MS / 1000.0 * sample_rate as f32) as usize).max(1)
+    }
+
     pub fn resize_buffers(&mut self, sample_rate: usize) {
-        self.x_buffer = Box::new(VecDeque::with_capacity(MAX_DELAY * sample_rate));
-        self.y_buffer = Box::new(VecDeque::with_capacity(MAX_DELAY * sample_rate));
-        for _ in 0..MAX_DELAY * sample_rate {
-            self.x_buffer.push_front(0.0);
-            self.y_buffer.push_front(0.0);
+        self.x_buffer.resize(buffer_len(sample_rate));
+        self.crossfade_length = Self::crossfade_length(sample_rate);
+    }
+
+    /// clears the stored input, the taps are cleared on their own
+    pub fn clear(&mut self) {
+        self.x_buffer.clear();
+        self.delay = self.target_delay;
+        self.crossfade_remaining = 0;
+    }
+
+    /// the base delay in samples the delays given to `read_tap` are modulated around. When it
+    /// changes, the reads crossfade from the old base to the new one over `CROSSFADE_MS`
+    /// instead of jumping, which would click or chirp. A change during a crossfade is picked up
+    /// once it is done
+    pub fn set_delay(&mut self, delay: f32) {
+        self.target_delay = delay;
+    }
+
+    /// starts a crossfade towards the target base delay if it changed and none is running
+    fn update_heads(&mut self) {
+        if self.crossfade_remaining == 0 && self.delay != self.target_delay {
+            self.previous_delay = self.delay;
+            self.delay = self.target_delay;
+            self.crossfade_remaining = self.crossfade_length;
+        }
+    }
+
+    /// `delay` moved from the target base to the base the new head is at
+    fn head_delay(&self, delay: f32) -> f32 {
+        self.clamp_delay(delay + self.delay - self.target_delay)
+    }
+
+    /// `delay` kept within what the buffers hold, so a read past `MAX_DELAY_MS` sticks to the
+    /// oldest sample instead of wrapping around to the newest ones
+    fn clamp_delay(&self, delay: f32) -> f32 {
+        delay.min(self.longest_delay())
+    }
+
+    /// longest delay in samples the buffers can be read at without clamping
+    pub fn longest_delay(&self) -> f32 {
+        (self.x_buffer.len() - SINC_TAPS) as f32
+    }
+
+    /// `new_read`, the new head's read of `buffer` at `delay`, crossfaded with the old head's
+    /// while a crossfade runs. The old head reads without the all-pass state
+    fn crossfade(&self, buffer: &RingBuffer<f64>, delay: f32, new_read: f64) -> f64 {
+        if self.crossfade_remaining == 0 {
+            return new_read;
+        }
+        let old_read = self.read(buffer, self.clamp_delay(delay + self.previous_delay - self.target_delay));
+        let fade = self.crossfade_remaining as f64 / self.crossfade_length as f64;
+        new_read + (old_read - new_read) * fade
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    /// keeps the input and the taps' outputs at full f64 precision. Otherwise every sample is
+    /// rounded to f32 as it is stored, like an f32 delay line, and the rounding error of each
+    /// pass around a tap's feedback loop comes back along with the signal
+    pub fn set_high_precision(&mut self, enabled: bool) {
+        self.high_precision = enabled;
+    }
+
+    /// rounds a sample to f32 precision unless high precision is on
+    fn precision(&self, x: f64) -> f64 {
+        if self.high_precision {
+            x
+        } else {
+            x as f32 as f64
         }
     }
 
+    /// the sample `delay` samples back in `buffer`, read with the current interpolation. The
+    /// all-pass needs a state per read, so stateless reads fall back to linear
+    fn read(&self, buffer: &RingBuffer<f64>, delay: f32) -> f64 {
+        match self.interpolation {
+            Interpolation::Nearest => buffer.get(delay.max(0.0).round() as usize),
+            Interpolation::Linear | Interpolation::AllPass => read_linear(buffer, delay),
+            Interpolation::Hermite => read_hermite(buffer, delay),
+            Interpolation::Sinc => read_sinc(buffer, delay),
+        }
+    }
+
+    /// stores the input of this frame, before any tap is read
+    pub fn push(&mut self, x: f64) {
+        self.crossfade_remaining = self.crossfade_remaining.saturating_sub(1);
+        self.x_buffer.push(self.precision(x));
+        self.update_heads();
+    }
+
+    /// whether `tap` runs its own feedback loop, only with feedback and a buffer for it
+    fn feeds_back(&self, tap: &Tap) -> bool {
+        self.feedback != 0.0 && tap.has_feedback()
+    }
+
     // y(n) = x(n - delay) + fb * y(n - delay)
-    pub fn process_sample(&mut self, x: f32, delay: usize) -> f32 {
-        self.x_buffer.rotate_right(1);
-        self.x_buffer[0] = x;
+    /// reads `tap` at `delay`, which is in samples and read between samples with the
+    /// interpolation set with `set_interpolation`, so a slowly modulated delay glides instead of
+    /// stepping from sample to sample
+    pub fn read_tap(&self, tap: &mut Tap, delay: f32) -> f64 {
+        let head_delay = self.head_delay(delay);
+        if !self.feeds_back(tap) {
+            let x_read = if self.interpolation == Interpolation::AllPass {
+                read_allpass(&self.x_buffer, head_delay, &mut tap.x_allpass_state)
+            } else {
+                self.read(&self.x_buffer, head_delay)
+            };
+            return self.precision(self.crossfade(&self.x_buffer, delay, x_read));
+        }
+        let (x_read, y_read) = if self.interpolation == Interpolation::AllPass {
+            (
+                read_allpass(&self.x_buffer, head_delay, &mut tap.x_allpass_state),
+                read_allpass(&tap.y_buffer, head_delay, &mut tap.y_allpass_state),
+            )
+        } else {
+            (self.read(&self.x_buffer, head_delay), self.read(&tap.y_buffer, head_delay))
+        };
+        let x_read = self.crossfade(&self.x_buffer, delay, x_read);
+        let y_read = self.crossfade(&tap.y_buffer, delay, y_read);
+        let y = self.precision(x_read + self.feedback as f64 * y_read);
 
-        let y = 
-        self.x_buffer.get(delay).unwrap()
-        + self.feedback * self.y_buffer.get(delay).unwrap();
+        tap.y_buffer.push(y);
 
-        self.y_buffer.rotate_right(1);
-        self.y_buffer[0] = y;
+        y
+    }
+
+    /// reads `tap` at several delays at once, each at its fractional `delays` and scaled by its
+    /// `gains`, like the overlapping grains of a pitch shifter. The all-pass interpolation reads
+    /// them linearly
+    pub fn read_taps<const N: usize>(&self, tap: &mut Tap, delays: [f32; N], gains: [f32; N]) -> f64 {
+        let feeds_back = self.feeds_back(tap);
+        let y = delays.iter().zip(gains).fold(0.0, |y, (&delay, gain)| {
+            let head_delay = self.head_delay(delay);
+            let x_read = self.crossfade(&self.x_buffer, delay, self.read(&self.x_buffer, head_delay));
+            if !feeds_back {
+                return y + gain as f64 * x_read;
+            }
+            let y_read = self.crossfade(&tap.y_buffer, delay, self.read(&tap.y_buffer, head_delay));
+            y + gain as f64 * (x_read + self.feedback as f64 * y_read)
+        });
+        let y = self.precision(y);
+
+        if feeds_back {
+            tap.y_buffer.push(y);
+        }
 
         y
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sinc_reads_pass_dc_at_unity_gain() {
+        let mut buffer = RingBuffer::new(64);
+        for _ in 0..64 {
+            buffer.push(1.0);
+        }
+        for step in 1..20 {
+            let delay = 20.0 + step as f32 / 20.0;
+            let y = read_sinc(&buffer, delay);
+            assert!((y - 1.0).abs() < 1e-9, "{delay}: {y}");
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,124 @@
+use crate::{lfo::LFO, ring_buffer::RingBuffer};
+
+// delays of the all-pass stages, spread out and unrelated so their echoes don't line up
+const STAGE_DELAYS_MS: [f32; 4] = [1.7, 3.1, 4.9, 7.3];
+// each stage's delay is swept by up to this much, at its own slow rate, so the smearing moves
+// instead of ringing at fixed frequencies
+const MODULATION_MS: f32 = 0.25;
+const MODULATION_RATES_HZ: [f32; 4] = [0.31, 0.43, 0.57, 0.71];
+// all-pass gain at full diffusion, higher gains start to ring metallic
+const MAX_GAIN: f32 = 0.7;
+
+/// Schroeder all-pass with a modulated, linearly interpolated delay
+#[derive(Clone)]
+struct AllPass {
+    buffer: RingBuffer,
+    delay_ms: f32,
+    lfo: LFO,
+}
+
+impl AllPass {
+    fn new(sample_rate: f32, delay_ms: f32, rate: f32, phase: f32) -> Self {
+        Self {
+            buffer: RingBuffer::new(buffer_len(sample_rate)),
+            delay_ms,
+            lfo: LFO::new_with_phase(sample_rate, rate, phase),
+        }
+    }
+
+    // w(n) = x(n) + g * w(n - D)
+    // y(n) = w(n - D) - g * w(n)
+    fn process(&mut self, x: f32, gain: f32, sample_rate: f32) -> f32 {
+        let delay_ms = self.delay_ms + MODULATION_MS * self.lfo.next_value();
+        self.lfo.update_lfo();
+
+        // read before this sample's push, so `get(0)` is w(n - 1)
+        let delay = (delay_ms / 1000.0 * sample_rate - 1.0).max(0.0);
+        let index = delay as usize;
+        let frac = delay - index as f32;
+        let delayed = self.buffer.get(index) + (self.buffer.get(index + 1) - self.buffer.get(index)) * frac;
+
+        let w = x + gain * delayed;
+        self.buffer.push(w);
+        delayed - gain * w
+    }
+}
+
+fn buffer_len(sample_rate: f32) -> usize {
+    let longest_ms = STAGE_DELAYS_MS.iter().fold(0.0f32, |longest, delay| longest.max(*delay)) + MODULATION_MS;
+    (longest_ms / 1000.0 * sample_rate) as usize + 2
+}
+
+/// a short chain of modulated all-pass filters smearing a signal in time, towards a reverb-like
+/// texture without changing its frequency balance
+#[derive(Clone)]
+pub struct Diffuser {
+    stages: Vec<AllPass>,
+    // where the stages' modulation starts, see `new`
+    phase: f32,
+    sample_rate: f32,
+    // 0 to 1, both the all-pass gain and the mix of the diffused signal
+    amount: f32,
+}
+
+impl Diffuser {
+    /// `phase` offsets the stages' modulation, so two channels can be decorrelated
+    pub fn new(sample_rate: f32, phase: f32) -> Self {
+        let stages = STAGE_DELAYS_MS
+            .iter()
+            .zip(MODULATION_RATES_HZ)
+            .map(|(delay_ms, rate)| AllPass::new(sample_rate, *delay_ms, rate, phase))
+            .collect();
+
+        Self {
+            stages,
+            phase,
+            sample_rate,
+            amount: 0.0,
+        }
+    }
+
+    /// reallocates the buffers, this is not realtime safe
+    pub fn resize_buffers(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for stage in self.stages.iter_mut() {
+            stage.buffer.resize(buffer_len(sample_rate));
+            stage.lfo.sample_rate = sample_rate;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.buffer.clear();
+        }
+    }
+
+    /// puts the stages' modulation back where it started
+    pub fn restart_modulation(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.lfo.set_phase(self.phase);
+        }
+    }
+
+    /// 0 passes the signal through, 1 mixes in only the fully diffused signal. Starting from 0
+    /// clears the stages, so nothing left over from before comes back
+    pub fn set_amount(&mut self, amount: f32) {
+        if self.amount == 0.0 && amount > 0.0 {
+            self.clear();
+        }
+        self.amount = amount.clamp(0.0, 1.0);
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        if self.amount == 0.0 {
+            return x;
+        }
+
+        let gain = MAX_GAIN * self.amount;
+        let mut diffused = x;
+        for stage in self.stages.iter_mut() {
+            diffused = stage.process(diffused, gain, self.sample_rate);
+        }
+        x + (diffused - x) * self.amount
+    }
+}
\ No newline at end of file
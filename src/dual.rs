@@ -0,0 +1,218 @@
+use std::iter;
+
+use crate::{chorus::{self, Chorus}, multiband::Multiband, ring_buffer::RingBuffer};
+
+/// how the second engine is wired to the first one
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Routing {
+    // only the first engine runs
+    Off,
+    // the second engine processes the output of the first one
+    Serial,
+    // both engines process the input
+    Parallel,
+}
+
+/// two complete chorus engines, the (multiband) first one and a second one with its own delay,
+/// depth and rate, for chorus into chorus textures. The blend crossfades from the first engine
+/// alone to the second engine's output. In series the second engine's dry delay comes on top of
+/// the first one's, so the first engine's output is delayed by as much before it is blended.
+///
+/// All choruses are set up the same way through `choruses_mut`, and differ only in what the
+/// caller sets per engine and band.
+pub struct DualEngine {
+    first: Multiband,
+    second: Chorus,
+    routing: Routing,
+    blend: f32,
+    // the first engine's output, for lining it up with the second engine's in series
+    left_first: RingBuffer<f32>,
+    right_first: RingBuffer<f32>,
+}
+
+/// length of a buffer that holds the latency of both engines in series
+pub fn latency_buffer_len(sample_rate: f32) -> usize {
+    2 * chorus::dry_buffer_len(sample_rate)
+}
+
+impl DualEngine {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            first: Multiband::new(sample_rate),
+            second: Chorus::new(sample_rate, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0),
+            routing: Routing::Off,
+            blend: 0.5,
+            left_first: RingBuffer::new(chorus::dry_buffer_len(sample_rate)),
+            right_first: RingBuffer::new(chorus::dry_buffer_len(sample_rate)),
+        }
+    }
+
+    /// latency in samples of both engines, the dry delay of the first one and in series the
+    /// second one's on top. All choruses get the settings the dry delay depends on, so the
+    /// bands and the engines in parallel all have the same
+    pub fn latency(&self) -> usize {
+        let second = if self.routing == Routing::Serial { self.second.latency() } else { 0 };
+        self.first.first().latency() + second
+    }
+
+    pub fn multiband(&self) -> &Multiband {
+        &self.first
+    }
+
+    pub fn multiband_mut(&mut self) -> &mut Multiband {
+        &mut self.first
+    }
+
+    /// both engines at once, so each can get its own settings in the same pass
+    pub fn engines_mut(&mut self) -> (&mut Multiband, &mut Chorus) {
+        (&mut self.first, &mut self.second)
+    }
+
+    /// the choruses of all bands of the first engine and the second engine, including the ones
+    /// not processed right now, so they come in with the current settings
+    pub fn choruses_mut(&mut self) -> impl Iterator<Item = &mut Chorus> {
+        self.first.bands_mut().chain(iter::once(&mut self.second))
+    }
+
+    /// the choruses being processed
+    pub fn active_choruses(&self) -> impl Iterator<Item = &Chorus> {
+        let second = (self.routing != Routing::Off).then_some(&self.second);
+        self.first.active_bands().iter().chain(second)
+    }
+
+    /// the second engine starts from a reset chorus when it is switched on, which fades it in
+    pub fn set_routing(&mut self, routing: Routing) {
+        if routing == self.routing {
+            return;
+        }
+        if self.routing == Routing::Off {
+            self.second.reset();
+        }
+        self.routing = routing;
+    }
+
+    /// 0 is only the first engine, 1 only the second engine's output
+    pub fn set_blend(&mut self, blend: f32) {
+        self.blend = if blend.is_finite() { blend.clamp(0.0, 1.0) } else { 0.5 };
+    }
+
+    /// reallocates the buffers, this is not realtime safe
+    pub fn resize_buffers(&mut self, sample_rate: f32) {
+        self.first.resize_buffers(sample_rate);
+        self.second.resize_buffers(sample_rate);
+        self.left_first.resize(chorus::dry_buffer_len(sample_rate));
+        self.right_first.resize(chorus::dry_buffer_len(sample_rate));
+    }
+
+    pub fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+        self.left_first.clear();
+        self.right_first.clear();
+    }
+
+    fn blend(&self, first: f32, second: f32) -> f32 {
+        first + (second - first) * self.blend
+    }
+
+    /// the first engine's left output as it is blended, late by the second engine's latency in
+    /// series
+    fn first_left(&mut self, first: f32) -> f32 {
+        self.left_first.push(first);
+        match self.routing {
+            Routing::Serial => self.left_first.get(self.second.latency()),
+            _ => first,
+        }
+    }
+
+    fn first_right(&mut self, first: f32) -> f32 {
+        self.right_first.push(first);
+        match self.routing {
+            Routing::Serial => self.right_first.get(self.second.latency()),
+            _ => first,
+        }
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        let first = self.first.process_left(x);
+        let second = match self.routing {
+            Routing::Off => return self.first_left(first),
+            Routing::Serial => self.second.process_left(first),
+            Routing::Parallel => self.second.process_left(x),
+        };
+        let first = self.first_left(first);
+        self.blend(first, second)
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        let first = self.first.process_right(x);
+        let second = match self.routing {
+            Routing::Off => return self.first_right(first),
+            Routing::Serial => self.second.process_right(first),
+            Routing::Parallel => self.second.process_right(x),
+        };
+        let first = self.first_right(first);
+        self.blend(first, second)
+    }
+
+    pub fn process_stereo(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let (first_left, first_right) = self.first.process_stereo(left, right);
+        let (second_left, second_right) = match self.routing {
+            Routing::Off => return (self.first_left(first_left), self.first_right(first_right)),
+            Routing::Serial => self.second.process_stereo(first_left, first_right),
+            Routing::Parallel => self.second.process_stereo(left, right),
+        };
+        let (first_left, first_right) = (self.first_left(first_left), self.first_right(first_right));
+        (self.blend(first_left, second_left), self.blend(first_right, second_right))
+    }
+
+    /// see `Chorus::process_dual_mono`
+    pub fn process_dual_mono(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let (first_left, first_right) = self.first.process_dual_mono(left, right);
+        let (second_left, second_right) = match self.routing {
+            Routing::Off => return (self.first_left(first_left), self.first_right(first_right)),
+            Routing::Serial => self.second.process_dual_mono(first_left, first_right),
+            Routing::Parallel => self.second.process_dual_mono(left, right),
+        };
+        let (first_left, first_right) = (self.first_left(first_left), self.first_right(first_right));
+        (self.blend(first_left, second_left), self.blend(first_right, second_right))
+    }
+
+    /// the voices of both engines in the last processed frame, blended like the output
+    pub fn wet_output(&self) -> (f32, f32) {
+        let (first_left, first_right) = self.first.wet_output();
+        if self.routing == Routing::Off {
+            return (first_left, first_right);
+        }
+        let (second_left, second_right) = self.second.wet_output();
+        (self.blend(first_left, second_left), self.blend(first_right, second_right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oversample::FilterOrder;
+
+    #[test]
+    fn serial_engines_blend_in_line() {
+        let sample_rate = 48000.0;
+        let mut engines = DualEngine::new(sample_rate);
+        // the dry path alone, the voices would smear the impulse
+        for chorus in engines.choruses_mut() {
+            chorus.set_params(sample_rate, 10.0, 0.0, 0.0, 0.5, 0.0, 1.0);
+            chorus.set_oversampling(Some(FilterOrder::Long));
+        }
+        engines.set_routing(Routing::Serial);
+        engines.set_blend(0.5);
+        engines.reset();
+        let latency = engines.latency();
+        assert_eq!(latency, 2 * FilterOrder::Long.latency());
+
+        for n in 0..2 * latency {
+            let (y, _) = engines.process_stereo(if n == 0 { 1.0 } else { 0.0 }, 0.0);
+            let expected = if n == latency { 1.0 } else { 0.0 };
+            assert!((y - expected).abs() < 1e-6, "sample {n}: {y}");
+        }
+    }
+}
\ No newline at end of file
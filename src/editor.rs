@@ -1,96 +1,852 @@
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use atomic_float::AtomicF32;
 
-use nih_plug::prelude::{util, Editor, Vst3Plugin};
+use nih_plug::prelude::{util, Editor, Params, Vst3Plugin};
 use nih_plug_vizia::vizia::image::Pixel;
 use nih_plug_vizia::vizia::prelude::*;
 use nih_plug_vizia::widgets::*;
 use nih_plug_vizia::{assets, create_vizia_editor, ViziaState, ViziaTheming};
 
 
-use crate::ChorusParams;
+use crate::{ChorusMode, ChorusParams, DepthMode, EngineRouting};
+use crate::lfo_feed::LfoFeed;
+use crate::lfo_scope::LfoScope;
+use crate::presets::{self, Preset, PresetCategory};
+use crate::table_editor::TableEditor;
 
 
 #[derive(Lens)]
 struct Data {
-    chorus_data: Arc<ChorusParams>
+    chorus_data: Arc<ChorusParams>,
+    buffer_overflow: Arc<AtomicBool>,
+    correlation: Arc<AtomicF32>,
+    feedback_energy: Arc<AtomicF32>,
+    lfo_feed: Arc<LfoFeed>,
+    // picked up and cleared by the audio thread at the start of the next block
+    reset_requested: Arc<AtomicBool>,
+    presets: Vec<Preset>,
+}
+
+// the resonance meter turns red above this feedback loop level
+const RESONANCE_WARNING_DB: f32 = -6.0;
+
+enum PresetEvent {
+    Load(usize),
+}
+
+enum EngineEvent {
+    Reset,
+}
+
+impl Model for Data {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|preset_event, meta| match preset_event {
+            PresetEvent::Load(index) => {
+                if let Some(preset) = self.presets.get(*index) {
+                    // goes through the regular parameter setters, so the host sees the change
+                    // and the smoothers apply. Parameters the preset doesn't list go back to their
+                    // defaults, so nothing of the previous sound carries over. Bypass isn't part
+                    // of the sound and stays as it is
+                    for (id, param_ptr, _) in self.chorus_data.param_map() {
+                        if id == "bypass" {
+                            continue;
+                        }
+                        let normalized = match preset.values.get(&id) {
+                            Some(value) => unsafe { param_ptr.preview_normalized(*value) },
+                            None => unsafe { param_ptr.default_normalized_value() },
+                        };
+                        cx.emit(RawParamEvent::BeginSetParameter(param_ptr));
+                        cx.emit(RawParamEvent::SetParameterNormalized(param_ptr, normalized));
+                        cx.emit(RawParamEvent::EndSetParameter(param_ptr));
+                    }
+                }
+
+                meta.consume();
+            }
+        });
+
+        event.map(|engine_event, meta| match engine_event {
+            EngineEvent::Reset => {
+                self.reset_requested.store(true, Ordering::Relaxed);
+                meta.consume();
+            }
+        });
+    }
+}
+
+/// the vibrato runs fully wet without feedback, so the mix controls do nothing in it
+fn vibrato_mode(params: &Arc<ChorusParams>) -> bool {
+    params.mode.value() == ChorusMode::Vibrato
+}
+
+/// the wet and dry knobs are replaced by the single mix knob, or do nothing in the vibrato
+fn wet_dry_disabled(params: &Arc<ChorusParams>) -> bool {
+    vibrato_mode(params) || params.single_mix.value()
+}
+
+/// the mix knob only applies in single mix mode
+fn mix_disabled(params: &Arc<ChorusParams>) -> bool {
+    vibrato_mode(params) || !params.single_mix.value()
 }
 
-impl Model for Data {}
+/// the detune only applies to the granular mode
+fn detune_disabled(params: &Arc<ChorusParams>) -> bool {
+    params.mode.value() != ChorusMode::Granular
+}
+
+/// flanging through zero only applies to the flanger mode
+fn through_zero_disabled(params: &Arc<ChorusParams>) -> bool {
+    params.mode.value() != ChorusMode::Flanger
+}
+
+/// the depth is set as a share of the delay with the depth % knob instead of the depth knob
+fn percentage_depth(params: &Arc<ChorusParams>) -> bool {
+    params.depth_mode.value() == DepthMode::Percentage
+}
+
+/// the Juno modes fix the delay, depth and rate
+fn juno_mode(params: &Arc<ChorusParams>) -> bool {
+    matches!(params.mode.value(), ChorusMode::JunoI | ChorusMode::JunoII | ChorusMode::JunoBoth)
+}
+
+/// the blend and the second engine's knobs only apply with the second engine on
+fn second_engine_disabled(params: &Arc<ChorusParams>) -> bool {
+    params.engine_routing.value() == EngineRouting::Off
+}
+
+/// the crossover and the band knobs of bands beyond the band count do nothing
+fn multiband_disabled(params: &Arc<ChorusParams>) -> bool {
+    params.band_count.value() < 2
+}
+
+fn three_bands_disabled(params: &Arc<ChorusParams>) -> bool {
+    params.band_count.value() < 3
+}
+
+fn preset_browser(cx: &mut Context, presets: &[Preset]) {
+    VStack::new(cx, |cx| {
+        Label::new(cx, "Presets").font_size(15.0)
+        .height(Pixels(30.0));
+
+        for category in PresetCategory::ALL {
+            Label::new(cx, category.name()).font_size(12.0)
+            .height(Pixels(20.0));
+
+            for (index, preset) in presets.iter().enumerate().filter(|(_, preset)| preset.category == category) {
+                Button::new(cx, move |cx| cx.emit(PresetEvent::Load(index)), |cx| Label::new(cx, &preset.name))
+                .height(Pixels(24.0))
+                .width(Pixels(140.0));
+            }
+        }
+    }).row_between(Pixels(3.0))
+    .width(Pixels(160.0));
+}
 
 pub(crate) fn default_state() -> Arc<ViziaState> {
-    ViziaState::new(|| (400, 300))
+    ViziaState::new(|| (1530, 1146))
 }
 
 pub(crate) fn create(
     chorus_data: Arc<ChorusParams>,
     editor_state: Arc<ViziaState>,
+    buffer_overflow: Arc<AtomicBool>,
+    correlation: Arc<AtomicF32>,
+    feedback_energy: Arc<AtomicF32>,
+    lfo_feed: Arc<LfoFeed>,
+    reset_requested: Arc<AtomicBool>,
 ) -> Option<Box<dyn Editor>> {
     create_vizia_editor(editor_state, 
         ViziaTheming::Custom, move |cx, _| {
             assets::register_noto_sans_light(cx);
             assets::register_noto_sans_thin(cx);
 
+            // scanned every time the editor opens, so new preset files show up without a restart
+            let loaded_presets = presets::all_presets();
+
             Data {
                 chorus_data: chorus_data.clone(),
+                buffer_overflow: buffer_overflow.clone(),
+                correlation: correlation.clone(),
+                feedback_energy: feedback_energy.clone(),
+                lfo_feed: lfo_feed.clone(),
+                reset_requested: reset_requested.clone(),
+                presets: loaded_presets.clone(),
             }.build(cx);
 
             ResizeHandle::new(cx);
 
-            VStack::new(cx, |cx| {
-                Label::new(cx, "CHORUS")
-                .font_family(vec![FamilyOwned::Name(String::from(
-                    assets::NOTO_SANS_THIN,
-                ))])
-                .font_size(30.0)
-                .height(Pixels(50.0))
-                .child_top(Stretch(1.0))
-                .child_bottom(Pixels(30.0));
+            HStack::new(cx, |cx| {
+                VStack::new(cx, |cx| {
+                    Label::new(cx, "CHORUS")
+                    .font_family(vec![FamilyOwned::Name(String::from(
+                        assets::NOTO_SANS_THIN,
+                    ))])
+                    .font_size(30.0)
+                    .height(Pixels(50.0))
+                    .child_top(Stretch(1.0))
+                    .child_bottom(Pixels(30.0));
                 
-                HStack::new(cx, |cx| {
-                    VStack::new(cx, |cx| {
-                        Label::new(cx, "Depth").font_size(15.0)
-                        .height(Pixels(30.0));
+                    HStack::new(cx, |cx| {
+                        VStack::new(cx, |cx| {
+                            Label::new(cx, "Depth").font_size(15.0)
+                            .height(Pixels(30.0));
     
-                        Label::new(cx, "Rate").font_size(15.0)
-                        .height(Pixels(30.0));
+                            Label::new(cx, "Rate").font_size(15.0)
+                            .height(Pixels(30.0));
     
-                        Label::new(cx, "Delay").font_size(15.0)
-                        .height(Pixels(30.0));
+                            Label::new(cx, "Delay").font_size(15.0)
+                            .height(Pixels(30.0));
     
-                        Label::new(cx, "feedback").font_size(15.0)
-                        .height(Pixels(30.0));
+                            Label::new(cx, "feedback").font_size(15.0)
+                            .height(Pixels(30.0));
     
-                        Label::new(cx, "Wet").font_size(15.0)
-                        .height(Pixels(30.0));
+                            Label::new(cx, "Wet").font_size(15.0)
+                            .height(Pixels(30.0));
     
-                        Label::new(cx, "Dry").font_size(15.0)
-                        .height(Pixels(30.0));
-                    }).child_top(Pixels(6.0)).row_between(Pixels(3.0));
+                            Label::new(cx, "Dry").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Extended").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "FB Makeup").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "3D").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Process").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Rate Follow").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Mono Protect").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Dyn. Depth").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Input").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Voice FB").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Fixed Blocks").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Bypass").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "HP Feedback").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Trans. Duck").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "SC > Depth").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "SC > Wet").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Voices").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Spread").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Pan Width").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Voice Taper").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Mode").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Detune").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Through Zero").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Depth Mode").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            Label::new(cx, "Depth %").font_size(15.0)
+                            .height(Pixels(30.0));
+                        }).child_top(Pixels(6.0)).row_between(Pixels(3.0));
     
-                    VStack::new(cx, |cx| {
-                        ParamSlider::new(cx, Data::chorus_data, |params| &params.depth)
-                        .height(Pixels(30.0));
+                        VStack::new(cx, |cx| {
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.depth)
+                            .disabled(Data::chorus_data.map(|params| juno_mode(params) || percentage_depth(params)))
+                            .height(Pixels(30.0));
                     
-                        ParamSlider::new(cx, Data::chorus_data, |params| &params.rate)
-                        .height(Pixels(30.0));
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.rate)
+                            .disabled(Data::chorus_data.map(juno_mode))
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.delay_ms)
+                            .disabled(Data::chorus_data.map(juno_mode))
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.feedback)
+                            .disabled(Data::chorus_data.map(vibrato_mode))
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.wet)
+                            .disabled(Data::chorus_data.map(wet_dry_disabled))
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.dry)
+                            .disabled(Data::chorus_data.map(wet_dry_disabled))
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.extended_ranges)
+                            .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.feedback_makeup)
+                            .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.motion_3d)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.process_mode)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.rate_follows_delay)
+                            .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.mono_protect)
+                            .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.dynamic_depth)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.input_mode)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.per_voice_feedback)
+                            .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.fixed_blocks)
+                            .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.bypass)
+                            .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.high_precision)
+                            .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.transient_duck)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.sidechain_depth)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.sidechain_wet)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.voice_count)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.delay_spread)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.pan_width)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.voice_taper)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.mode)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.grain_detune)
+                            .disabled(Data::chorus_data.map(detune_disabled))
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.through_zero)
+                            .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                            .disabled(Data::chorus_data.map(through_zero_disabled))
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.depth_mode)
+                            .height(Pixels(30.0));
+
+                            ParamSlider::new(cx, Data::chorus_data, |params| &params.depth_percent)
+                            .disabled(Data::chorus_data.map(|params| juno_mode(params) || !percentage_depth(params)))
+                            .height(Pixels(30.0));
+                        }).row_between(Pixels(3.0));
+
+                        // the LFO shape, timing and phase relations get their own column
+                        VStack::new(cx, |cx| {
+                            Label::new(cx, "LFO").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            HStack::new(cx, |cx| {
+                                VStack::new(cx, |cx| {
+                                    Label::new(cx, "Waveform").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Sync").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Division").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Stereo Phase").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Voice Spread").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Retrigger").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Start Phase").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Shape").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Humanize").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Step Seq").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Rate Mod").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Chaos").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Chaos Speed").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Unipolar").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Key Track").font_size(15.0)
+                                    .height(Pixels(30.0));
 
-                        ParamSlider::new(cx, Data::chorus_data, |params| &params.delay_ms)
-                        .height(Pixels(30.0));
+                                    Label::new(cx, "Rate Spread").font_size(15.0)
+                                    .height(Pixels(30.0));
+                                }).child_top(Pixels(6.0)).row_between(Pixels(3.0));
 
-                        ParamSlider::new(cx, Data::chorus_data, |params| &params.feedback)
-                        .height(Pixels(30.0));
+                                VStack::new(cx, |cx| {
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.waveform)
+                                    .height(Pixels(30.0));
 
-                        ParamSlider::new(cx, Data::chorus_data, |params| &params.wet)
-                        .height(Pixels(30.0));
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.rate_sync)
+                                    .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                                    .height(Pixels(30.0));
 
-                        ParamSlider::new(cx, Data::chorus_data, |params| &params.dry)
-                        .height(Pixels(30.0));
-                    }).row_between(Pixels(3.0));
-                }).col_between(Pixels(30.0));
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.rate_division)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.stereo_phase)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.voice_spread)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.retrigger)
+                                    .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.retrigger_phase)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.lfo_shape)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.lfo_humanize)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.step_mode)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.rate_mod)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.chaos_amount)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.chaos_speed)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.unipolar)
+                                    .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.key_track)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.rate_spread)
+                                    .height(Pixels(30.0));
+                                }).row_between(Pixels(3.0));
+                            }).col_between(Pixels(30.0));
+
+                            // the custom waveform, drawn with the mouse
+                            TableEditor::new(cx, chorus_data.clone(), |params| &params.lfo_table, |params| &params.lfo_table_changed, false)
+                            .width(Pixels(256.0))
+                            .height(Pixels(96.0))
+                            .top(Pixels(6.0));
+
+                            // the step sequence, one bar of sixteenth notes
+                            TableEditor::new(cx, chorus_data.clone(), |params| &params.step_values, |params| &params.step_values_changed, true)
+                            .width(Pixels(256.0))
+                            .height(Pixels(64.0))
+                            .top(Pixels(6.0));
+
+                            // the voices' modulation as it is being played
+                            LfoScope::new(cx, lfo_feed.clone(), Data::lfo_feed.map(|feed| feed.generation()))
+                            .width(Pixels(256.0))
+                            .height(Pixels(64.0))
+                            .top(Pixels(6.0));
+                        }).row_between(Pixels(3.0));
+
+                        // routing and coloring of the feedback loops and the wet path
+                        VStack::new(cx, |cx| {
+                            Label::new(cx, "FEEDBACK").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            HStack::new(cx, |cx| {
+                                VStack::new(cx, |cx| {
+                                    Label::new(cx, "Cross FB").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "FB Low-Pass").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "FB High-Pass").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Cross LP").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Invert FB").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "FB Drive").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Diffusion").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "BBD").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "BBD Stages").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Noise").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Tone").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Wet HP").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Filter Pos.").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Bass Preserve").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Width").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Mono").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Auto Gain").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Mix Law").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Single Mix").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Mix").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Invert R Wet").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Haas").font_size(15.0)
+                                    .height(Pixels(30.0));
+                                }).child_top(Pixels(6.0)).row_between(Pixels(3.0));
+
+                                VStack::new(cx, |cx| {
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.cross_feedback)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.feedback_lowpass)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.feedback_highpass)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.cross_feedback_lowpass)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.invert_feedback)
+                                    .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.feedback_drive)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.diffusion)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.bbd)
+                                    .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.bbd_stages)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.noise)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.tone)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.wet_highpass)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.filter_position)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.bass_preserve)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.width)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.mono_audition)
+                                    .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.auto_gain)
+                                    .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.mix_law)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.single_mix)
+                                    .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.mix)
+                                    .disabled(Data::chorus_data.map(mix_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.invert_right_wet)
+                                    .set_style(ParamSliderStyle::CurrentStepLabeled { even: true })
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.haas_delay)
+                                    .height(Pixels(30.0));
+                                }).row_between(Pixels(3.0));
+                            }).col_between(Pixels(30.0));
+                        }).row_between(Pixels(3.0));
+
+                        // splitting the input into bands with their own depth and rate, and the second engine
+                        VStack::new(cx, |cx| {
+                            Label::new(cx, "ENGINES").font_size(15.0)
+                            .height(Pixels(30.0));
+
+                            HStack::new(cx, |cx| {
+                                VStack::new(cx, |cx| {
+                                    Label::new(cx, "Bands").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Low X-Over").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "High X-Over").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Low Depth").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Mid Depth").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "High Depth").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Low Rate").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Mid Rate").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "High Rate").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Engine B").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "A/B Blend").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "B Delay").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "B Depth").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "B Rate").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Quality").font_size(15.0)
+                                    .height(Pixels(30.0));
+
+                                    Label::new(cx, "Oversampling").font_size(15.0)
+                                    .height(Pixels(30.0));
+                                }).child_top(Pixels(6.0)).row_between(Pixels(3.0));
+
+                                VStack::new(cx, |cx| {
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.band_count)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.low_crossover)
+                                    .disabled(Data::chorus_data.map(multiband_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.high_crossover)
+                                    .disabled(Data::chorus_data.map(three_bands_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.low_band_depth)
+                                    .disabled(Data::chorus_data.map(multiband_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.mid_band_depth)
+                                    .disabled(Data::chorus_data.map(multiband_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.high_band_depth)
+                                    .disabled(Data::chorus_data.map(three_bands_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.low_band_rate)
+                                    .disabled(Data::chorus_data.map(multiband_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.mid_band_rate)
+                                    .disabled(Data::chorus_data.map(multiband_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.high_band_rate)
+                                    .disabled(Data::chorus_data.map(three_bands_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.engine_routing)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.engine_blend)
+                                    .disabled(Data::chorus_data.map(second_engine_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.second_delay_ms)
+                                    .disabled(Data::chorus_data.map(second_engine_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.second_depth)
+                                    .disabled(Data::chorus_data.map(second_engine_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.second_rate)
+                                    .disabled(Data::chorus_data.map(second_engine_disabled))
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.interpolation)
+                                    .height(Pixels(30.0));
+
+                                    ParamSlider::new(cx, Data::chorus_data, |params| &params.oversampling)
+                                    .height(Pixels(30.0));
+                                }).row_between(Pixels(3.0));
+                            }).col_between(Pixels(30.0));
+                        }).row_between(Pixels(3.0));
+                    }).col_between(Pixels(30.0));
+
+                    Label::new(cx, Data::correlation.map(|correlation| {
+                        format!("Correlation {:.2}", correlation.load(Ordering::Relaxed))
+                    }))
+                    .font_size(12.0)
+                    .height(Pixels(20.0));
+
+                    HStack::new(cx, |cx| {
+                        Binding::new(
+                            cx,
+                            Data::feedback_energy.map(|energy| {
+                                util::gain_to_db(energy.load(Ordering::Relaxed)) > RESONANCE_WARNING_DB
+                            }),
+                            |cx, warning| {
+                                let label = Label::new(cx, "Resonance").font_size(12.0)
+                                .width(Pixels(80.0));
+                                if warning.get(cx) {
+                                    label.color(Color::rgb(220, 60, 60));
+                                }
+                            },
+                        );
+
+                        PeakMeter::new(
+                            cx,
+                            Data::feedback_energy.map(|energy| util::gain_to_db(energy.load(Ordering::Relaxed))),
+                            None,
+                        )
+                        .width(Pixels(200.0));
+
+                        // clears the delay lines and feedback when something runs away or gets stuck
+                        Button::new(cx, |cx| cx.emit(EngineEvent::Reset), |cx| Label::new(cx, "Reset"))
+                        .height(Pixels(20.0))
+                        .left(Pixels(10.0));
+                    }).height(Pixels(20.0));
+
+                    Binding::new(cx, Data::chorus_data.map(|params| params.invert_right_wet.value()), |cx, inverted| {
+                        if inverted.get(cx) {
+                            Label::new(cx, "Right wet inverted, the voices cancel in mono").font_size(12.0)
+                            .color(Color::rgb(220, 60, 60))
+                            .height(Pixels(20.0));
+                        }
+                    });
+
+                    Binding::new(cx, Data::buffer_overflow.map(|overflow| overflow.load(Ordering::Relaxed)), |cx, overflow| {
+                        if overflow.get(cx) {
+                            Label::new(cx, "Delay too long for the buffers, clamped").font_size(12.0)
+                            .color(Color::rgb(220, 60, 60))
+                            .height(Pixels(20.0));
+                        }
+                    });
                 
-            }).row_between(Pixels(0.0))
-            .child_left(Stretch(1.0))
-            .child_right(Stretch(1.0));
+                }).row_between(Pixels(0.0))
+                .child_left(Stretch(1.0))
+                .child_right(Stretch(1.0));
+
+                preset_browser(cx, &loaded_presets);
+            });
 
         })
 }
\ No newline at end of file
@@ -0,0 +1,88 @@
+/// peak envelope follower with separate attack and release times
+#[derive(Clone, Copy)]
+pub struct EnvelopeFollower {
+    attack_ms: f32,
+    release_ms: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    envelope: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(sample_rate: f32, attack_ms: f32, release_ms: f32) -> Self {
+        let mut follower = Self {
+            attack_ms,
+            release_ms,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            envelope: 0.0,
+        };
+        follower.set_sample_rate(sample_rate);
+        follower
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.attack_coeff = (-1.0 / (self.attack_ms / 1000.0 * sample_rate)).exp();
+        self.release_coeff = (-1.0 / (self.release_ms / 1000.0 * sample_rate)).exp();
+    }
+
+    pub fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+
+    /// feeds one sample and returns the current envelope
+    pub fn process(&mut self, x: f32) -> f32 {
+        let level = x.abs();
+        let coeff = if level > self.envelope {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        };
+        self.envelope = level + coeff * (self.envelope - level);
+
+        self.envelope
+    }
+
+    pub fn value(&self) -> f32 {
+        self.envelope
+    }
+}
+
+/// finds transients by comparing a fast envelope with a slow one: the fast one jumps ahead on an
+/// attack and the slow one catches up over its attack time, sustained material keeps them level
+#[derive(Clone, Copy)]
+pub struct TransientDetector {
+    fast: EnvelopeFollower,
+    slow: EnvelopeFollower,
+}
+
+impl TransientDetector {
+    pub fn new(sample_rate: f32, fast_attack_ms: f32, slow_attack_ms: f32, release_ms: f32) -> Self {
+        Self {
+            fast: EnvelopeFollower::new(sample_rate, fast_attack_ms, release_ms),
+            slow: EnvelopeFollower::new(sample_rate, slow_attack_ms, release_ms),
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.fast.set_sample_rate(sample_rate);
+        self.slow.set_sample_rate(sample_rate);
+    }
+
+    pub fn reset(&mut self) {
+        self.fast.reset();
+        self.slow.reset();
+    }
+
+    /// feeds one sample and returns how strong the current transient is, 0 to 1
+    pub fn process(&mut self, x: f32) -> f32 {
+        let fast = self.fast.process(x);
+        let slow = self.slow.process(x);
+
+        if fast > 1e-6 {
+            ((fast - slow) / fast).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
\ No newline at end of file
@@ -422,4 +422,55 @@ impl BiquadFilter {
             }
         }
     }
+}
+
+/// fourth order Linkwitz-Riley crossover, two cascaded Butterworth sections per band. The bands
+/// are in phase at every frequency and sum back to an all-pass, so a signal split with it and
+/// added back together keeps its shape
+#[derive(Clone, Copy)]
+pub struct LinkwitzRiley {
+    lowpass: [BiquadFilter; 2],
+    highpass: [BiquadFilter; 2],
+}
+
+impl LinkwitzRiley {
+    pub fn new() -> Self {
+        Self {
+            lowpass: [BiquadFilter::new(); 2],
+            highpass: [BiquadFilter::new(); 2],
+        }
+    }
+
+    pub fn set_cutoff(&mut self, sample_rate: f32, cutoff: f32) {
+        for filter in self.lowpass.iter_mut() {
+            filter.second_order_lpf_coefficients(sample_rate, cutoff, core::f32::consts::FRAC_1_SQRT_2);
+        }
+        for filter in self.highpass.iter_mut() {
+            filter.second_order_hpf_coefficients(sample_rate, cutoff, core::f32::consts::FRAC_1_SQRT_2);
+        }
+    }
+
+    pub fn reset_filter(&mut self) {
+        for filter in self.lowpass.iter_mut().chain(self.highpass.iter_mut()) {
+            filter.reset_filter();
+        }
+    }
+
+    /// splits a left channel sample into (low, high)
+    pub fn split_left(&mut self, x: f32) -> (f32, f32) {
+        let low = self.lowpass[0].process_left(x);
+        let low = self.lowpass[1].process_left(low);
+        let high = self.highpass[0].process_left(x);
+        let high = self.highpass[1].process_left(high);
+        (low, high)
+    }
+
+    /// splits a right channel sample into (low, high)
+    pub fn split_right(&mut self, x: f32) -> (f32, f32) {
+        let low = self.lowpass[0].process_right(x);
+        let low = self.lowpass[1].process_right(low);
+        let high = self.highpass[0].process_right(x);
+        let high = self.highpass[1].process_right(high);
+        (low, high)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,69 @@
+use crate::ring_buffer::RingBuffer;
+
+/// longest delay of the Haas stage
+pub const MAX_HAAS_MS: f32 = 30.0;
+// the delayed channel is raised and the early one lowered by up to this much at the longest
+// delay, which pulls the image back towards the center the precedence effect pulls it from
+const MAX_COMPENSATION_DB: f32 = 3.0;
+
+/// a Haas widener, delays the right channel by a few ms against the left one. Below the echo
+/// threshold the ear fuses both into one wider sound instead of hearing a repeat. The delay is
+/// read between samples, so it can be swept without zipper noise
+pub struct Haas {
+    buffer: RingBuffer,
+    sample_rate: f32,
+    // the delay in samples and the channel gains it results in
+    delay: f32,
+    early_gain: f32,
+    late_gain: f32,
+}
+
+impl Haas {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            buffer: RingBuffer::new(Self::buffer_len(sample_rate)),
+            sample_rate,
+            delay: 0.0,
+            early_gain: 1.0,
+            late_gain: 1.0,
+        }
+    }
+
+    fn buffer_len(sample_rate: f32) -> usize {
+        (MAX_HAAS_MS / 1000.0 * sample_rate).ceil() as usize + 2
+    }
+
+    /// reallocates the buffer, this is not realtime safe
+    pub fn resize_buffers(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.buffer.resize(Self::buffer_len(sample_rate));
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// delay of the right channel, 0 to `MAX_HAAS_MS`. 0 bypasses the stage
+    pub fn set_delay(&mut self, delay_ms: f32) {
+        let delay_ms = if delay_ms.is_finite() { delay_ms.clamp(0.0, MAX_HAAS_MS) } else { 0.0 };
+        self.delay = delay_ms / 1000.0 * self.sample_rate;
+
+        // opposite gains in dB keep the summed power about the same
+        let compensation_db = MAX_COMPENSATION_DB * delay_ms / MAX_HAAS_MS;
+        self.late_gain = 10f32.powf(compensation_db / 20.0);
+        self.early_gain = 1.0 / self.late_gain;
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        // the buffer is always written, so raising the delay from 0 doesn't read silence
+        self.buffer.push(right);
+        if self.delay == 0.0 {
+            return (left, right);
+        }
+
+        let index = self.delay as usize;
+        let frac = self.delay - index as f32;
+        let delayed = self.buffer.get(index) + (self.buffer.get(index + 1) - self.buffer.get(index)) * frac;
+        (left * self.early_gain, delayed * self.late_gain)
+    }
+}
\ No newline at end of file
@@ -1,42 +1,283 @@
-use std::{f32::consts::PI, ops::Range};
+use core::{f32::consts::PI, f64::consts::TAU, ops::Range};
+use std::sync::OnceLock;
 
-use rand::Rng;
+use crate::random::Xorshift;
 
+/// how long switching the waveform crossfades between the old and the new shape
+pub const WAVEFORM_CROSSFADE_MS: f32 = 20.0;
+// keeps the rise and fall of a fully skewed shape from getting infinitely steep
+const MAX_SKEW: f32 = 0.99;
+/// number of points in a user drawn LFO table
+pub const TABLE_SIZE: usize = 256;
+// humanize wanders the phase by up to this fraction of a cycle, which also bends the rate while
+// the offset is moving
+const MAX_HUMANIZE_PHASE: f32 = 0.1;
+// how often the humanize noise picks a new target, on average
+const HUMANIZE_RATE_HZ: f32 = 0.3;
+// points per cycle of the sine lookup table, linear interpolation keeps the error below 5e-6
+const SINE_TABLE_SIZE: usize = 1024;
+/// seed of the random shapes until `reseed` gives the LFO one of its own
+pub const DEFAULT_SEED: u32 = 0x2545_f491;
+
+// one sine cycle plus the first point again, so interpolation never has to wrap
+static SINE_TABLE: OnceLock<[f32; SINE_TABLE_SIZE + 1]> = OnceLock::new();
+
+fn sine_lookup_table() -> &'static [f32; SINE_TABLE_SIZE + 1] {
+    SINE_TABLE.get_or_init(|| {
+        let mut table = [0.0; SINE_TABLE_SIZE + 1];
+        for (i, value) in table.iter_mut().enumerate() {
+            *value = (2.0 * PI * i as f32 / SINE_TABLE_SIZE as f32).sin();
+        }
+        table
+    })
+}
+
+/// sine of a phase given as a 0 to 1 fraction of the cycle, read from the shared lookup table
+/// instead of calling `sin()` for every LFO on every sample
+fn sine(t: f32) -> f32 {
+    let table = sine_lookup_table();
+    let position = t.rem_euclid(1.0) * SINE_TABLE_SIZE as f32;
+    let index = (position as usize).min(SINE_TABLE_SIZE - 1);
+    let frac = position - index as f32;
+    table[index] + (table[index + 1] - table[index]) * frac
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    SawUp,
+    SawDown,
+    Square,
+    // a new random target every cycle, glided to so the delay doesn't jump
+    Random,
+    // a new random value every cycle, held until the next one
+    SampleAndHold,
+    // glides between random targets like `Random`, but each glide takes a random 0.5 to 1.5
+    // cycles, so the modulation wanders irregularly like tape drift instead of repeating
+    Drift,
+    // reads the user drawn table set with `set_table`
+    Custom,
+}
+
+/// one sine cycle, the starting point of a user drawn table
+pub fn sine_table() -> [f32; TABLE_SIZE] {
+    let mut table = [0.0; TABLE_SIZE];
+    for (i, value) in table.iter_mut().enumerate() {
+        *value = (2.0 * PI * i as f32 / TABLE_SIZE as f32).sin();
+    }
+    table
+}
+
+#[derive(Clone)]
 pub struct LFO {
     pub rate: f32,
-    phase: f32,
-    pub sample_rate: f32
+    // in radians, kept in f64, see `set_high_precision`
+    phase: f64,
+    high_precision: bool,
+    pub sample_rate: f32,
+    waveform: Waveform,
+    previous_waveform: Waveform,
+    // samples left of the crossfade from `previous_waveform`
+    crossfade_remaining: usize,
+    crossfade_length: usize,
+    random_from: f32,
+    random_to: f32,
+    // picks the targets of the random shapes, the drift and the humanize noise
+    random: Xorshift,
+    drift_from: f32,
+    drift_to: f32,
+    // progress of the current drift glide, 0 to 1, and its speed relative to the rate
+    drift_position: f32,
+    drift_speed: f32,
+    // part of the cycle spent rising, 0.5 for the symmetric shapes
+    rise: f32,
+    table: [f32; TABLE_SIZE],
+    // 0 to 1, how far the slow humanize noise moves the phase
+    humanize: f32,
+    humanize_from: f32,
+    humanize_to: f32,
+    humanize_position: f32,
+    // scales `rate`, set by modulation sources such as the rate modulation LFO
+    rate_multiplier: f32,
 }
 
 impl LFO {
     pub fn new(sample_rate: f32, rate: f32) -> Self {
-        Self {
+        Self::new_with_phase(sample_rate, rate, 0.0)
+    }
+
+    pub fn new_with_phase(sample_rate: f32, rate: f32, phase: f32) -> Self {
+        // built here so the audio thread never has to
+        sine_lookup_table();
+
+        let mut lfo = Self {
             sample_rate,
             rate,
-            phase: 0.0,
+            phase: (phase as f64).rem_euclid(TAU),
+            high_precision: false,
+            waveform: Waveform::Sine,
+            previous_waveform: Waveform::Sine,
+            crossfade_remaining: 0,
+            crossfade_length: 0,
+            random_from: 0.0,
+            random_to: 0.0,
+            random: Xorshift::new(DEFAULT_SEED),
+            drift_from: 0.0,
+            drift_to: 0.0,
+            drift_position: 0.0,
+            drift_speed: 1.0,
+            rise: 0.5,
+            table: sine_table(),
+            humanize: 0.0,
+            humanize_from: 0.0,
+            humanize_to: 0.0,
+            humanize_position: 0.0,
+            rate_multiplier: 1.0,
+        };
+        lfo.reseed(DEFAULT_SEED);
+        lfo
+    }
+
+    /// restarts the random shapes, the drift and the humanize noise from `seed`, so they play
+    /// the same sequence every time they are reseeded with it. LFOs running side by side need
+    /// seeds of their own, or they all wander the same way
+    pub fn reseed(&mut self, seed: u32) {
+        self.random = Xorshift::new(seed);
+        self.random_from = 0.0;
+        self.random_to = self.random.next_value();
+        self.drift_from = 0.0;
+        self.drift_to = self.random.next_value();
+        self.drift_position = 0.0;
+        self.drift_speed = 1.0;
+        self.humanize_from = 0.0;
+        self.humanize_to = self.random.next_value();
+        self.humanize_position = 0.0;
+    }
+
+    pub fn phase(&self) -> f32 {
+        self.phase as f32
+    }
+
+    /// jumps to `phase` (in radians, wrapped into one cycle)
+    pub fn set_phase(&mut self, phase: f32) {
+        self.phase = (phase as f64).rem_euclid(TAU);
+    }
+
+    /// advances the phase in f64. In f32 the increment of a slow LFO is close to the rounding
+    /// step of the phase, so the rate comes out off by up to several percent and drifts apart
+    /// between LFOs that should stay locked
+    pub fn set_high_precision(&mut self, enabled: bool) {
+        self.high_precision = enabled;
+    }
+
+    /// replaces the table of the custom waveform. Shorter tables only replace the start of it,
+    /// values are clamped to -1..1
+    pub fn set_table(&mut self, table: &[f32]) {
+        for (value, new_value) in self.table.iter_mut().zip(table) {
+            *value = if new_value.is_finite() { new_value.clamp(-1.0, 1.0) } else { 0.0 };
         }
     }
 
-    pub fn new_random_phase(sample_rate: f32, rate: f32) -> Self {
-        let mut rng = rand::thread_rng();
-        Self {
-            sample_rate,
-            rate,
-            phase: rng.gen_range(0.0..(2.0 * PI))
+    /// emulates analog instability by slowly wandering the phase (and with it the rate) with a
+    /// noise of its own, 0 to 1
+    pub fn set_humanize(&mut self, amount: f32) {
+        self.humanize = amount.clamp(0.0, 1.0);
+    }
+
+    /// current phase offset of the humanize noise, as a fraction of the cycle
+    fn humanize_offset(&self) -> f32 {
+        let glide = 0.5 - 0.5 * (PI * self.humanize_position).cos();
+        let noise = self.humanize_from + (self.humanize_to - self.humanize_from) * glide;
+        self.humanize * MAX_HUMANIZE_PHASE * noise
+    }
+
+    /// scales the rate without touching `rate` itself, for modulating it
+    pub fn set_rate_multiplier(&mut self, multiplier: f32) {
+        self.rate_multiplier = multiplier;
+    }
+
+    /// skews the rise and fall times, -1 to 1. 0 leaves the shapes as they are, -1 turns the
+    /// triangle into a saw down and 1 into a saw up. The other shapes are bent the same way.
+    pub fn set_skew(&mut self, skew: f32) {
+        self.rise = 0.5 + 0.5 * skew.clamp(-1.0, 1.0) * MAX_SKEW;
+    }
+
+    /// the phase as a 0 to 1 fraction of the cycle, warped so the rising half of the cycle
+    /// takes `rise` of it and the falling half the rest
+    fn warped_phase(&self) -> f32 {
+        // counted from the minimum of the sine and triangle, which rise for the first half
+        let u = ((self.phase / TAU) as f32 + 0.25 + self.humanize_offset()).rem_euclid(1.0);
+        let u = if u < self.rise {
+            0.5 * u / self.rise
+        } else {
+            0.5 + 0.5 * (u - self.rise) / (1.0 - self.rise)
+        };
+        (u + 0.75).fract()
+    }
+
+    /// switches the shape, crossfading from the old one so the modulation doesn't jump
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        if waveform == self.waveform {
+            return;
         }
+        self.previous_waveform = self.waveform;
+        self.waveform = waveform;
+        self.crossfade_length = ((WAVEFORM_CROSSFADE_MS / 1000.0) * self.sample_rate).max(1.0) as usize;
+        self.crossfade_remaining = self.crossfade_length;
     }
 
-    pub fn new_with_phase(sample_rate: f32, rate: f32, phase: f32) -> Self {
-        Self {
-            sample_rate,
-            rate,
-            phase
+    /// value of `waveform` at the current phase, -1 to 1. All shapes start at 0 going up (square
+    /// and the random ones excepted), so they line up with the sine
+    fn shape(&self, waveform: Waveform) -> f32 {
+        let t = self.warped_phase();
+        match waveform {
+            Waveform::Sine => sine(t),
+            Waveform::Triangle => {
+                if t < 0.25 {
+                    4.0 * t
+                } else if t < 0.75 {
+                    2.0 - 4.0 * t
+                } else {
+                    4.0 * t - 4.0
+                }
+            }
+            Waveform::SawUp => 2.0 * (t + 0.5).fract() - 1.0,
+            Waveform::SawDown => 1.0 - 2.0 * (t + 0.5).fract(),
+            Waveform::Square => if t < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Random => {
+                let glide = 0.5 - 0.5 * (PI * t).cos();
+                self.random_from + (self.random_to - self.random_from) * glide
+            }
+            Waveform::SampleAndHold => self.random_from,
+            Waveform::Drift => {
+                let glide = 0.5 - 0.5 * (PI * self.drift_position).cos();
+                self.drift_from + (self.drift_to - self.drift_from) * glide
+            }
+            Waveform::Custom => {
+                // linear interpolation, wrapping around from the last point to the first
+                let position = t * TABLE_SIZE as f32;
+                let index = position as usize % TABLE_SIZE;
+                let frac = position.fract();
+                let next = self.table[(index + 1) % TABLE_SIZE];
+                self.table[index] + (next - self.table[index]) * frac
+            }
         }
     }
 
     /// returns next value of LFO. Values of <-1, 1>
     pub fn next_value(&mut self) -> f32 {
-        self.phase.sin()
+        self.current_value()
+    }
+
+    /// value at the current phase, without needing mutable access
+    pub fn current_value(&self) -> f32 {
+        let value = self.shape(self.waveform);
+        if self.crossfade_remaining == 0 {
+            return value;
+        }
+        let fade = self.crossfade_remaining as f32 / self.crossfade_length as f32;
+        value + (self.shape(self.previous_waveform) - value) * fade
     }
 
     pub fn next_value_range(&mut self, range: Range<f32>) -> f32 {
@@ -46,10 +287,66 @@ impl LFO {
         scaled
     }
 
+    /// advances the phase by one sample. The rate only sets the phase increment, so changing it
+    /// (or automating it) bends the frequency without the phase ever jumping
     pub fn update_lfo(&mut self) {
-        self.phase += 2.0 * std::f32::consts::PI * self.rate / self.sample_rate;
-        if self.phase > 2.0 * PI {
-            self.phase -= 2.0 * PI;
+        let rate = self.rate * self.rate_multiplier;
+        self.phase += TAU * rate as f64 / self.sample_rate as f64;
+        if !self.high_precision {
+            self.phase = self.phase as f32 as f64;
+        }
+        if self.phase >= TAU {
+            self.phase = self.phase.rem_euclid(TAU);
+            self.random_from = self.random_to;
+            self.random_to = self.random.next_value();
+        }
+
+        self.drift_position += self.drift_speed * rate / self.sample_rate;
+        if self.drift_position >= 1.0 {
+            self.drift_position = self.drift_position.fract();
+            self.drift_from = self.drift_to;
+            self.drift_to = self.random.next_value();
+            self.drift_speed = 1.0 / (1.0 + 0.5 * self.random.next_value());
+        }
+
+        self.humanize_position += HUMANIZE_RATE_HZ / self.sample_rate;
+        if self.humanize_position >= 1.0 {
+            self.humanize_position = self.humanize_position.fract();
+            self.humanize_from = self.humanize_to;
+            self.humanize_to = self.random.next_value();
+        }
+
+        self.crossfade_remaining = self.crossfade_remaining.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_sweeps_keep_the_phase_continuous() {
+        let sample_rate = 48000.0;
+        let mut lfo = LFO::new(sample_rate, 0.1);
+        lfo.set_high_precision(true);
+        let samples = 2 * sample_rate as usize;
+        let mut previous_phase = lfo.phase() as f64;
+        let mut previous_value = lfo.next_value();
+        for n in 0..samples {
+            // an exponential sweep from 0.1 to 5 Hz, changing every sample like smoothed automation
+            let rate = 0.1 * 50.0f32.powf(n as f32 / samples as f32);
+            lfo.rate = rate;
+            lfo.update_lfo();
+
+            let increment = (lfo.phase() as f64 - previous_phase).rem_euclid(TAU);
+            let expected = TAU * rate as f64 / sample_rate as f64;
+            assert!((increment - expected).abs() < 1e-6, "sample {n}: phase moved by {increment}, expected {expected}");
+            // the sine can't move further than its steepest slope allows at this rate, give or
+            // take the error of the lookup table
+            let value = lfo.next_value();
+            assert!((value - previous_value).abs() <= expected as f32 + 1e-5, "sample {n}: jumped from {previous_value} to {value}");
+            previous_phase = lfo.phase() as f64;
+            previous_value = value;
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use atomic_float::AtomicF32;
+
+/// most voices per channel the feed carries
+pub const VOICES: usize = crate::chorus::MAX_VOICES;
+/// number of published frames kept for the editor to draw
+pub const HISTORY: usize = 128;
+
+/// lock free ring buffer carrying the voices' modulation from the audio thread to the editor.
+/// The audio thread publishes one frame per block without allocating or locking, the editor
+/// reads the latest `HISTORY` frames whenever it redraws. There is a single writer, a reader
+/// racing it may see a frame that is half written, which doesn't matter for drawing.
+pub struct LfoFeed {
+    // the left channel voices followed by the right ones
+    frames: Vec<[AtomicF32; 2 * VOICES]>,
+    // number of voices per channel in the last published frame
+    voice_count: AtomicUsize,
+    // index of the frame the next publish writes to
+    write_index: AtomicUsize,
+    // bumped on every publish, lets the editor redraw only when something new arrived
+    generation: AtomicU32,
+}
+
+impl LfoFeed {
+    pub fn new() -> Self {
+        Self {
+            frames: (0..HISTORY).map(|_| core::array::from_fn(|_| AtomicF32::new(0.0))).collect(),
+            voice_count: AtomicUsize::new(0),
+            write_index: AtomicUsize::new(0),
+            generation: AtomicU32::new(0),
+        }
+    }
+
+    /// writes the modulation of each voice of both channels, -1 to 1, as the newest frame. Only
+    /// the audio thread publishes
+    pub fn publish(&self, left: &[f32], right: &[f32]) {
+        let index = self.write_index.load(Ordering::Relaxed);
+        let (left_slots, right_slots) = self.frames[index].split_at(VOICES);
+        for (slot, value) in left_slots.iter().zip(left).chain(right_slots.iter().zip(right)) {
+            slot.store(*value, Ordering::Relaxed);
+        }
+        self.voice_count.store(left.len().min(VOICES), Ordering::Relaxed);
+        self.write_index.store((index + 1) % HISTORY, Ordering::Release);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.voice_count.load(Ordering::Relaxed)
+    }
+
+    /// value of `voice` of `channel` (0 left, 1 right) `age` frames before the newest one, 0
+    /// being the newest
+    pub fn value(&self, channel: usize, voice: usize, age: usize) -> f32 {
+        let newest = self.write_index.load(Ordering::Acquire) + HISTORY - 1;
+        self.frames[(newest - age % HISTORY) % HISTORY][channel * VOICES + voice].load(Ordering::Relaxed)
+    }
+}
\ No newline at end of file
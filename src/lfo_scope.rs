@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use nih_plug_vizia::vizia::prelude::*;
+use nih_plug_vizia::vizia::vg;
+
+use crate::lfo_feed::{self, LfoFeed};
+
+/// draws the recent modulation of every voice from the `LfoFeed`, newest on the right. Left
+/// channel voices are drawn in blue, right channel ones in orange, from -1 (bottom) to 1 (top).
+pub struct LfoScope {
+    feed: Arc<LfoFeed>,
+}
+
+impl LfoScope {
+    pub fn new<L>(cx: &mut Context, feed: Arc<LfoFeed>, generation: L) -> Handle<Self>
+    where
+        L: Lens<Target = u32>,
+    {
+        Self { feed }
+            .build(cx, |_| {})
+            // the audio thread bumps the generation on every block, so this redraws while the
+            // plugin is processing and stays idle otherwise
+            .bind(generation, |mut handle, _| handle.cx.needs_redraw())
+    }
+}
+
+impl View for LfoScope {
+    fn element(&self) -> Option<&'static str> {
+        Some("lfo-scope")
+    }
+
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+        if bounds.w == 0.0 || bounds.h == 0.0 {
+            return;
+        }
+
+        let mut background = vg::Path::new();
+        background.rect(bounds.x, bounds.y, bounds.w, bounds.h);
+        canvas.fill_path(&mut background, &vg::Paint::color(vg::Color::rgbf(0.15, 0.15, 0.15)));
+
+        let step = bounds.w / (lfo_feed::HISTORY - 1) as f32;
+        let voice_count = self.feed.voice_count();
+        for (channel, voice) in (0..2).flat_map(|channel| (0..voice_count).map(move |voice| (channel, voice))) {
+            let mut line = vg::Path::new();
+            for age in 0..lfo_feed::HISTORY {
+                let value = self.feed.value(channel, voice, age).clamp(-1.0, 1.0);
+                let x = bounds.x + bounds.w - step * age as f32;
+                let y = bounds.y + bounds.h * (1.0 - value) / 2.0;
+                if age == 0 {
+                    line.move_to(x, y);
+                } else {
+                    line.line_to(x, y);
+                }
+            }
+
+            let color = if channel == 0 {
+                vg::Color::rgbf(0.4, 0.6, 0.9)
+            } else {
+                vg::Color::rgbf(0.9, 0.6, 0.3)
+            };
+            let mut paint = vg::Paint::color(color);
+            paint.set_line_width(1.0);
+            canvas.stroke_path(&mut line, &paint);
+        }
+    }
+}
\ No newline at end of file
@@ -1,33 +1,198 @@
-use chorus::Chorus;
 use nih_plug::prelude::*;
-use std::{sync::{Arc, mpsc::channel}, collections::VecDeque, env};
+use std::{sync::{Arc, RwLock, mpsc::channel, atomic::{AtomicBool, AtomicU32, Ordering}}, env};
 
 use nih_plug::prelude::*;
+use nih_plug::wrapper::state::PluginState;
 use nih_plug_vizia::ViziaState;
+use atomic_float::AtomicF32;
 
+mod bbd;
 mod delay;
+mod diffuser;
 mod lfo;
+mod lfo_feed;
+mod lfo_scope;
+mod multiband;
+mod oversample;
+mod dual;
+mod haas;
 mod editor;
 mod chorus;
+mod chaos;
 mod filter;
+mod envelope;
+mod ring_buffer;
+mod presets;
+mod random;
+mod reblock;
+mod state;
+mod stepseq;
+mod stereo;
+mod table_editor;
 
 struct ChorusPlugin {
     params: Arc<ChorusParams>,
     sample_rate: f32,
-    chorus: chorus::Chorus,
+    engines: dual::DualEngine,
+    haas: haas::Haas,
     output_hpf: filter::BiquadFilter,
+    // set when the current settings need more delay than the buffers can hold, shown in the editor
+    buffer_overflow: Arc<AtomicBool>,
+    // correlation of the left and right voices, shown in the editor
+    correlation: Arc<AtomicF32>,
+    // RMS of the feedback loop, shown as the resonance meter
+    feedback_energy: Arc<AtomicF32>,
+    // modulation of the voices, animated in the editor
+    lfo_feed: Arc<lfo_feed::LfoFeed>,
+    // set by the editor's reset button, the next process call resets the engine
+    reset_requested: Arc<AtomicBool>,
+    reblocker: reblock::Reblocker<FrameParams>,
+    // carries the wet output through the fixed blocks, only its outputs are used
+    wet_reblocker: reblock::Reblocker,
+    // the input delayed by the chorus latency, what the bypass lets through so toggling it
+    // doesn't shift the output against the host's delay compensation
+    bypass_delay: ring_buffer::RingBuffer<(f32, f32)>,
+    // the wet voices of the last `process_frame`, for the wet output
+    wet_output: (f32, f32),
+    // whether the latency of the fixed block size is currently reported to the host
+    fixed_blocks_active: bool,
+    // the latency of the chorus currently reported to the host, the dry delay of the
+    // through-zero flanger and the oversampling, of both engines in series
+    chorus_latency: u32,
+    // transport state of the previous block, to catch playback starting or looping
+    was_playing: bool,
+    last_position_samples: Option<i64>,
+    step_sequencer: stepseq::StepSequencer,
+    // last MIDI note played, kept after its note off so the rate doesn't jump back on release
+    key_track_note: Option<u8>,
+}
+
+/// the smoothed parameter values of one sample, read when the sample comes in and applied when
+/// it gets processed, which with fixed blocks is up to a block later
+#[derive(Clone, Copy, Default)]
+struct FrameParams {
+    delay_ms: f32,
+    depth: f32,
+    rate: f32,
+    feedback: f32,
+    wet: f32,
+    dry: f32,
+    motion_3d: f32,
+    delay_spread: f32,
+    pan_width: f32,
+    width: f32,
+    voice_taper: f32,
+    grain_detune: f32,
+    cross_feedback: f32,
+    feedback_lowpass: f32,
+    feedback_highpass: f32,
+    cross_feedback_lowpass: f32,
+    feedback_drive: f32,
+    diffusion: f32,
+    noise: f32,
+    tone: f32,
+    wet_highpass: f32,
+    bass_preserve: f32,
+    dynamic_depth: f32,
+    transient_duck: f32,
+    sidechain_depth: f32,
+    sidechain_wet: f32,
+    stereo_phase: f32,
+    voice_spread: f32,
+    rate_spread: f32,
+    lfo_shape: f32,
+    lfo_humanize: f32,
+    rate_mod: f32,
+    chaos_amount: f32,
+    chaos_speed: f32,
+    second_delay_ms: f32,
+    second_depth: f32,
+    second_rate: f32,
+    engine_blend: f32,
+    low_crossover: f32,
+    high_crossover: f32,
+    band_depths: [f32; 3],
+    band_rates: [f32; 3],
+    haas_delay: f32,
+    // the sidechain only exists with the second IO layout, the step value only with a step mode
+    sidechain_level: Option<f32>,
+    step_value: Option<f32>,
 }
 
 #[derive(Params)]
 struct ChorusParams {
     #[persist = "editor-state"]
     editor_state: Arc<ViziaState>,
+    // version of the saved state, see `state::migrate_state`
+    #[persist = "state-version"]
+    state_version: AtomicU32,
+    // points of the custom LFO waveform, drawn in the editor
+    #[persist = "lfo-table"]
+    pub lfo_table: RwLock<Vec<f32>>,
+    // set by the editor after drawing, the audio thread then copies the table into the LFOs
+    pub lfo_table_changed: AtomicBool,
+    // values of the step sequencer, -1 to 1, drawn in the editor like the LFO table
+    #[persist = "step-values"]
+    pub step_values: RwLock<Vec<f32>>,
+    pub step_values_changed: AtomicBool,
+    // LFO phase in degrees as of the last processed block, restored on load so the modulation
+    // picks up where it left off
+    #[persist = "lfo-phase"]
+    pub lfo_phase: AtomicF32,
 
     // parameters for chorus
     #[id = "depth"]
     pub depth: FloatParam,
+    // whether the depth is set in ms or as a share of the delay with `depth_percent`
+    #[id = "depth_mode"]
+    pub depth_mode: EnumParam<DepthMode>,
+    #[id = "depth_percent"]
+    pub depth_percent: FloatParam,
     #[id = "rate"]
     pub rate: FloatParam,
+    #[id = "waveform"]
+    pub waveform: EnumParam<LfoWaveform>,
+    #[id = "lfo_shape"]
+    pub lfo_shape: FloatParam,
+    // sweeps only above the delay time instead of around it
+    #[id = "unipolar"]
+    pub unipolar: BoolParam,
+    #[id = "lfo_humanize"]
+    pub lfo_humanize: FloatParam,
+    #[id = "rate_mod"]
+    pub rate_mod: FloatParam,
+    // chaotic modulation crossfaded with the LFOs
+    #[id = "chaos_amount"]
+    pub chaos_amount: FloatParam,
+    #[id = "chaos_speed"]
+    pub chaos_speed: FloatParam,
+    // locks the LFO to the host tempo, `rate_division` then sets the rate instead of `rate`
+    #[id = "rate_sync"]
+    pub rate_sync: BoolParam,
+    #[id = "rate_division"]
+    pub rate_division: EnumParam<NoteDivision>,
+    // scales the free running rate by the last MIDI note played, see `key_track_multiplier`
+    #[id = "key_track"]
+    pub key_track: FloatParam,
+    #[id = "stereo_phase"]
+    pub stereo_phase: FloatParam,
+    #[id = "voice_spread"]
+    pub voice_spread: FloatParam,
+    // detunes the voices' LFO rates against each other
+    #[id = "rate_spread"]
+    pub rate_spread: FloatParam,
+    // restarts all modulation at `retrigger_phase` whenever the host transport starts or loops,
+    // so a bounce sounds the same every time
+    #[id = "retrigger"]
+    pub retrigger: BoolParam,
+    #[id = "retrigger_phase"]
+    pub retrigger_phase: FloatParam,
+    #[id = "step_mode"]
+    pub step_mode: EnumParam<StepSeqMode>,
+    #[id = "mode"]
+    pub mode: EnumParam<ChorusMode>,
+    #[id = "voice_count"]
+    pub voice_count: IntParam,
     #[id = "delay_ms"]
     pub delay_ms: FloatParam,
     #[id = "feedback"]
@@ -36,6 +201,569 @@ struct ChorusParams {
     pub wet: FloatParam,
     #[id = "dry"]
     pub dry: FloatParam,
+    // replaces the wet and dry knobs with the single mix knob
+    #[id = "single_mix"]
+    pub single_mix: BoolParam,
+    #[id = "mix"]
+    pub mix: FloatParam,
+    #[id = "feedback_makeup"]
+    pub feedback_makeup: BoolParam,
+    // compensates the level change of the wet, dry and feedback settings
+    #[id = "auto_gain"]
+    pub auto_gain: BoolParam,
+    #[id = "mix_law"]
+    pub mix_law: EnumParam<MixLaw>,
+    #[id = "motion_3d"]
+    pub motion_3d: FloatParam,
+    // staggers the voices' base delays
+    #[id = "delay_spread"]
+    pub delay_spread: FloatParam,
+    // spreads the voices across the stereo field
+    #[id = "pan_width"]
+    pub pan_width: FloatParam,
+    // mid/side width of the wet signal
+    #[id = "width"]
+    pub width: FloatParam,
+    // shifts the level balance between the shortest and the longest voice
+    #[id = "voice_taper"]
+    pub voice_taper: FloatParam,
+    // pitch offset of the outermost voices in the granular mode
+    #[id = "grain_detune"]
+    pub grain_detune: FloatParam,
+    #[id = "process_mode"]
+    pub process_mode: EnumParam<ProcessMode>,
+    #[id = "rate_follows_delay"]
+    pub rate_follows_delay: BoolParam,
+    #[id = "mono_protect"]
+    pub mono_protect: BoolParam,
+    #[id = "dynamic_depth"]
+    pub dynamic_depth: FloatParam,
+    #[id = "transient_duck"]
+    pub transient_duck: FloatParam,
+    // how the sidechain input's level drives the depth and the level of the voices
+    #[id = "sidechain_depth"]
+    pub sidechain_depth: FloatParam,
+    #[id = "sidechain_wet"]
+    pub sidechain_wet: FloatParam,
+    #[id = "input_mode"]
+    pub input_mode: EnumParam<InputMode>,
+    // sums the output to mono, for checking what the voices cancel in mono
+    #[id = "mono_audition"]
+    pub mono_audition: BoolParam,
+    // a widening trick that cancels the voices in mono, the editor warns while it is on
+    #[id = "invert_right_wet"]
+    pub invert_right_wet: BoolParam,
+    // delays the right channel after the chorus, for plain doubling and widening
+    #[id = "haas_delay"]
+    pub haas_delay: FloatParam,
+    #[id = "per_voice_feedback"]
+    pub per_voice_feedback: BoolParam,
+    // feeds each channel's voices back into the other channel
+    #[id = "cross_feedback"]
+    pub cross_feedback: FloatParam,
+    #[id = "invert_feedback"]
+    pub invert_feedback: BoolParam,
+    // delays the dry path in the flanger mode so the voice sweeps through it, adds latency
+    #[id = "through_zero"]
+    pub through_zero: BoolParam,
+    #[id = "feedback_drive"]
+    pub feedback_drive: FloatParam,
+    // smears the voices towards a reverb-like texture
+    #[id = "diffusion"]
+    pub diffusion: FloatParam,
+    // tone filters inside the feedback loops, off at the ends of their ranges
+    #[id = "feedback_lowpass"]
+    pub feedback_lowpass: FloatParam,
+    #[id = "feedback_highpass"]
+    pub feedback_highpass: FloatParam,
+    // darkens only the feedback crossing over between the channels, on top of the low-pass
+    #[id = "cross_feedback_lowpass"]
+    pub cross_feedback_lowpass: FloatParam,
+    // bucket-brigade chip emulation of the delay lines
+    #[id = "bbd"]
+    pub bbd: BoolParam,
+    #[id = "bbd_stages"]
+    pub bbd_stages: IntParam,
+    // hiss in the wet path, following the input level
+    #[id = "noise"]
+    pub noise: FloatParam,
+    // tilt EQ of the wet voices, dark to bright
+    #[id = "tone"]
+    pub tone: FloatParam,
+    // keeps the lows out of the wet voices, off at the bottom of its range
+    #[id = "wet_highpass"]
+    pub wet_highpass: FloatParam,
+    // whether the tone and wet high-pass filter the voices' input or their output
+    #[id = "filter_position"]
+    pub filter_position: EnumParam<FilterPosition>,
+    // crossover below which the input bypasses the chorus, off at the bottom of its range
+    #[id = "bass_preserve"]
+    pub bass_preserve: FloatParam,
+    // splits the input into bands that each get a chorus of their own
+    #[id = "band_count"]
+    pub band_count: IntParam,
+    #[id = "low_crossover"]
+    pub low_crossover: FloatParam,
+    #[id = "high_crossover"]
+    pub high_crossover: FloatParam,
+    // the depth and rate of each band relative to the main knobs. Bands at different rates drift
+    // apart in phase, a retrigger or reloading the project lines them up again
+    #[id = "low_band_depth"]
+    pub low_band_depth: FloatParam,
+    #[id = "mid_band_depth"]
+    pub mid_band_depth: FloatParam,
+    #[id = "high_band_depth"]
+    pub high_band_depth: FloatParam,
+    #[id = "low_band_rate"]
+    pub low_band_rate: FloatParam,
+    #[id = "mid_band_rate"]
+    pub mid_band_rate: FloatParam,
+    #[id = "high_band_rate"]
+    pub high_band_rate: FloatParam,
+    // a second engine with its own delay, depth and rate, in series or parallel with the first
+    #[id = "engine_routing"]
+    pub engine_routing: EnumParam<EngineRouting>,
+    // how the delay lines read between samples, better sounding ones cost more CPU
+    #[id = "interpolation"]
+    pub interpolation: EnumParam<Quality>,
+    // runs the tape saturation at twice the sample rate, the longer anti-aliasing filters
+    // reject more aliasing for more latency
+    #[id = "oversampling"]
+    pub oversampling: EnumParam<Oversampling>,
+    #[id = "engine_blend"]
+    pub engine_blend: FloatParam,
+    #[id = "second_delay_ms"]
+    pub second_delay_ms: FloatParam,
+    #[id = "second_depth"]
+    pub second_depth: FloatParam,
+    #[id = "second_rate"]
+    pub second_rate: FloatParam,
+    // processes in internal blocks of reblock::BLOCK_SIZE samples no matter what the host sends,
+    // parameters then only apply at block boundaries. Adds BLOCK_SIZE samples of latency
+    #[id = "fixed_blocks"]
+    pub fixed_blocks: BoolParam,
+    #[id = "bypass"]
+    pub bypass: BoolParam,
+    // runs the delay lines, the feedback accumulator and the LFOs in f64, the loop filters,
+    // tape and BBD still round, see `Chorus::set_high_precision`. Kept under its old id so saved
+    // states load
+    #[id = "high_precision_feedback"]
+    pub high_precision: BoolParam,
+
+    // nih-plug ranges are fixed once the params are registered, so instead of swapping ranges
+    // the rate and depth knobs keep their normalized position and it gets mapped onto the wider
+    // EXTENDED_* range when this is enabled. The display formatters follow the same flag.
+    #[id = "extended_ranges"]
+    pub extended_ranges: BoolParam,
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ProcessMode {
+    #[name = "Stereo"]
+    Stereo,
+    // chorus only the mid signal, the side passes through untouched
+    #[name = "Mid Only"]
+    MidOnly,
+    // chorus only the side signal, widens without touching the center
+    #[name = "Side Only"]
+    SideOnly,
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InputMode {
+    #[name = "Stereo"]
+    Stereo,
+    // both channels are summed, so the engine gets a clean mono source to widen
+    #[name = "Mono Sum"]
+    MonoSum,
+    // each channel runs through its own mono chain, without anything linking the two but the LFOs
+    #[name = "Dual Mono"]
+    DualMono,
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LfoWaveform {
+    #[name = "Sine"]
+    Sine,
+    #[name = "Triangle"]
+    Triangle,
+    #[name = "Saw Up"]
+    SawUp,
+    #[name = "Saw Down"]
+    SawDown,
+    #[name = "Square"]
+    Square,
+    #[name = "Random"]
+    Random,
+    #[name = "S&H"]
+    SampleAndHold,
+    #[name = "Drift"]
+    Drift,
+    #[name = "Custom"]
+    Custom,
+}
+
+/// length of one LFO cycle when the rate is synced to the host tempo
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NoteDivision {
+    #[name = "1/1"]
+    Whole,
+    #[name = "1/1 D"]
+    WholeDotted,
+    #[name = "1/1 T"]
+    WholeTriplet,
+    #[name = "1/2"]
+    Half,
+    #[name = "1/2 D"]
+    HalfDotted,
+    #[name = "1/2 T"]
+    HalfTriplet,
+    #[name = "1/4"]
+    Quarter,
+    #[name = "1/4 D"]
+    QuarterDotted,
+    #[name = "1/4 T"]
+    QuarterTriplet,
+    #[name = "1/8"]
+    Eighth,
+    #[name = "1/8 D"]
+    EighthDotted,
+    #[name = "1/8 T"]
+    EighthTriplet,
+    #[name = "1/16"]
+    Sixteenth,
+    #[name = "1/16 D"]
+    SixteenthDotted,
+    #[name = "1/16 T"]
+    SixteenthTriplet,
+    #[name = "1/32"]
+    ThirtySecond,
+    #[name = "1/32 D"]
+    ThirtySecondDotted,
+    #[name = "1/32 T"]
+    ThirtySecondTriplet,
+}
+
+impl NoteDivision {
+    /// length of the division in quarter notes
+    fn beats(self) -> f32 {
+        match self {
+            NoteDivision::Whole => 4.0,
+            NoteDivision::WholeDotted => 6.0,
+            NoteDivision::WholeTriplet => 8.0 / 3.0,
+            NoteDivision::Half => 2.0,
+            NoteDivision::HalfDotted => 3.0,
+            NoteDivision::HalfTriplet => 4.0 / 3.0,
+            NoteDivision::Quarter => 1.0,
+            NoteDivision::QuarterDotted => 1.5,
+            NoteDivision::QuarterTriplet => 2.0 / 3.0,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::EighthDotted => 0.75,
+            NoteDivision::EighthTriplet => 1.0 / 3.0,
+            NoteDivision::Sixteenth => 0.25,
+            NoteDivision::SixteenthDotted => 0.375,
+            NoteDivision::SixteenthTriplet => 1.0 / 6.0,
+            NoteDivision::ThirtySecond => 0.125,
+            NoteDivision::ThirtySecondDotted => 0.1875,
+            NoteDivision::ThirtySecondTriplet => 1.0 / 12.0,
+        }
+    }
+
+    /// LFO rate in Hz for one cycle per division at `tempo` BPM
+    fn rate_hz(self, tempo: f32) -> f32 {
+        tempo / 60.0 / self.beats()
+    }
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChorusMode {
+    #[name = "Chorus"]
+    Chorus,
+    // a single fully wet voice, the mix controls are disabled in the editor
+    #[name = "Vibrato"]
+    Vibrato,
+    // the delay and depth knobs switch to the FLANGER_* ranges
+    #[name = "Flanger"]
+    Flanger,
+    #[name = "Ensemble"]
+    Ensemble,
+    #[name = "Dimension"]
+    Dimension,
+    // the Juno chorus buttons, the delay, depth and rate knobs are disabled in the editor
+    #[name = "Juno I"]
+    JunoI,
+    #[name = "Juno II"]
+    JunoII,
+    #[name = "Juno I+II"]
+    JunoBoth,
+    #[name = "Tape"]
+    Tape,
+    // the depth and rate knobs do nothing, the detune knob sets the pitch offset
+    #[name = "Granular"]
+    Granular,
+}
+
+impl From<ChorusMode> for chorus::Algorithm {
+    fn from(mode: ChorusMode) -> Self {
+        match mode {
+            ChorusMode::Chorus => chorus::Algorithm::Chorus,
+            ChorusMode::Vibrato => chorus::Algorithm::Vibrato,
+            ChorusMode::Flanger => chorus::Algorithm::Flanger,
+            ChorusMode::Ensemble => chorus::Algorithm::Ensemble,
+            ChorusMode::Dimension => chorus::Algorithm::Dimension,
+            ChorusMode::JunoI => chorus::Algorithm::Juno(chorus::JunoMode::I),
+            ChorusMode::JunoII => chorus::Algorithm::Juno(chorus::JunoMode::II),
+            ChorusMode::JunoBoth => chorus::Algorithm::Juno(chorus::JunoMode::Both),
+            ChorusMode::Tape => chorus::Algorithm::Tape,
+            ChorusMode::Granular => chorus::Algorithm::Granular,
+        }
+    }
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MixLaw {
+    #[name = "Linear"]
+    Linear,
+    #[name = "Equal Power"]
+    EqualPower,
+}
+
+impl From<MixLaw> for chorus::MixLaw {
+    fn from(mix_law: MixLaw) -> Self {
+        match mix_law {
+            MixLaw::Linear => chorus::MixLaw::Linear,
+            MixLaw::EqualPower => chorus::MixLaw::EqualPower,
+        }
+    }
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FilterPosition {
+    #[name = "Pre"]
+    Pre,
+    #[name = "Post"]
+    Post,
+}
+
+impl From<FilterPosition> for chorus::FilterPosition {
+    fn from(position: FilterPosition) -> Self {
+        match position {
+            FilterPosition::Pre => chorus::FilterPosition::Pre,
+            FilterPosition::Post => chorus::FilterPosition::Post,
+        }
+    }
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DepthMode {
+    #[name = "ms"]
+    Milliseconds,
+    // the depth follows the delay, so automating the delay keeps the sweep in proportion
+    #[name = "% of Delay"]
+    Percentage,
+}
+
+// from the cheapest to the best sounding
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Quality {
+    #[name = "Nearest"]
+    Nearest,
+    #[name = "Linear"]
+    Linear,
+    #[name = "All-Pass"]
+    AllPass,
+    #[name = "Cubic"]
+    Cubic,
+    #[name = "Sinc"]
+    Sinc,
+}
+
+impl From<Quality> for delay::Interpolation {
+    fn from(quality: Quality) -> Self {
+        match quality {
+            Quality::Nearest => delay::Interpolation::Nearest,
+            Quality::Linear => delay::Interpolation::Linear,
+            Quality::AllPass => delay::Interpolation::AllPass,
+            Quality::Cubic => delay::Interpolation::Hermite,
+            Quality::Sinc => delay::Interpolation::Sinc,
+        }
+    }
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Oversampling {
+    #[name = "Off"]
+    Off,
+    #[name = "2x Short"]
+    Short,
+    #[name = "2x Long"]
+    Long,
+}
+
+impl From<Oversampling> for Option<oversample::FilterOrder> {
+    fn from(oversampling: Oversampling) -> Self {
+        match oversampling {
+            Oversampling::Off => None,
+            Oversampling::Short => Some(oversample::FilterOrder::Short),
+            Oversampling::Long => Some(oversample::FilterOrder::Long),
+        }
+    }
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EngineRouting {
+    #[name = "Off"]
+    Off,
+    #[name = "Serial"]
+    Serial,
+    #[name = "Parallel"]
+    Parallel,
+}
+
+impl From<EngineRouting> for dual::Routing {
+    fn from(routing: EngineRouting) -> Self {
+        match routing {
+            EngineRouting::Off => dual::Routing::Off,
+            EngineRouting::Serial => dual::Routing::Serial,
+            EngineRouting::Parallel => dual::Routing::Parallel,
+        }
+    }
+}
+
+#[derive(Enum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StepSeqMode {
+    #[name = "Off"]
+    Off,
+    #[name = "Replace LFO"]
+    Replace,
+    #[name = "Add to LFO"]
+    Sum,
+}
+
+impl From<StepSeqMode> for Option<stepseq::StepMode> {
+    fn from(mode: StepSeqMode) -> Self {
+        match mode {
+            StepSeqMode::Off => None,
+            StepSeqMode::Replace => Some(stepseq::StepMode::Replace),
+            StepSeqMode::Sum => Some(stepseq::StepMode::Sum),
+        }
+    }
+}
+
+impl From<LfoWaveform> for lfo::Waveform {
+    fn from(waveform: LfoWaveform) -> Self {
+        match waveform {
+            LfoWaveform::Sine => lfo::Waveform::Sine,
+            LfoWaveform::Triangle => lfo::Waveform::Triangle,
+            LfoWaveform::SawUp => lfo::Waveform::SawUp,
+            LfoWaveform::SawDown => lfo::Waveform::SawDown,
+            LfoWaveform::Square => lfo::Waveform::Square,
+            LfoWaveform::Random => lfo::Waveform::Random,
+            LfoWaveform::SampleAndHold => lfo::Waveform::SampleAndHold,
+            LfoWaveform::Drift => lfo::Waveform::Drift,
+            LfoWaveform::Custom => lfo::Waveform::Custom,
+        }
+    }
+}
+
+// skewed so the subtle settings at the low end, where most usable values are, get more of the knob
+const DEPTH_RANGE: FloatRange = FloatRange::Skewed { min: 0.0, max: 25.0, factor: 0.5 };
+const EXTENDED_DEPTH_RANGE: FloatRange = FloatRange::Skewed { min: 0.0, max: 50.0, factor: 0.5 };
+const RATE_RANGE: FloatRange = FloatRange::Skewed { min: 0.02, max: 10.0, factor: 0.3 };
+const EXTENDED_RATE_RANGE: FloatRange = FloatRange::Skewed { min: 0.02, max: 40.0, factor: 0.2 };
+const DELAY_RANGE: FloatRange = FloatRange::Linear { min: 0.1, max: 50.0 };
+// the flanger mode maps the delay and depth knobs onto these, like the extended ranges. Short
+// delays with sub-millisecond resolution, the feedback already goes up to 99.9%
+const FLANGER_DELAY_RANGE: FloatRange = FloatRange::Skewed { min: 0.05, max: 5.0, factor: 0.5 };
+const FLANGER_DEPTH_RANGE: FloatRange = FloatRange::Skewed { min: 0.0, max: 5.0, factor: 0.5 };
+// key tracking leaves the rate as it is at this note (middle C)
+const KEY_TRACK_REFERENCE_NOTE: f32 = 60.0;
+// key tracked rates stop at the top of the extended rate range, above it the chorus turns into FM
+const KEY_TRACK_MAX_RATE: f32 = 40.0;
+
+/// rate multiplier for the given note, at full key tracking it doubles the rate every octave
+/// above the reference note and halves it every octave below
+fn key_track_multiplier(note: u8, amount: f32) -> f32 {
+    ((note as f32 - KEY_TRACK_REFERENCE_NOTE) / 12.0 * amount).exp2()
+}
+
+/// (wet, dry) levels of the single mix knob, 0 to 1. The dry level stays full up to the middle
+/// and the wet level from it on, so the middle is an even mix of both at full level
+fn mix_levels(mix: f32) -> (f32, f32) {
+    ((2.0 * mix).min(1.0), (2.0 - 2.0 * mix).min(1.0))
+}
+
+/// maps a value of the default range onto the extended range, keeping the knob position
+fn extend_range(value: f32, range: &FloatRange, extended: &FloatRange) -> f32 {
+    extended.unnormalize(range.normalize(value))
+}
+
+/// like `formatters::v2s_f32_rounded`, but shows the extended value while `extended` is set
+fn v2s_extendable(
+    range: FloatRange,
+    extended_range: FloatRange,
+    extended: Arc<AtomicBool>,
+) -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        let value = if extended.load(Ordering::Relaxed) {
+            extend_range(value, &range, &extended_range)
+        } else {
+            value
+        };
+        format!("{:.2}", value)
+    })
+}
+
+/// the inverse of `v2s_extendable`, reads a typed value as an extended one while `extended` is
+/// set and maps it back onto the default range
+fn s2v_extendable(
+    range: FloatRange,
+    extended_range: FloatRange,
+    extended: Arc<AtomicBool>,
+) -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
+    Arc::new(move |string| {
+        let value = string.trim().parse::<f32>().ok()?;
+        if extended.load(Ordering::Relaxed) {
+            Some(extend_range(value, &extended_range, &range))
+        } else {
+            Some(value)
+        }
+    })
+}
+
+/// shows the value mapped onto the flanger range while `flanger` is set and falls back to
+/// `formatter` otherwise
+fn v2s_flanger(
+    range: FloatRange,
+    flanger_range: FloatRange,
+    flanger: Arc<AtomicBool>,
+    formatter: Arc<dyn Fn(f32) -> String + Send + Sync>,
+) -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        if flanger.load(Ordering::Relaxed) {
+            format!("{:.2}", extend_range(value, &range, &flanger_range))
+        } else {
+            formatter(value)
+        }
+    })
+}
+
+/// the inverse of `v2s_flanger`, reads a typed value on the flanger range while `flanger` is
+/// set and falls back to `parser` otherwise
+fn s2v_flanger(
+    range: FloatRange,
+    flanger_range: FloatRange,
+    flanger: Arc<AtomicBool>,
+    parser: Arc<dyn Fn(&str) -> Option<f32> + Send + Sync>,
+) -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
+    Arc::new(move |string| {
+        if flanger.load(Ordering::Relaxed) {
+            let value = string.trim().parse::<f32>().ok()?;
+            Some(extend_range(value, &flanger_range, &range))
+        } else {
+            parser(string)
+        }
+    })
 }
 
 impl Default for ChorusPlugin {
@@ -43,31 +771,176 @@ impl Default for ChorusPlugin {
         Self {
             params: Arc::new(ChorusParams::default()),
             sample_rate: 44100.0,
-            chorus: Chorus::new(44100.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0),
+            engines: dual::DualEngine::new(44100.0),
+            haas: haas::Haas::new(44100.0),
             output_hpf: filter::BiquadFilter::new(),
+            buffer_overflow: Arc::new(AtomicBool::new(false)),
+            correlation: Arc::new(AtomicF32::new(1.0)),
+            feedback_energy: Arc::new(AtomicF32::new(0.0)),
+            lfo_feed: Arc::new(lfo_feed::LfoFeed::new()),
+            reset_requested: Arc::new(AtomicBool::new(false)),
+            reblocker: reblock::Reblocker::new(),
+            wet_reblocker: reblock::Reblocker::new(),
+            bypass_delay: ring_buffer::RingBuffer::new(dual::latency_buffer_len(44100.0)),
+            wet_output: (0.0, 0.0),
+            fixed_blocks_active: false,
+            chorus_latency: 0,
+            was_playing: false,
+            last_position_samples: None,
+            step_sequencer: stepseq::StepSequencer::new(44100.0),
+            key_track_note: None,
         }
     }
 }
 
 impl Default for ChorusParams {
     fn default() -> Self {
+        let extended_ranges = Arc::new(AtomicBool::new(false));
+        let flanger_ranges = Arc::new(AtomicBool::new(false));
+
         Self {
             editor_state: editor::default_state(),
+            state_version: AtomicU32::new(state::STATE_VERSION),
+            lfo_table: RwLock::new(lfo::sine_table().to_vec()),
+            lfo_table_changed: AtomicBool::new(false),
+            step_values: RwLock::new(vec![0.0; stepseq::STEPS]),
+            step_values_changed: AtomicBool::new(false),
+            lfo_phase: AtomicF32::new(0.0),
             // implement depth, rate, delay_ms, feedback, wet parameters
             // DEPTH
-            depth: FloatParam::new("Depth", 5.0, FloatRange::Linear { min: 0.0, max: 25.0 })
+            depth: FloatParam::new("Depth", 5.0, DEPTH_RANGE)
             .with_unit("ms")
-            .with_value_to_string(formatters::v2s_f32_rounded(2)),
-            
+            .with_value_to_string(v2s_flanger(
+                DEPTH_RANGE,
+                FLANGER_DEPTH_RANGE,
+                flanger_ranges.clone(),
+                v2s_extendable(DEPTH_RANGE, EXTENDED_DEPTH_RANGE, extended_ranges.clone()),
+            ))
+            .with_string_to_value(s2v_flanger(
+                DEPTH_RANGE,
+                FLANGER_DEPTH_RANGE,
+                flanger_ranges.clone(),
+                s2v_extendable(DEPTH_RANGE, EXTENDED_DEPTH_RANGE, extended_ranges.clone()),
+            )),
+
+            // DEPTH MODE
+            depth_mode: EnumParam::new("Depth Mode", DepthMode::Milliseconds),
+            // at 100% the sweep reaches from no delay to twice the delay, the most it can without
+            // being clamped
+            depth_percent: FloatParam::new("Depth %", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
             // RATE
-            rate: FloatParam::new("Rate", 0.5, FloatRange::Skewed { min: 0.02, max: 10.0, factor: 0.3 })
+            rate: FloatParam::new("Rate", 0.5, RATE_RANGE)
+            // glide between rates instead of stepping, the LFO phase stays continuous either way
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
             .with_unit("Hz")
+            .with_value_to_string(v2s_extendable(RATE_RANGE, EXTENDED_RATE_RANGE, extended_ranges.clone()))
+            .with_string_to_value(s2v_extendable(RATE_RANGE, EXTENDED_RATE_RANGE, extended_ranges.clone())),
+
+            // WAVEFORM
+            waveform: EnumParam::new("Waveform", LfoWaveform::Sine),
+
+            // SHAPE
+            // skews the rise and fall of the waveform, -100% is a saw down and 100% a saw up
+            lfo_shape: FloatParam::new("Shape", 0.0, FloatRange::Linear { min: -1.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // UNIPOLAR
+            unipolar: BoolParam::new("Unipolar", false),
+
+            // RATE MODULATION
+            // a slow second LFO bending the rate, for modulation that doesn't repeat
+            rate_mod: FloatParam::new("Rate Mod", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // CHAOS
+            chaos_amount: FloatParam::new("Chaos", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            chaos_speed: FloatParam::new("Chaos Speed", 0.5, FloatRange::Skewed { min: 0.05, max: 5.0, factor: 0.3 })
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
             .with_value_to_string(formatters::v2s_f32_rounded(2)),
 
+            // HUMANIZE
+            // named so it doesn't get mixed up with the drift waveform
+            lfo_humanize: FloatParam::new("Humanize", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // TEMPO SYNC
+            rate_sync: BoolParam::new("Sync", false),
+            rate_division: EnumParam::new("Division", NoteDivision::Quarter),
+
+            // KEY TRACK
+            key_track: FloatParam::new("Key Track", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // STEREO PHASE
+            stereo_phase: FloatParam::new("Stereo Phase", 90.0, FloatRange::Linear { min: 0.0, max: 180.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("°")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            // VOICE PHASE SPREAD
+            voice_spread: FloatParam::new("Voice Phase Spread", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // RATE SPREAD
+            rate_spread: FloatParam::new("Rate Spread", 0.0, FloatRange::Linear { min: 0.0, max: 0.2 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // RETRIGGER
+            retrigger: BoolParam::new("Retrigger", false),
+            retrigger_phase: FloatParam::new("Start Phase", 0.0, FloatRange::Linear { min: 0.0, max: 360.0 })
+            .with_unit("°")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            // STEP SEQUENCER
+            step_mode: EnumParam::new("Step Sequencer", StepSeqMode::Off),
+
+            // MODE
+            mode: EnumParam::new("Mode", ChorusMode::Chorus)
+            .with_callback({
+                let flanger_ranges = flanger_ranges.clone();
+                Arc::new(move |mode| flanger_ranges.store(mode == ChorusMode::Flanger, Ordering::Relaxed))
+            }),
+
+            // VOICES
+            voice_count: IntParam::new("Voices", 3, IntRange::Linear { min: 1, max: chorus::MAX_VOICES as i32 }),
+
             // DELAY
-            delay_ms: FloatParam::new("Delay", 15.0, FloatRange::Linear { min: 0.1, max: 50.0 })
+            delay_ms: FloatParam::new("Delay", 15.0, DELAY_RANGE)
             .with_unit("ms")
-            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            .with_value_to_string(v2s_flanger(DELAY_RANGE, FLANGER_DELAY_RANGE, flanger_ranges.clone(), formatters::v2s_f32_rounded(2)))
+            .with_string_to_value(s2v_flanger(
+                DELAY_RANGE,
+                FLANGER_DELAY_RANGE,
+                flanger_ranges,
+                Arc::new(|string: &str| string.trim().parse::<f32>().ok()),
+            )),
 
             // FEEDBACK
             feedback: FloatParam::new("Feedback", 0.0, FloatRange::Linear { min: 0.0, max: 0.999 })
@@ -75,18 +948,606 @@ impl Default for ChorusParams {
             .with_value_to_string(formatters::v2s_f32_percentage(1))
             .with_string_to_value(formatters::s2v_f32_percentage()),
             // WET
-            wet: FloatParam::new("Wet", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            wet: FloatParam::new("Wet", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
             .with_unit("%")
             .with_value_to_string(formatters::v2s_f32_percentage(1))
             .with_string_to_value(formatters::s2v_f32_percentage()),
 
             // DRY
-            dry: FloatParam::new("Dry", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            dry: FloatParam::new("Dry", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // MIX
+            single_mix: BoolParam::new("Single Mix", false),
+            mix: FloatParam::new("Mix", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // FEEDBACK MAKEUP
+            feedback_makeup: BoolParam::new("FB Makeup", false),
+
+            // AUTO GAIN
+            auto_gain: BoolParam::new("Auto Gain", false),
+
+            // MIX LAW
+            mix_law: EnumParam::new("Mix Law", MixLaw::Linear),
+
+            // 3D
+            motion_3d: FloatParam::new("3D", 0.0, FloatRange::Linear { min: 0.0, max: 5.0 })
+            .with_unit("ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            // DELAY SPREAD
+            delay_spread: FloatParam::new("Spread", 0.0, FloatRange::Linear { min: 0.0, max: 10.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            // PAN WIDTH
+            pan_width: FloatParam::new("Pan Width", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // WIDTH
+            width: FloatParam::new("Width", 1.0, FloatRange::Linear { min: 0.0, max: chorus::MAX_WIDTH })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // VOICE TAPER
+            voice_taper: FloatParam::new("Voice Taper", 0.0, FloatRange::Linear { min: -1.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // DETUNE
+            grain_detune: FloatParam::new("Detune", 10.0, FloatRange::Linear { min: 0.0, max: chorus::MAX_GRAIN_DETUNE_CENTS })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("ct")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            // PROCESS
+            process_mode: EnumParam::new("Process", ProcessMode::Stereo),
+
+            // RATE FOLLOWS DELAY
+            rate_follows_delay: BoolParam::new("Rate Follows Delay", false),
+
+            // MONO PROTECT
+            mono_protect: BoolParam::new("Mono Protect", false),
+
+            // DYNAMIC DEPTH
+            // positive amounts open the depth up when playing louder, negative ones close it
+            dynamic_depth: FloatParam::new("Dynamic Depth", 0.0, FloatRange::Linear { min: -1.0, max: 1.0 })
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // TRANSIENT DUCK
+            transient_duck: FloatParam::new("Transient Duck", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // SIDECHAIN
+            // positive amounts let a loud sidechain open the chorus up, negative ones duck it
+            sidechain_depth: FloatParam::new("Sidechain > Depth", 0.0, FloatRange::Linear { min: -1.0, max: 1.0 })
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            sidechain_wet: FloatParam::new("Sidechain > Wet", 0.0, FloatRange::Linear { min: -1.0, max: 1.0 })
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // INPUT MODE
+            input_mode: EnumParam::new("Input", InputMode::Stereo),
+
+            // MONO AUDITION
+            mono_audition: BoolParam::new("Mono", false),
+
+            // INVERT RIGHT WET
+            invert_right_wet: BoolParam::new("Invert Right Wet", false),
+
+            // HAAS
+            haas_delay: FloatParam::new("Haas", 0.0, FloatRange::Linear { min: 0.0, max: haas::MAX_HAAS_MS })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            // PER-VOICE FEEDBACK
+            per_voice_feedback: BoolParam::new("Per-Voice Feedback", false),
+
+            // CROSS FEEDBACK
+            cross_feedback: FloatParam::new("Cross Feedback", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // FEEDBACK POLARITY
+            invert_feedback: BoolParam::new("Invert Feedback", false),
+
+            // THROUGH ZERO
+            through_zero: BoolParam::new("Through Zero", false),
+
+            // FEEDBACK DRIVE
+            feedback_drive: FloatParam::new("Feedback Drive", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
             .with_unit("%")
             .with_value_to_string(formatters::v2s_f32_percentage(1))
             .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // DIFFUSION
+            diffusion: FloatParam::new("Diffusion", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // FEEDBACK TONE
+            feedback_lowpass: FloatParam::new(
+                "FB Low-Pass",
+                chorus::FEEDBACK_LOWPASS_OFF_HZ,
+                FloatRange::Skewed { min: 200.0, max: chorus::FEEDBACK_LOWPASS_OFF_HZ, factor: FloatRange::skew_factor(-2.0) },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            feedback_highpass: FloatParam::new(
+                "FB High-Pass",
+                chorus::FEEDBACK_HIGHPASS_OFF_HZ,
+                FloatRange::Skewed { min: chorus::FEEDBACK_HIGHPASS_OFF_HZ, max: 2000.0, factor: FloatRange::skew_factor(-2.0) },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            cross_feedback_lowpass: FloatParam::new(
+                "Cross FB Low-Pass",
+                chorus::FEEDBACK_LOWPASS_OFF_HZ,
+                FloatRange::Skewed { min: 200.0, max: chorus::FEEDBACK_LOWPASS_OFF_HZ, factor: FloatRange::skew_factor(-2.0) },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            // BBD
+            bbd: BoolParam::new("BBD", false),
+            bbd_stages: IntParam::new(
+                "BBD Stages",
+                bbd::DEFAULT_STAGES as i32,
+                IntRange::Linear { min: bbd::MIN_STAGES as i32, max: bbd::MAX_STAGES as i32 },
+            ),
+
+            // NOISE
+            noise: FloatParam::new("Noise", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // TONE
+            tone: FloatParam::new("Tone", 0.0, FloatRange::Linear { min: -1.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // WET HIGH-PASS
+            wet_highpass: FloatParam::new(
+                "Wet HP",
+                chorus::WET_HIGHPASS_OFF_HZ,
+                FloatRange::Skewed { min: chorus::WET_HIGHPASS_OFF_HZ, max: 1000.0, factor: FloatRange::skew_factor(-2.0) },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            // FILTER POSITION
+            filter_position: EnumParam::new("Filter Position", FilterPosition::Post),
+
+            // BASS PRESERVE
+            bass_preserve: FloatParam::new(
+                "Bass Preserve",
+                chorus::BASS_PRESERVE_OFF_HZ,
+                FloatRange::Skewed { min: chorus::BASS_PRESERVE_OFF_HZ, max: 500.0, factor: FloatRange::skew_factor(-2.0) },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            // MULTIBAND
+            band_count: IntParam::new("Bands", 1, IntRange::Linear { min: 1, max: multiband::MAX_BANDS as i32 }),
+            low_crossover: FloatParam::new(
+                "Low X-Over",
+                multiband::DEFAULT_LOW_CROSSOVER_HZ,
+                FloatRange::Skewed { min: 50.0, max: 1000.0, factor: FloatRange::skew_factor(-1.0) },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            high_crossover: FloatParam::new(
+                "High X-Over",
+                multiband::DEFAULT_HIGH_CROSSOVER_HZ,
+                FloatRange::Skewed { min: 1000.0, max: 10000.0, factor: FloatRange::skew_factor(-1.0) },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            low_band_depth: FloatParam::new("Low Depth", 1.0, FloatRange::Linear { min: 0.0, max: 2.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            mid_band_depth: FloatParam::new("Mid Depth", 1.0, FloatRange::Linear { min: 0.0, max: 2.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            high_band_depth: FloatParam::new("High Depth", 1.0, FloatRange::Linear { min: 0.0, max: 2.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            low_band_rate: FloatParam::new(
+                "Low Rate",
+                1.0,
+                FloatRange::Skewed { min: 0.25, max: 4.0, factor: FloatRange::skew_factor(-1.0) },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("x")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            mid_band_rate: FloatParam::new(
+                "Mid Rate",
+                1.0,
+                FloatRange::Skewed { min: 0.25, max: 4.0, factor: FloatRange::skew_factor(-1.0) },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("x")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            high_band_rate: FloatParam::new(
+                "High Rate",
+                1.0,
+                FloatRange::Skewed { min: 0.25, max: 4.0, factor: FloatRange::skew_factor(-1.0) },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("x")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            // QUALITY
+            interpolation: EnumParam::new("Quality", Quality::Linear),
+            oversampling: EnumParam::new("Oversampling", Oversampling::Off),
+
+            // SECOND ENGINE
+            engine_routing: EnumParam::new("Engine B", EngineRouting::Off),
+            engine_blend: FloatParam::new("A/B Blend", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            second_delay_ms: FloatParam::new("B Delay", 25.0, DELAY_RANGE)
+            .with_unit("ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            second_depth: FloatParam::new("B Depth", 3.0, DEPTH_RANGE)
+            .with_unit("ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            second_rate: FloatParam::new("B Rate", 0.3, RATE_RANGE)
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            // FIXED BLOCKS
+            fixed_blocks: BoolParam::new("Fixed Block Size", false),
+
+            // BYPASS
+            bypass: BoolParam::new("Bypass", false).make_bypass(),
+
+            // HIGH PRECISION FEEDBACK
+            high_precision: BoolParam::new("High Precision", false),
+
+            // EXTENDED RANGES
+            extended_ranges: BoolParam::new("Extended Ranges", false)
+            .with_callback(Arc::new(move |value| extended_ranges.store(value, Ordering::Relaxed))),
+        }
+    }
+}
+
+impl ChorusPlugin {
+    /// one stereo frame through the input stage, the chorus and the output filter
+    fn process_frame(&mut self, left: f32, right: f32) -> (f32, f32) {
+        // kept running, so bypassing picks up right where the audio is
+        self.bypass_delay.push((left, right));
+
+        let input_mode = self.params.input_mode.value();
+        let (left, right) = if input_mode == InputMode::MonoSum {
+            let mono = (left + right) / 2.0;
+            (mono, mono)
+        } else {
+            (left, right)
+        };
+
+        let (left, right) = match self.params.process_mode.value() {
+            ProcessMode::Stereo if input_mode == InputMode::DualMono => self.engines.process_dual_mono(left, right),
+            ProcessMode::Stereo => self.engines.process_stereo(left, right),
+            ProcessMode::MidOnly => {
+                let (mid, side) = stereo::encode_mid_side(left, right);
+                stereo::decode_mid_side(self.engines.process_left(mid), side)
+            }
+            ProcessMode::SideOnly => {
+                let (mid, side) = stereo::encode_mid_side(left, right);
+                stereo::decode_mid_side(mid, self.engines.process_right(side))
+            }
+        };
+
+        let (wet_left, wet_right) = self.engines.wet_output();
+        self.wet_output = match self.params.process_mode.value() {
+            ProcessMode::Stereo => (wet_left, wet_right),
+            ProcessMode::MidOnly => stereo::decode_mid_side(wet_left, 0.0),
+            ProcessMode::SideOnly => stereo::decode_mid_side(0.0, wet_right),
+        };
+
+        let (left, right) = self.haas.process(left, right);
+        let (left, right) = (self.output_hpf.process_left(left), self.output_hpf.process_right(right));
+
+        if self.params.mono_audition.value() {
+            let mono = (left + right) / 2.0;
+            (mono, mono)
+        } else {
+            (left, right)
+        }
+    }
+
+    /// settings that only change per host buffer
+    fn apply_block_settings(&mut self, sidechain_connected: bool, synced: bool) {
+        let step_mode: Option<stepseq::StepMode> = self.params.step_mode.value().into();
+        self.engines.multiband_mut().set_band_count(self.params.band_count.value() as usize);
+        self.engines.set_routing(self.params.engine_routing.value().into());
+        for chorus in self.engines.choruses_mut() {
+            chorus.set_step_mode(step_mode);
+            chorus.set_sidechain_connected(sidechain_connected);
+            chorus.set_feedback_makeup(self.params.feedback_makeup.value());
+            chorus.set_auto_gain(self.params.auto_gain.value());
+            chorus.set_mix_law(self.params.mix_law.value().into());
+            chorus.set_filter_position(self.params.filter_position.value().into());
+            // scaling a synced rate would pull it off the grid
+            chorus.set_rate_follows_delay(self.params.rate_follows_delay.value() && !synced);
+            chorus.set_mono_protect(self.params.mono_protect.value());
+            chorus.set_invert_right_wet(self.params.invert_right_wet.value());
+            chorus.set_per_voice_feedback(self.params.per_voice_feedback.value());
+            chorus.set_waveform(self.params.waveform.value().into());
+            chorus.set_unipolar(self.params.unipolar.value());
+            chorus.set_voice_count(self.params.voice_count.value() as usize);
+            chorus.set_algorithm(self.params.mode.value().into());
+            chorus.set_high_precision(self.params.high_precision.value());
+            chorus.set_invert_feedback(self.params.invert_feedback.value());
+            chorus.set_through_zero(self.params.through_zero.value());
+            chorus.set_interpolation(self.params.interpolation.value().into());
+            chorus.set_oversampling(self.params.oversampling.value().into());
+            chorus.set_bbd(self.params.bbd.value());
+            chorus.set_bbd_stages(self.params.bbd_stages.value() as usize);
+        }
+    }
+
+    /// advances the smoothers and the step sequencer by one sample
+    fn next_frame_params(&mut self, synced_rate: Option<f32>, sidechain_level: Option<f32>) -> FrameParams {
+        let mut depth = self.params.depth.smoothed.next();
+        let mut rate = self.params.rate.smoothed.next();
+        let flanger = self.params.mode.value() == ChorusMode::Flanger;
+        if flanger {
+            depth = extend_range(depth, &DEPTH_RANGE, &FLANGER_DEPTH_RANGE);
+        }
+        if self.params.extended_ranges.value() {
+            if !flanger {
+                depth = extend_range(depth, &DEPTH_RANGE, &EXTENDED_DEPTH_RANGE);
+            }
+            rate = extend_range(rate, &RATE_RANGE, &EXTENDED_RATE_RANGE);
+        }
+        let key_track = self.params.key_track.smoothed.next();
+        if let Some(synced_rate) = synced_rate {
+            rate = synced_rate;
+        } else if let Some(note) = self.key_track_note {
+            // like rate follow, key tracking would pull a synced rate off the grid
+            rate = (rate * key_track_multiplier(note, key_track)).min(KEY_TRACK_MAX_RATE);
+        }
+        let mut delay_ms = self.params.delay_ms.smoothed.next();
+        if flanger {
+            delay_ms = extend_range(delay_ms, &DELAY_RANGE, &FLANGER_DELAY_RANGE);
+        }
+        let depth_percent = self.params.depth_percent.smoothed.next();
+        if self.params.depth_mode.value() == DepthMode::Percentage {
+            depth = depth_percent * 2.0 * delay_ms;
+        }
+        let feedback = self.params.feedback.smoothed.next();
+        let wet = self.params.wet.smoothed.next();
+        let dry = self.params.dry.smoothed.next();
+        let mix = self.params.mix.smoothed.next();
+        let (wet, dry) = if self.params.single_mix.value() { mix_levels(mix) } else { (wet, dry) };
+
+        let mut frame = FrameParams {
+            delay_ms,
+            depth,
+            rate,
+            feedback,
+            wet,
+            dry,
+            motion_3d: self.params.motion_3d.smoothed.next(),
+            delay_spread: self.params.delay_spread.smoothed.next(),
+            pan_width: self.params.pan_width.smoothed.next(),
+            width: self.params.width.smoothed.next(),
+            voice_taper: self.params.voice_taper.smoothed.next(),
+            grain_detune: self.params.grain_detune.smoothed.next(),
+            cross_feedback: self.params.cross_feedback.smoothed.next(),
+            feedback_lowpass: self.params.feedback_lowpass.smoothed.next(),
+            feedback_drive: self.params.feedback_drive.smoothed.next(),
+            diffusion: self.params.diffusion.smoothed.next(),
+            noise: self.params.noise.smoothed.next(),
+            tone: self.params.tone.smoothed.next(),
+            wet_highpass: self.params.wet_highpass.smoothed.next(),
+            bass_preserve: self.params.bass_preserve.smoothed.next(),
+            feedback_highpass: self.params.feedback_highpass.smoothed.next(),
+            cross_feedback_lowpass: self.params.cross_feedback_lowpass.smoothed.next(),
+            dynamic_depth: self.params.dynamic_depth.smoothed.next(),
+            transient_duck: self.params.transient_duck.smoothed.next(),
+            sidechain_depth: self.params.sidechain_depth.smoothed.next(),
+            sidechain_wet: self.params.sidechain_wet.smoothed.next(),
+            stereo_phase: self.params.stereo_phase.smoothed.next(),
+            voice_spread: self.params.voice_spread.smoothed.next(),
+            rate_spread: self.params.rate_spread.smoothed.next(),
+            lfo_shape: self.params.lfo_shape.smoothed.next(),
+            lfo_humanize: self.params.lfo_humanize.smoothed.next(),
+            rate_mod: self.params.rate_mod.smoothed.next(),
+            chaos_amount: self.params.chaos_amount.smoothed.next(),
+            chaos_speed: self.params.chaos_speed.smoothed.next(),
+            second_depth: self.params.second_depth.smoothed.next(),
+            second_rate: self.params.second_rate.smoothed.next(),
+            second_delay_ms: self.params.second_delay_ms.smoothed.next(),
+            engine_blend: self.params.engine_blend.smoothed.next(),
+            low_crossover: self.params.low_crossover.smoothed.next(),
+            high_crossover: self.params.high_crossover.smoothed.next(),
+            band_depths: [
+                self.params.low_band_depth.smoothed.next(),
+                self.params.mid_band_depth.smoothed.next(),
+                self.params.high_band_depth.smoothed.next(),
+            ],
+            band_rates: [
+                self.params.low_band_rate.smoothed.next(),
+                self.params.mid_band_rate.smoothed.next(),
+                self.params.high_band_rate.smoothed.next(),
+            ],
+            haas_delay: self.params.haas_delay.smoothed.next(),
+            sidechain_level,
+            step_value: None,
+        };
+        if flanger {
+            frame.second_depth = extend_range(frame.second_depth, &DEPTH_RANGE, &FLANGER_DEPTH_RANGE);
+            frame.second_delay_ms = extend_range(frame.second_delay_ms, &DELAY_RANGE, &FLANGER_DELAY_RANGE);
+        }
+        if self.params.extended_ranges.value() {
+            if !flanger {
+                frame.second_depth = extend_range(frame.second_depth, &DEPTH_RANGE, &EXTENDED_DEPTH_RANGE);
+            }
+            frame.second_rate = extend_range(frame.second_rate, &RATE_RANGE, &EXTENDED_RATE_RANGE);
+        }
+        let step_mode: Option<stepseq::StepMode> = self.params.step_mode.value().into();
+        frame.step_value = step_mode.map(|_| self.step_sequencer.next_value());
+        frame
+    }
+
+    fn apply_frame_params(&mut self, frame: &FrameParams) {
+        self.engines.set_blend(frame.engine_blend);
+        self.haas.set_delay(frame.haas_delay);
+        let (multiband, second_engine) = self.engines.engines_mut();
+        multiband.set_crossovers(frame.low_crossover, frame.high_crossover);
+        let choruses = multiband.bands_mut().enumerate()
+            .map(|(band, chorus)| (chorus, frame.delay_ms, frame.depth * frame.band_depths[band], frame.rate * frame.band_rates[band]))
+            .chain(std::iter::once((second_engine, frame.second_delay_ms, frame.second_depth, frame.second_rate)));
+        for (chorus, delay_ms, depth, rate) in choruses {
+            chorus.set_params(self.sample_rate, delay_ms, frame.feedback, depth, rate, frame.wet, frame.dry);
+            chorus.set_motion_3d(frame.motion_3d);
+            chorus.set_delay_spread(frame.delay_spread);
+            chorus.set_pan_width(frame.pan_width);
+            chorus.set_width(frame.width);
+            chorus.set_voice_taper(frame.voice_taper);
+            chorus.set_grain_detune(frame.grain_detune);
+            chorus.set_cross_feedback(frame.cross_feedback);
+            chorus.set_feedback_tone(frame.feedback_lowpass, frame.feedback_highpass);
+            chorus.set_cross_feedback_lowpass(frame.cross_feedback_lowpass);
+            chorus.set_feedback_drive(frame.feedback_drive);
+            chorus.set_diffusion(frame.diffusion);
+            chorus.set_noise(frame.noise);
+            chorus.set_tone(frame.tone);
+            chorus.set_wet_highpass(frame.wet_highpass);
+            chorus.set_bass_preserve(frame.bass_preserve);
+            chorus.set_dynamic_depth(frame.dynamic_depth);
+            chorus.set_transient_duck(frame.transient_duck);
+            chorus.set_sidechain_depth(frame.sidechain_depth);
+            chorus.set_sidechain_wet(frame.sidechain_wet);
+            if let Some(level) = frame.sidechain_level {
+                chorus.process_sidechain(level);
+            }
+            chorus.set_stereo_phase(frame.stereo_phase);
+            chorus.set_voice_spread(frame.voice_spread);
+            chorus.set_rate_spread(frame.rate_spread);
+            chorus.set_lfo_skew(frame.lfo_shape);
+            chorus.set_lfo_humanize(frame.lfo_humanize);
+            chorus.set_rate_mod_amount(frame.rate_mod);
+            chorus.set_chaos_amount(frame.chaos_amount);
+            chorus.set_chaos_speed(frame.chaos_speed);
+            if let Some(step_value) = frame.step_value {
+                chorus.set_step_value(step_value);
+            }
+        }
+    }
+
+    /// the input `chorus_latency` samples late, as the bypass lets it through
+    fn bypass_frame(&mut self, left: f32, right: f32) -> (f32, f32) {
+        self.bypass_delay.push((left, right));
+        self.bypass_delay.get(self.chorus_latency as usize)
+    }
+
+    /// processes a block in place, every sample with its own parameter values, and writes the
+    /// wet voices alone to `wet_left` and `wet_right`
+    fn process_block(
+        &mut self,
+        left: &mut [f32],
+        right: &mut [f32],
+        wet_left: &mut [f32],
+        wet_right: &mut [f32],
+        frames: &[FrameParams],
+    ) {
+        for (j, frame) in frames.iter().enumerate() {
+            self.apply_frame_params(frame);
+            (left[j], right[j]) = self.process_frame(left[j], right[j]);
+            (wet_left[j], wet_right[j]) = self.wet_output;
+        }
+    }
+
+    /// one host sample, returns the output and the wet voices. With fixed blocks both come out a
+    /// block late, processed with the parameter values `frame` was read with
+    fn process_sample(&mut self, left: f32, right: f32, frame: FrameParams, bypassed: bool) -> ((f32, f32), (f32, f32)) {
+        if self.fixed_blocks_active {
+            let output = self.reblocker.push(left, right, frame);
+            let wet = self.wet_reblocker.push(0.0, 0.0, ());
+            if self.reblocker.block_ready() {
+                let (mut block_left, mut block_right, frames) = self.reblocker.take_block();
+                let mut wet_left = [0.0; reblock::BLOCK_SIZE];
+                let mut wet_right = [0.0; reblock::BLOCK_SIZE];
+                // bypassed blocks still pass through the reblocker, so the output keeps the
+                // latency reported to the host and doesn't shift in time when toggling
+                if bypassed {
+                    for (left, right) in block_left.iter_mut().zip(block_right.iter_mut()) {
+                        (*left, *right) = self.bypass_frame(*left, *right);
+                    }
+                } else {
+                    self.process_block(&mut block_left, &mut block_right, &mut wet_left, &mut wet_right, &frames);
+                }
+                self.reblocker.set_output(block_left, block_right);
+                self.wet_reblocker.set_output(wet_left, wet_right);
+            }
+            (output, wet)
+        } else if bypassed {
+            (self.bypass_frame(left, right), (0.0, 0.0))
+        } else {
+            self.apply_frame_params(&frame);
+            (self.process_frame(left, right), self.wet_output)
         }
     }
+
+    fn latency_samples(&self) -> u32 {
+        let block_latency = if self.fixed_blocks_active {
+            reblock::BLOCK_SIZE as u32
+        } else {
+            0
+        };
+        block_latency + self.chorus_latency
+    }
 }
 
 impl Plugin for ChorusPlugin {
@@ -104,9 +1565,24 @@ impl Plugin for ChorusPlugin {
             main_output_channels: NonZeroU32::new(2),
             ..AudioIOLayout::const_default()
         },
+        // same as above with a sidechain input that can drive the depth and level of the voices,
+        // the LFO as a control signal on an extra output and the voices alone on another, for
+        // hosts that can route those
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(2),
+            main_output_channels: NonZeroU32::new(2),
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[new_nonzero_u32(1), new_nonzero_u32(2)],
+            names: PortNames {
+                aux_inputs: &["Sidechain"],
+                aux_outputs: &["LFO", "Wet"],
+                ..PortNames::const_default()
+            },
+            ..AudioIOLayout::const_default()
+        },
     ];
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
+    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
 
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -124,17 +1600,48 @@ impl Plugin for ChorusPlugin {
         self.params.clone()
     }
 
+    fn filter_state(state: &mut PluginState) {
+        state::migrate_state(state);
+    }
+
     fn initialize(
         &mut self,
         _audio_io_layout: &AudioIOLayout,
         _buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
-        self.sample_rate = 2.0 * _buffer_config.sample_rate as f32;
+        // all parameters are stored in ms/Hz, so they are converted using the current sample rate
+        // here and presets made at a different rate sound the same
+        self.sample_rate = _buffer_config.sample_rate as f32;
 
-        self.chorus.resize_buffers(self.sample_rate);
+        self.engines.resize_buffers(self.sample_rate);
+        // per-voice feedback can be switched on at any time, which mustn't allocate
+        for chorus in self.engines.choruses_mut() {
+            chorus.allocate_voice_feedback(self.sample_rate);
+        }
+        self.haas.resize_buffers(self.sample_rate);
+        self.bypass_delay.resize(dual::latency_buffer_len(self.sample_rate));
         self.output_hpf.set_sample_rate(_buffer_config.sample_rate as f32);
         self.output_hpf.coefficients(filter::FilterType::HighPass2, 25.0, 0.707, 1.0);
+
+        self.fixed_blocks_active = self.params.fixed_blocks.value();
+        context.set_latency_samples(self.latency_samples());
+
+        // the table may have just been restored along with the rest of the state
+        if let Ok(table) = self.params.lfo_table.read() {
+            for chorus in self.engines.choruses_mut() {
+                chorus.set_lfo_table(&table);
+            }
+        }
+        if let Ok(steps) = self.params.step_values.read() {
+            self.step_sequencer.set_steps(&steps);
+        }
+        self.step_sequencer.set_sample_rate(self.sample_rate);
+        // the LFOs resume at the saved phase, the other sources start over from their seeds
+        let lfo_phase = self.params.lfo_phase.load(Ordering::Relaxed);
+        for chorus in self.engines.choruses_mut() {
+            chorus.restart_modulation(lfo_phase);
+        }
         // Resize buffers and perform other potentially expensive initialization operations here.
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
@@ -144,41 +1651,146 @@ impl Plugin for ChorusPlugin {
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
+        self.reblocker.reset();
+        self.wet_reblocker.reset();
+        self.bypass_delay.clear();
+        self.engines.reset();
+        self.haas.reset();
+        self.step_sequencer.reset();
     }
 
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
+        // the reset fades the output back in, so pressing it mid-playback doesn't click
+        if self.reset_requested.swap(false, Ordering::Relaxed) {
+            self.reset();
+        }
 
-        // In current configuration this function iterates as follows:
-        // 1. outer loop iterates block-size times
-        // 2. inner loop iterates channel-size times. 
+        if self.params.fixed_blocks.value() != self.fixed_blocks_active {
+            self.fixed_blocks_active = self.params.fixed_blocks.value();
+            self.reblocker.reset();
+            self.wet_reblocker.reset();
+            context.set_latency_samples(self.latency_samples());
+        }
 
-        for (i, channel_samples) in buffer.iter_samples().enumerate() {
+        // In current configuration this function iterates block-size times, both channels are
+        // read at once so they can be encoded to mid/side before processing.
 
-            let depth = self.params.depth.smoothed.next();
-            let rate = self.params.rate.smoothed.next();
-            let delay_ms = self.params.delay_ms.smoothed.next();
-            let feedback = self.params.feedback.smoothed.next();
-            let wet = self.params.wet.smoothed.next();
-            let dry = self.params.dry.smoothed.next();
+        let transport = context.transport();
+        // a position before the previous block's means the host looped (or jumped back)
+        let started = transport.playing && !self.was_playing;
+        let looped = transport.playing
+            && matches!((transport.pos_samples(), self.last_position_samples), (Some(position), Some(last)) if position < last);
+        if self.params.retrigger.value() && (started || looped) {
+            for chorus in self.engines.choruses_mut() {
+                chorus.restart_modulation(self.params.retrigger_phase.value());
+            }
+        }
+        self.was_playing = transport.playing;
+        self.last_position_samples = transport.pos_samples();
+
+        // the editor may hold the lock while drawing, the table is then picked up next block
+        if self.params.lfo_table_changed.swap(false, Ordering::Relaxed) {
+            match self.params.lfo_table.try_read() {
+                Ok(table) => {
+                    for chorus in self.engines.choruses_mut() {
+                        chorus.set_lfo_table(&table);
+                    }
+                }
+                Err(_) => self.params.lfo_table_changed.store(true, Ordering::Relaxed),
+            }
+        }
 
-            self.chorus.set_params(self.sample_rate, delay_ms, feedback, depth, rate, wet, dry);
+        if self.params.step_values_changed.swap(false, Ordering::Relaxed) {
+            match self.params.step_values.try_read() {
+                Ok(steps) => self.step_sequencer.set_steps(&steps),
+                Err(_) => self.params.step_values_changed.store(true, Ordering::Relaxed),
+            }
+        }
 
-            for (num, sample) in channel_samples.into_iter().enumerate() {
-                if num == 0 {
-                    *sample = self.chorus.process_left(*sample);
-                    *sample = self.output_hpf.process_left(*sample);
-                } else {
-                    *sample = self.chorus.process_right(*sample);
-                    *sample = self.output_hpf.process_right(*sample);
+        // the sequencer follows the song position while playing and otherwise runs free at the
+        // host tempo (120 BPM without one)
+        self.step_sequencer.set_tempo(transport.tempo.unwrap_or(120.0));
+        if let (true, Some(beats)) = (transport.playing, transport.pos_beats()) {
+            self.step_sequencer.sync(beats);
+        }
+        // without a tempo from the host the LFO keeps running free
+        let synced_rate = if self.params.rate_sync.value() {
+            context.transport().tempo.map(|tempo| self.params.rate_division.value().rate_hz(tempo as f32))
+        } else {
+            None
+        };
+
+        // the sidechain only exists with the second IO layout
+        self.apply_block_settings(!aux.inputs.is_empty(), synced_rate.is_some());
+
+        let bypassed = self.params.bypass.value();
+        let mut next_event = context.next_event();
+        for (i, mut channel_samples) in buffer.iter_samples().enumerate() {
+            while let Some(event) = next_event {
+                if event.timing() > i as u32 {
+                    break;
                 }
+                if let NoteEvent::NoteOn { note, .. } = event {
+                    self.key_track_note = Some(note);
+                }
+                next_event = context.next_event();
+            }
+
+            let sidechain_level = aux.inputs.first().map(|sidechain| {
+                let channels = sidechain.as_slice_immutable();
+                channels.iter().map(|channel| channel[i]).sum::<f32>() / channels.len().max(1) as f32
+            });
+            let frame = self.next_frame_params(synced_rate, sidechain_level);
+
+            let left = *channel_samples.get_mut(0).unwrap();
+            let right = *channel_samples.get_mut(1).unwrap();
+            let ((left, right), wet) = self.process_sample(left, right, frame, bypassed);
+
+            *channel_samples.get_mut(0).unwrap() = left;
+            *channel_samples.get_mut(1).unwrap() = right;
+
+            // the LFO control output only exists with the second IO layout, scaled to 0..1
+            if let Some(lfo_output) = aux.outputs.get_mut(0) {
+                let lfo_value = (self.engines.multiband().first().lfo_value() + 1.0) / 2.0;
+                for channel in lfo_output.as_slice() {
+                    channel[i] = lfo_value;
+                }
+            }
+            // so does the wet output, the voices alone no matter the wet and dry levels
+            if let Some(wet_output) = aux.outputs.get_mut(1) {
+                let channels = wet_output.as_slice();
+                channels[0][i] = wet.0;
+                channels[1][i] = wet.1;
             }
         }
 
+        self.buffer_overflow.store(self.engines.active_choruses().any(|chorus| chorus.exceeds_buffers()), Ordering::Relaxed);
+        self.feedback_energy.store(
+            self.engines.active_choruses().fold(0.0, |energy, chorus| chorus.feedback_rms().max(energy)),
+            Ordering::Relaxed,
+        );
+        // the bands' LFOs only run in step at equal band rates, otherwise each drifts at its own
+        // rate until the next restart. The meters follow the lowest band of the first engine
+        let chorus = self.engines.multiband().first();
+        self.params.lfo_phase.store(chorus.lfo_phase(), Ordering::Relaxed);
+        self.correlation.store(chorus.correlation(), Ordering::Relaxed);
+        let (left_modulation, right_modulation) = chorus.modulation_values();
+        let voice_count = chorus.voice_count();
+        self.lfo_feed.publish(&left_modulation[..voice_count], &right_modulation[..voice_count]);
+
+        // the dry delay only changes with the through-zero, oversampling and routing switches,
+        // the host hears about it from the next block on
+        let chorus_latency = self.engines.latency() as u32;
+        if chorus_latency != self.chorus_latency {
+            self.chorus_latency = chorus_latency;
+            context.set_latency_samples(self.latency_samples());
+        }
+
         ProcessStatus::Normal
     }
 
@@ -186,6 +1798,11 @@ impl Plugin for ChorusPlugin {
         editor::create(
             self.params.clone(),
             self.params.editor_state.clone(),
+            self.buffer_overflow.clone(),
+            self.correlation.clone(),
+            self.feedback_energy.clone(),
+            self.lfo_feed.clone(),
+            self.reset_requested.clone(),
         )
     }
 }
@@ -210,3 +1827,129 @@ impl Vst3Plugin for ChorusPlugin {
 
 //nih_export_clap!(Chorus);
 nih_export_vst3!(ChorusPlugin);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_extended_values_map_back_onto_the_default_range() {
+        let extended = Arc::new(AtomicBool::new(true));
+        let v2s = v2s_extendable(DEPTH_RANGE, EXTENDED_DEPTH_RANGE, extended.clone());
+        let s2v = s2v_extendable(DEPTH_RANGE, EXTENDED_DEPTH_RANGE, extended.clone());
+        for value in [0.0, 1.0, 5.0, 12.5, 25.0] {
+            let typed = s2v(&v2s(value)).unwrap();
+            assert!((typed - value).abs() < 0.01, "{value} came back as {typed}");
+        }
+        assert!((s2v("50").unwrap() - 25.0).abs() < 1e-4);
+
+        extended.store(false, Ordering::Relaxed);
+        assert_eq!(s2v("10"), Some(10.0));
+        assert_eq!(s2v("ten"), None);
+    }
+
+    #[test]
+    fn typed_flanger_values_map_back_onto_the_default_range() {
+        let flanger = Arc::new(AtomicBool::new(true));
+        let v2s = v2s_flanger(DELAY_RANGE, FLANGER_DELAY_RANGE, flanger.clone(), formatters::v2s_f32_rounded(2));
+        let s2v = s2v_flanger(DELAY_RANGE, FLANGER_DELAY_RANGE, flanger.clone(), Arc::new(|string: &str| string.trim().parse::<f32>().ok()));
+        for value in [0.1, 5.0, 15.0, 30.0, 50.0] {
+            let typed = s2v(&v2s(value)).unwrap();
+            // two decimals of the flanger range are coarse at its short end
+            assert!((typed - value).abs() < 0.2, "{value} came back as {typed}");
+        }
+        assert!((s2v("5").unwrap() - 50.0).abs() < 1e-3);
+
+        flanger.store(false, Ordering::Relaxed);
+        assert_eq!(s2v("15"), Some(15.0));
+    }
+
+    /// a plugin at its default settings, with the smoothers settled on the default values like
+    /// the host would leave them
+    fn default_plugin(fixed_blocks: bool) -> ChorusPlugin {
+        let mut plugin = ChorusPlugin::default();
+        for (_, param_ptr, _) in plugin.params.param_map() {
+            if let ParamPtr::FloatParam(param) = param_ptr {
+                let param = unsafe { &*param };
+                param.smoothed.reset(param.value());
+            }
+        }
+        plugin.output_hpf.set_sample_rate(plugin.sample_rate);
+        plugin.output_hpf.coefficients(filter::FilterType::HighPass2, 25.0, 0.707, 1.0);
+        plugin.fixed_blocks_active = fixed_blocks;
+        plugin
+    }
+
+    /// the left output of `input` fed to the plugin in host buffers of `host_block` samples,
+    /// with the delay and the wet level automated on every sample
+    fn render_automated(fixed_blocks: bool, host_block: usize, input: &[f32]) -> Vec<f32> {
+        let mut plugin = default_plugin(fixed_blocks);
+        let defaults = plugin.next_frame_params(None, None);
+        let mut output = Vec::new();
+        for (block, samples) in input.chunks(host_block).enumerate() {
+            plugin.apply_block_settings(false, false);
+            for (j, &x) in samples.iter().enumerate() {
+                let sweep = ((block * host_block + j) as f32 / 200.0).sin();
+                // without modulation the random start of the LFOs doesn't show in the output
+                let frame = FrameParams {
+                    delay_ms: 15.0 + 10.0 * sweep,
+                    depth: 0.0,
+                    second_depth: 0.0,
+                    wet: 0.5 + 0.4 * sweep,
+                    ..defaults
+                };
+                output.push(plugin.process_sample(x, x, frame, false).0.0);
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn automation_lands_on_the_same_samples_at_any_host_block_size() {
+        let input: Vec<f32> = (0..4096).map(|n| (n as f32 * 0.05).sin() * 0.5).collect();
+        let unblocked = render_automated(false, 512, &input);
+        assert!(unblocked.iter().any(|x| x.abs() > 0.1));
+        for host_block in [1, 7, 64, 100, 512] {
+            let fixed = render_automated(true, host_block, &input);
+            // the fixed blocks add a block of latency and nothing else
+            assert!(fixed[..reblock::BLOCK_SIZE].iter().all(|&x| x == 0.0));
+            for (n, (fixed, unblocked)) in fixed[reblock::BLOCK_SIZE..].iter().zip(&unblocked).enumerate() {
+                assert_eq!(fixed, unblocked, "sample {n} with host buffers of {host_block}");
+            }
+        }
+    }
+
+    /// where an impulse comes out of the plugin running with the long oversampling filters, and
+    /// so with latency reported for them
+    fn impulse_peak(fixed_blocks: bool, bypassed: bool) -> usize {
+        let mut plugin = default_plugin(fixed_blocks);
+        plugin.apply_block_settings(false, false);
+        for chorus in plugin.engines.choruses_mut() {
+            chorus.set_oversampling(Some(oversample::FilterOrder::Long));
+        }
+        // the dry path would crossfade to the new latency, the reset skips that
+        plugin.engines.reset();
+        plugin.chorus_latency = plugin.engines.latency() as u32;
+        // the dry alone, the voices would smear the peak
+        let frame = FrameParams { wet: 0.0, dry: 1.0, ..plugin.next_frame_params(None, None) };
+        let output: Vec<f32> = (0..512)
+            .map(|n| {
+                let x = if n == 0 { 1.0 } else { 0.0 };
+                plugin.process_sample(x, x, frame, bypassed).0.0
+            })
+            .collect();
+        (0..output.len()).fold(0, |peak, n| if output[n].abs() > output[peak].abs() { n } else { peak })
+    }
+
+    #[test]
+    fn bypass_keeps_the_reported_latency() {
+        for fixed_blocks in [false, true] {
+            let active = impulse_peak(fixed_blocks, false);
+            let bypassed = impulse_peak(fixed_blocks, true);
+            assert_eq!(bypassed, active, "fixed blocks {fixed_blocks}");
+
+            let block_latency = if fixed_blocks { reblock::BLOCK_SIZE } else { 0 };
+            assert_eq!(active, block_latency + oversample::FilterOrder::Long.latency(), "fixed blocks {fixed_blocks}");
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{chorus::Chorus, filter::LinkwitzRiley};
+
+/// most bands `set_band_count` accepts, all of them are allocated up front
+pub const MAX_BANDS: usize = 3;
+pub const DEFAULT_LOW_CROSSOVER_HZ: f32 = 250.0;
+pub const DEFAULT_HIGH_CROSSOVER_HZ: f32 = 2500.0;
+
+/// splits the input into up to `MAX_BANDS` bands with Linkwitz-Riley crossovers and runs each
+/// through a chorus of its own, so the lows can be chorused more subtly than the highs. The
+/// bands add back up to an all-pass, with a single band the chorus runs on the whole signal.
+///
+/// All band choruses are set up the same way through `bands_mut`, and differ only in what the
+/// caller sets per band. Their LFOs start out in step, but bands given different rates drift
+/// apart until their modulation is restarted, see `Chorus::restart_modulation`.
+pub struct Multiband {
+    // always `MAX_BANDS` long, only the first `band_count` are processed
+    bands: Vec<Chorus>,
+    band_count: usize,
+    // the low crossover splits off the lowest band, the high one the highest out of the rest.
+    // The lowest band goes through an all-pass matching the high crossover, so it stays in
+    // phase with the other two
+    low_crossover: LinkwitzRiley,
+    high_crossover: LinkwitzRiley,
+    low_compensation: LinkwitzRiley,
+    low_crossover_hz: f32,
+    high_crossover_hz: f32,
+    sample_rate: f32,
+}
+
+impl Multiband {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut multiband = Self {
+            bands: (0..MAX_BANDS).map(|_| Chorus::new(sample_rate, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0)).collect(),
+            band_count: 1,
+            low_crossover: LinkwitzRiley::new(),
+            high_crossover: LinkwitzRiley::new(),
+            low_compensation: LinkwitzRiley::new(),
+            low_crossover_hz: DEFAULT_LOW_CROSSOVER_HZ,
+            high_crossover_hz: DEFAULT_HIGH_CROSSOVER_HZ,
+            sample_rate,
+        };
+        multiband.update_crossovers();
+        multiband
+    }
+
+    /// all band choruses, including the ones not processed at the current band count, so they
+    /// come in with the current settings
+    pub fn bands_mut(&mut self) -> impl Iterator<Item = &mut Chorus> {
+        self.bands.iter_mut()
+    }
+
+    /// the chorus of the lowest band, the whole signal with a single band
+    pub fn first(&self) -> &Chorus {
+        &self.bands[0]
+    }
+
+    /// the choruses of the bands being processed
+    pub fn active_bands(&self) -> &[Chorus] {
+        &self.bands[..self.band_count]
+    }
+
+    /// number of bands, 1 to `MAX_BANDS`. Bands coming in start from a reset chorus, which
+    /// fades them in
+    pub fn set_band_count(&mut self, count: usize) {
+        let count = count.clamp(1, MAX_BANDS);
+        if count == self.band_count {
+            return;
+        }
+        for band in self.bands[self.band_count.min(count)..count].iter_mut() {
+            band.reset();
+        }
+        self.low_crossover.reset_filter();
+        self.high_crossover.reset_filter();
+        self.low_compensation.reset_filter();
+        self.band_count = count;
+    }
+
+    /// the frequencies between the bands. With two bands only the low one is used, the high one
+    /// is kept above the low one
+    pub fn set_crossovers(&mut self, low_hz: f32, high_hz: f32) {
+        let nyquist = self.sample_rate / 2.0;
+        let low_hz = if low_hz.is_finite() { low_hz.clamp(20.0, nyquist * 0.9) } else { DEFAULT_LOW_CROSSOVER_HZ };
+        let high_hz = if high_hz.is_finite() { high_hz.clamp(low_hz, nyquist * 0.9) } else { DEFAULT_HIGH_CROSSOVER_HZ };
+        if low_hz == self.low_crossover_hz && high_hz == self.high_crossover_hz {
+            return;
+        }
+        self.low_crossover_hz = low_hz;
+        self.high_crossover_hz = high_hz;
+        self.update_crossovers();
+    }
+
+    fn update_crossovers(&mut self) {
+        self.low_crossover.set_cutoff(self.sample_rate, self.low_crossover_hz);
+        self.high_crossover.set_cutoff(self.sample_rate, self.high_crossover_hz);
+        self.low_compensation.set_cutoff(self.sample_rate, self.high_crossover_hz);
+    }
+
+    /// reallocates the buffers, this is not realtime safe
+    pub fn resize_buffers(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for band in self.bands.iter_mut() {
+            band.resize_buffers(sample_rate);
+        }
+        self.update_crossovers();
+    }
+
+    pub fn reset(&mut self) {
+        for band in self.bands.iter_mut() {
+            band.reset();
+        }
+        self.low_crossover.reset_filter();
+        self.high_crossover.reset_filter();
+        self.low_compensation.reset_filter();
+    }
+
+    /// the input split into the processed bands, lowest first. Unused bands are 0
+    fn split_left(&mut self, x: f32) -> [f32; MAX_BANDS] {
+        match self.band_count {
+            1 => [x, 0.0, 0.0],
+            2 => {
+                let (low, high) = self.low_crossover.split_left(x);
+                [low, high, 0.0]
+            }
+            _ => {
+                let (low, rest) = self.low_crossover.split_left(x);
+                let (low_low, low_high) = self.low_compensation.split_left(low);
+                let (mid, high) = self.high_crossover.split_left(rest);
+                [low_low + low_high, mid, high]
+            }
+        }
+    }
+
+    fn split_right(&mut self, x: f32) -> [f32; MAX_BANDS] {
+        match self.band_count {
+            1 => [x, 0.0, 0.0],
+            2 => {
+                let (low, high) = self.low_crossover.split_right(x);
+                [low, high, 0.0]
+            }
+            _ => {
+                let (low, rest) = self.low_crossover.split_right(x);
+                let (low_low, low_high) = self.low_compensation.split_right(low);
+                let (mid, high) = self.high_crossover.split_right(rest);
+                [low_low + low_high, mid, high]
+            }
+        }
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        let bands = self.split_left(x);
+        let mut out = 0.0;
+        for (band, x) in self.bands[..self.band_count].iter_mut().zip(bands) {
+            out += band.process_left(x);
+        }
+        out
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        let bands = self.split_right(x);
+        let mut out = 0.0;
+        for (band, x) in self.bands[..self.band_count].iter_mut().zip(bands) {
+            out += band.process_right(x);
+        }
+        out
+    }
+
+    pub fn process_stereo(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let left_bands = self.split_left(left);
+        let right_bands = self.split_right(right);
+        let mut out = (0.0, 0.0);
+        for (band, (left, right)) in self.bands[..self.band_count].iter_mut().zip(left_bands.into_iter().zip(right_bands)) {
+            let (band_left, band_right) = band.process_stereo(left, right);
+            out.0 += band_left;
+            out.1 += band_right;
+        }
+        out
+    }
+
+    /// see `Chorus::process_dual_mono`
+    pub fn process_dual_mono(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let left_bands = self.split_left(left);
+        let right_bands = self.split_right(right);
+        let mut out = (0.0, 0.0);
+        for (band, (left, right)) in self.bands[..self.band_count].iter_mut().zip(left_bands.into_iter().zip(right_bands)) {
+            let (band_left, band_right) = band.process_dual_mono(left, right);
+            out.0 += band_left;
+            out.1 += band_right;
+        }
+        out
+    }
+
+    /// the voices of all bands in the last processed frame, see `Chorus::wet_output`
+    pub fn wet_output(&self) -> (f32, f32) {
+        self.active_bands().iter().fold((0.0, 0.0), |(left, right), band| {
+            let (band_left, band_right) = band.wet_output();
+            (left + band_left, right + band_right)
+        })
+    }
+}
\ No newline at end of file
@@ -0,0 +1,167 @@
+use crate::ring_buffer::RingBuffer;
+
+// lengths of the anti-aliasing filters, odd so the latency is a whole number of samples
+const SHORT_TAPS: usize = 15;
+const LONG_TAPS: usize = 63;
+/// the most latency the oversampler adds, with the long filters
+pub const MAX_LATENCY: usize = (LONG_TAPS - 1) / 2;
+
+/// length of the oversampler's anti-aliasing filters. The long filters reject far more of the
+/// aliasing, but add more latency and cost more CPU
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterOrder {
+    Short,
+    Long,
+}
+
+impl FilterOrder {
+    fn taps(self) -> usize {
+        match self {
+            FilterOrder::Short => SHORT_TAPS,
+            FilterOrder::Long => LONG_TAPS,
+        }
+    }
+
+    /// latency in samples of the base rate the oversampler adds with this order, the filters
+    /// going up and down each delay by half their length at the doubled rate
+    pub fn latency(self) -> usize {
+        (self.taps() - 1) / 2
+    }
+}
+
+/// Blackman windowed half-band lowpass, cutting off at the Nyquist frequency of the base rate.
+/// Normalized to unity gain at DC
+fn kernel(taps: usize) -> Vec<f64> {
+    let center = (taps - 1) as f64 / 2.0;
+    let kernel: Vec<f64> = (0..taps)
+        .map(|n| {
+            let t = n as f64 - center;
+            let sinc = if t == 0.0 { 0.5 } else { (core::f64::consts::FRAC_PI_2 * t).sin() / (core::f64::consts::PI * t) };
+            let phase = core::f64::consts::TAU * n as f64 / (taps - 1) as f64;
+            sinc * (0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos())
+        })
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.into_iter().map(|weight| weight / sum).collect()
+}
+
+fn convolve(buffer: &RingBuffer<f64>, kernel: &[f64]) -> f64 {
+    kernel.iter().enumerate().map(|(i, weight)| buffer.get(i) * weight).sum()
+}
+
+/// runs a nonlinear stage at twice the sample rate, so the harmonics it adds up to the doubled
+/// Nyquist frequency are filtered out instead of folding back as aliasing. The filters of both
+/// orders are built up front, so switching between them doesn't allocate
+#[derive(Clone)]
+pub struct Oversampler {
+    order: FilterOrder,
+    short_kernel: Vec<f64>,
+    long_kernel: Vec<f64>,
+    // the zero stuffed input and the stage's output, both at the doubled rate
+    up_buffer: RingBuffer<f64>,
+    down_buffer: RingBuffer<f64>,
+}
+
+impl Oversampler {
+    pub fn new(order: FilterOrder) -> Self {
+        Self {
+            order,
+            short_kernel: kernel(SHORT_TAPS),
+            long_kernel: kernel(LONG_TAPS),
+            up_buffer: RingBuffer::new(LONG_TAPS),
+            down_buffer: RingBuffer::new(LONG_TAPS),
+        }
+    }
+
+    /// switches the filter order, the filters start over from silence
+    pub fn set_order(&mut self, order: FilterOrder) {
+        if order != self.order {
+            self.order = order;
+            self.clear();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.up_buffer.clear();
+        self.down_buffer.clear();
+    }
+
+    pub fn latency(&self) -> usize {
+        self.order.latency()
+    }
+
+    /// runs `stage` on `x` at the doubled rate, the result comes out `latency()` samples late
+    pub fn process(&mut self, x: f64, mut stage: impl FnMut(f64) -> f64) -> f64 {
+        let kernel = match self.order {
+            FilterOrder::Short => &self.short_kernel,
+            FilterOrder::Long => &self.long_kernel,
+        };
+        // the stuffed zeros halve the level, which the factor of two makes up for
+        self.up_buffer.push(2.0 * x);
+        self.down_buffer.push(stage(convolve(&self.up_buffer, kernel)));
+        // only the even samples are kept, so the odd ones don't need the down filter
+        let y = convolve(&self.down_buffer, kernel);
+        self.up_buffer.push(0.0);
+        self.down_buffer.push(stage(convolve(&self.up_buffer, kernel)));
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    /// level of `frequency` in `signal`, a single bin of a Hann windowed DFT
+    fn level(signal: &[f64], frequency: f64) -> f64 {
+        let len = signal.len() as f64;
+        let (re, im) = signal.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, x)| {
+            let window = 0.5 - 0.5 * (core::f64::consts::TAU * n as f64 / len).cos();
+            let phase = core::f64::consts::TAU * frequency * n as f64 / SAMPLE_RATE;
+            (re + x * window * phase.cos(), im - x * window * phase.sin())
+        });
+        (re * re + im * im).sqrt() / len
+    }
+
+    /// a 10 kHz sine through a hard clipper, `None` without oversampling
+    fn clip_sine(order: Option<FilterOrder>) -> Vec<f64> {
+        let clip = |x: f64| x.clamp(-0.5, 0.5);
+        let mut oversampler = order.map(Oversampler::new);
+        (0..8192)
+            .map(|n| {
+                let x = (core::f64::consts::TAU * 10000.0 * n as f64 / SAMPLE_RATE).sin();
+                match oversampler.as_mut() {
+                    Some(oversampler) => oversampler.process(x, clip),
+                    None => clip(x),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn longer_filters_reject_more_aliasing() {
+        // the third harmonic at 30 kHz folds back to 14.1 kHz
+        let alias = SAMPLE_RATE - 30000.0;
+        let plain = level(&clip_sine(None), alias);
+        let short = level(&clip_sine(Some(FilterOrder::Short)), alias);
+        let long = level(&clip_sine(Some(FilterOrder::Long)), alias);
+        assert!(short < plain / 5.0, "{short} against {plain}");
+        assert!(long < short / 100.0, "{long} against {short}");
+
+        // the fundamental comes through either way
+        let fundamental = level(&clip_sine(None), 10000.0);
+        let oversampled = level(&clip_sine(Some(FilterOrder::Long)), 10000.0);
+        assert!((oversampled / fundamental - 1.0).abs() < 0.05, "{oversampled} against {fundamental}");
+    }
+
+    #[test]
+    fn latency_is_whole_samples() {
+        for order in [FilterOrder::Short, FilterOrder::Long] {
+            let mut oversampler = Oversampler::new(order);
+            let impulse: Vec<f64> = (0..100).map(|n| oversampler.process(if n == 0 { 1.0 } else { 0.0 }, |x| x)).collect();
+            let peak = impulse.iter().enumerate().fold(0, |peak, (n, x)| if x.abs() > impulse[peak].abs() { n } else { peak });
+            assert_eq!(peak, order.latency());
+        }
+    }
+}
\ No newline at end of file
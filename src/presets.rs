@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use nih_plug::nih_log;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PresetCategory {
+    Chorus,
+    Flanger,
+    Vibrato,
+    Creative,
+}
+
+impl PresetCategory {
+    pub const ALL: [PresetCategory; 4] = [
+        PresetCategory::Chorus,
+        PresetCategory::Flanger,
+        PresetCategory::Vibrato,
+        PresetCategory::Creative,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PresetCategory::Chorus => "Chorus",
+            PresetCategory::Flanger => "Flanger",
+            PresetCategory::Vibrato => "Vibrato",
+            PresetCategory::Creative => "Creative",
+        }
+    }
+}
+
+/// A preset as stored in the user presets folder (one JSON file per preset). Values are plain
+/// parameter values keyed by the parameter's `#[id]`, parameters that are missing are set to
+/// their defaults when the preset gets loaded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Preset {
+    pub name: String,
+    pub category: PresetCategory,
+    pub values: BTreeMap<String, f32>,
+}
+
+impl Preset {
+    fn new(name: &str, category: PresetCategory, values: &[(&str, f32)]) -> Self {
+        Self {
+            name: name.to_string(),
+            category,
+            values: values.iter().map(|(id, value)| (id.to_string(), *value)).collect(),
+        }
+    }
+}
+
+pub fn factory_presets() -> Vec<Preset> {
+    vec![
+        Preset::new("Classic", PresetCategory::Chorus, &[
+            ("depth", 5.0), ("rate", 0.5), ("delay_ms", 15.0), ("feedback", 0.0), ("wet", 0.5), ("dry", 0.5),
+        ]),
+        Preset::new("Wide", PresetCategory::Chorus, &[
+            ("depth", 8.0), ("rate", 0.3), ("delay_ms", 20.0), ("feedback", 0.1), ("wet", 0.5), ("dry", 0.5),
+            ("motion_3d", 2.0),
+        ]),
+        Preset::new("Jet", PresetCategory::Flanger, &[
+            ("depth", 2.0), ("rate", 0.15), ("delay_ms", 1.0), ("feedback", 0.8), ("wet", 0.5), ("dry", 0.5),
+        ]),
+        Preset::new("Pitch Wobble", PresetCategory::Vibrato, &[
+            ("depth", 4.0), ("rate", 5.0), ("delay_ms", 5.0), ("feedback", 0.0), ("wet", 1.0), ("dry", 0.0),
+        ]),
+        Preset::new("Seasick", PresetCategory::Creative, &[
+            ("depth", 20.0), ("rate", 0.2), ("delay_ms", 30.0), ("feedback", 0.5), ("wet", 0.6), ("dry", 0.4),
+            ("dynamic_depth", 0.5),
+        ]),
+    ]
+}
+
+pub fn user_presets_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let base = std::env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(target_os = "macos")]
+    let base = std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join("Library").join("Application Support"));
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")));
+
+    base.map(|base| base.join("tsk_chorus").join("presets"))
+}
+
+/// reads every `.json` preset in the user presets folder. A missing folder means no user presets,
+/// files that can't be read or parsed are skipped
+pub fn user_presets() -> Vec<Preset> {
+    let Some(dir) = user_presets_dir() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut presets: Vec<Preset> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .filter_map(|path| {
+            let preset = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|json| serde_json::from_str::<Preset>(&json).map_err(|err| err.to_string()));
+            match preset {
+                Ok(preset) => Some(preset),
+                Err(err) => {
+                    nih_log!("Skipping preset '{}': {}", path.display(), err);
+                    None
+                }
+            }
+        })
+        .collect();
+    presets.sort_by(|a, b| a.name.cmp(&b.name));
+
+    presets
+}
+
+/// factory presets followed by the user presets
+pub fn all_presets() -> Vec<Preset> {
+    let mut presets = factory_presets();
+    presets.extend(user_presets());
+    presets
+}
\ No newline at end of file
@@ -0,0 +1,23 @@
+/// xorshift generator, cheap enough to draw from on the audio thread. Far from good randomness,
+/// but plenty for hiss and random modulation
+#[derive(Clone)]
+pub struct Xorshift {
+    state: u32,
+}
+
+impl Xorshift {
+    /// `seed` picks the sequence, the same seed always plays the same one
+    pub fn new(seed: u32) -> Self {
+        // xorshift gets stuck on 0
+        Self { state: seed.max(1) }
+    }
+
+    /// next value of the sequence, -1 to 1
+    pub fn next_value(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        self.state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
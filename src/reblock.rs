@@ -0,0 +1,59 @@
+/// size of the internal blocks, this is also the latency the re-blocking adds
+pub const BLOCK_SIZE: usize = 64;
+
+/// Collects host samples into fixed size blocks, independent of the host's buffer sizes. Every
+/// sample pushed returns the processed sample from exactly `BLOCK_SIZE` samples earlier, and once
+/// `block_ready()` the collected block has to be taken, processed and handed back with
+/// `set_output()`. Partial blocks simply carry over into the next host buffer. Each sample can
+/// carry a `T` along, like the parameter values it has to be processed with, so the block is
+/// processed with the same automation as the samples were pushed with
+pub struct Reblocker<T: Copy + Default = ()> {
+    input_left: [f32; BLOCK_SIZE],
+    input_right: [f32; BLOCK_SIZE],
+    input_frames: [T; BLOCK_SIZE],
+    output_left: [f32; BLOCK_SIZE],
+    output_right: [f32; BLOCK_SIZE],
+    position: usize,
+}
+
+impl<T: Copy + Default> Reblocker<T> {
+    pub fn new() -> Self {
+        Self {
+            input_left: [0.0; BLOCK_SIZE],
+            input_right: [0.0; BLOCK_SIZE],
+            input_frames: [T::default(); BLOCK_SIZE],
+            output_left: [0.0; BLOCK_SIZE],
+            output_right: [0.0; BLOCK_SIZE],
+            position: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn push(&mut self, left: f32, right: f32, frame: T) -> (f32, f32) {
+        let output = (self.output_left[self.position], self.output_right[self.position]);
+
+        self.input_left[self.position] = left;
+        self.input_right[self.position] = right;
+        self.input_frames[self.position] = frame;
+        self.position = (self.position + 1) % BLOCK_SIZE;
+
+        output
+    }
+
+    /// true right after the push that completed a block
+    pub fn block_ready(&self) -> bool {
+        self.position == 0
+    }
+
+    pub fn take_block(&self) -> ([f32; BLOCK_SIZE], [f32; BLOCK_SIZE], [T; BLOCK_SIZE]) {
+        (self.input_left, self.input_right, self.input_frames)
+    }
+
+    pub fn set_output(&mut self, left: [f32; BLOCK_SIZE], right: [f32; BLOCK_SIZE]) {
+        self.output_left = left;
+        self.output_right = right;
+    }
+}
\ No newline at end of file
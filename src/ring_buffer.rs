@@ -0,0 +1,45 @@
+/// Fixed-size ring buffer over a boxed slice. `push` stores the newest sample and `get(0)`
+/// returns it, `get(n)` the one pushed n samples ago. Only needs `alloc`, so the DSP modules
+/// don't depend on `std` collections. Holds `f32` samples unless asked for another type.
+#[derive(Clone)]
+pub struct RingBuffer<T = f32> {
+    buffer: Box<[T]>,
+    // index of the newest sample, moves backwards so reads are `write_pos + delay`
+    write_pos: usize,
+}
+
+impl<T: Copy + Default> RingBuffer<T> {
+    pub fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![T::default(); len.max(1)].into_boxed_slice(),
+            write_pos: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// reallocates the buffer, this is not realtime safe
+    pub fn resize(&mut self, len: usize) {
+        *self = Self::new(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.fill(T::default());
+    }
+
+    pub fn push(&mut self, x: T) {
+        self.write_pos = if self.write_pos == 0 {
+            self.buffer.len() - 1
+        } else {
+            self.write_pos - 1
+        };
+        self.buffer[self.write_pos] = x;
+    }
+
+    /// the sample pushed `delay` samples ago, wraps around for delays longer than the buffer
+    pub fn get(&self, delay: usize) -> T {
+        self.buffer[(self.write_pos + delay) % self.buffer.len()]
+    }
+}
\ No newline at end of file
@@ -0,0 +1,118 @@
+use nih_plug::prelude::Enum;
+use nih_plug::wrapper::state::{ParamValue, PluginState};
+
+use crate::{InputMode, Quality};
+
+/// bumped whenever a change needs saved states to be converted in `migrate_state`
+pub const STATE_VERSION: u32 = 4;
+pub const STATE_VERSION_KEY: &str = "state-version";
+
+/// Upgrades a state saved by an older version of the plugin one version at a time. Called
+/// through `Plugin::filter_state` before the state is loaded. Parameters missing from old
+/// states keep their defaults.
+pub fn migrate_state(state: &mut PluginState) {
+    // states from before the version field existed are version 1
+    let version: u32 = state
+        .fields
+        .get(STATE_VERSION_KEY)
+        .and_then(|version| serde_json::from_str(version).ok())
+        .unwrap_or(1);
+
+    if version < 2 {
+        // version 1 ran the engine at twice the host sample rate, so delay and depth sounded twice
+        // as long and the LFO ran at half the displayed rate. Rescale so old projects sound the
+        // same, as far as the parameter ranges allow
+        scale_f32(state, "delay_ms", 2.0, 0.1, 50.0);
+        scale_f32(state, "depth", 2.0, 0.0, 25.0);
+        scale_f32(state, "rate", 0.5, 0.02, 10.0);
+    }
+
+    if version < 3 {
+        // version 2 had a force mono input switch, which became the mono sum input mode. Enum
+        // parameters are saved as their variant index
+        if let Some(ParamValue::Bool(force_mono)) = state.params.remove("force_mono_input") {
+            let input_mode = if force_mono { InputMode::MonoSum } else { InputMode::Stereo };
+            state.params.insert("input_mode".to_string(), ParamValue::I32(input_mode.to_index() as i32));
+        }
+    }
+
+    if version < 4 {
+        // version 3's interpolation choices became the quality setting, which puts them in order
+        // of cost
+        if let Some(ParamValue::I32(index)) = state.params.get_mut("interpolation") {
+            let quality = match *index {
+                1 => Quality::Cubic,
+                2 => Quality::AllPass,
+                _ => Quality::Linear,
+            };
+            *index = quality.to_index() as i32;
+        }
+    }
+
+    state.fields.insert(STATE_VERSION_KEY.to_string(), STATE_VERSION.to_string());
+}
+
+fn scale_f32(state: &mut PluginState, id: &str, factor: f32, min: f32, max: f32) {
+    if let Some(ParamValue::F32(value)) = state.params.get_mut(id) {
+        *value = (*value * factor).clamp(min, max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the parameters of a state saved by version 1, which had no version field yet
+    fn version_1_state() -> PluginState {
+        PluginState {
+            version: "0.1.0".to_string(),
+            params: [
+                ("delay_ms".to_string(), ParamValue::F32(20.0)),
+                ("depth".to_string(), ParamValue::F32(15.0)),
+                ("rate".to_string(), ParamValue::F32(1.0)),
+                ("force_mono_input".to_string(), ParamValue::Bool(true)),
+            ]
+            .into_iter()
+            .collect(),
+            fields: Default::default(),
+        }
+    }
+
+    fn f32_param(state: &PluginState, id: &str) -> f32 {
+        match state.params.get(id) {
+            Some(ParamValue::F32(value)) => *value,
+            _ => panic!("{id} is missing or not a float"),
+        }
+    }
+
+    fn i32_param(state: &PluginState, id: &str) -> i32 {
+        match state.params.get(id) {
+            Some(ParamValue::I32(value)) => *value,
+            _ => panic!("{id} is missing or not an index"),
+        }
+    }
+
+    #[test]
+    fn version_1_states_load_into_the_current_version() {
+        let mut state = version_1_state();
+        migrate_state(&mut state);
+
+        // the double rate engine's times, clamped to the ranges
+        assert_eq!(f32_param(&state, "delay_ms"), 40.0);
+        assert_eq!(f32_param(&state, "depth"), 25.0);
+        assert_eq!(f32_param(&state, "rate"), 0.5);
+
+        assert!(!state.params.contains_key("force_mono_input"));
+        assert_eq!(i32_param(&state, "input_mode"), InputMode::MonoSum.to_index() as i32);
+        // the quality setting came later, the state leaves it at its default
+        assert!(!state.params.contains_key("interpolation"));
+        assert_eq!(state.fields.get(STATE_VERSION_KEY), Some(&STATE_VERSION.to_string()));
+
+        // loading it again leaves the migrated state alone
+        let migrated = state.params.len();
+        migrate_state(&mut state);
+        assert_eq!(state.params.len(), migrated);
+        assert_eq!(f32_param(&state, "delay_ms"), 40.0);
+        assert_eq!(i32_param(&state, "input_mode"), InputMode::MonoSum.to_index() as i32);
+    }
+}
\ No newline at end of file
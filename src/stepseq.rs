@@ -0,0 +1,77 @@
+/// number of steps in the sequence
+pub const STEPS: usize = 16;
+// each step lasts a sixteenth note
+const STEP_BEATS: f64 = 0.25;
+// the delay time glides to each new step instead of jumping, which would click
+const GLIDE_MS: f32 = 5.0;
+
+/// how the sequencer is combined with the LFOs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepMode {
+    // every voice follows the sequence instead of its LFO
+    Replace,
+    // the sequence is added to the LFOs
+    Sum,
+}
+
+/// 16 step modulation source running in sixteenth notes of the host tempo
+pub struct StepSequencer {
+    steps: [f32; STEPS],
+    // position in quarter notes, wraps around after the last step
+    beats: f64,
+    beats_per_sample: f64,
+    value: f32,
+    glide_coeff: f32,
+    sample_rate: f32,
+}
+
+impl StepSequencer {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut sequencer = Self {
+            steps: [0.0; STEPS],
+            beats: 0.0,
+            beats_per_sample: 0.0,
+            value: 0.0,
+            glide_coeff: 0.0,
+            sample_rate,
+        };
+        sequencer.set_sample_rate(sample_rate);
+        sequencer
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.glide_coeff = (-1.0 / (GLIDE_MS / 1000.0 * sample_rate)).exp();
+    }
+
+    pub fn reset(&mut self) {
+        self.beats = 0.0;
+        self.value = 0.0;
+    }
+
+    /// replaces the step values, -1 to 1. Shorter slices only replace the first steps
+    pub fn set_steps(&mut self, steps: &[f32]) {
+        for (step, new_step) in self.steps.iter_mut().zip(steps) {
+            *step = if new_step.is_finite() { new_step.clamp(-1.0, 1.0) } else { 0.0 };
+        }
+    }
+
+    /// tempo in BPM the sequence runs at until the next call
+    pub fn set_tempo(&mut self, tempo: f64) {
+        self.beats_per_sample = tempo / 60.0 / self.sample_rate as f64;
+    }
+
+    /// jumps to the host's song position in quarter notes, so the steps line up with the bars
+    pub fn sync(&mut self, beats: f64) {
+        self.beats = beats.rem_euclid(STEPS as f64 * STEP_BEATS);
+    }
+
+    /// value of the current step, glided, and advances by one sample
+    pub fn next_value(&mut self) -> f32 {
+        let step = (self.beats / STEP_BEATS) as usize % STEPS;
+        self.value = self.steps[step] + self.glide_coeff * (self.value - self.steps[step]);
+
+        self.beats = (self.beats + self.beats_per_sample) % (STEPS as f64 * STEP_BEATS);
+        self.value
+    }
+}
\ No newline at end of file
@@ -0,0 +1,8 @@
+/// splits a stereo pair into mid and side, scaled so `decode_mid_side` restores it exactly
+pub fn encode_mid_side(left: f32, right: f32) -> (f32, f32) {
+    ((left + right) / 2.0, (left - right) / 2.0)
+}
+
+pub fn decode_mid_side(mid: f32, side: f32) -> (f32, f32) {
+    (mid + side, mid - side)
+}
@@ -0,0 +1,155 @@
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use nih_plug_vizia::vizia::prelude::*;
+use nih_plug_vizia::vizia::vg;
+
+use crate::ChorusParams;
+
+/// shows one of the persisted value tables (the custom LFO waveform, the step sequence) and lets
+/// it be drawn over with the mouse. The horizontal axis runs through the table, the vertical one
+/// goes from -1 (bottom) to 1 (top).
+pub struct TableEditor {
+    params: Arc<ChorusParams>,
+    table: fn(&ChorusParams) -> &RwLock<Vec<f32>>,
+    // flag telling the audio thread to pick up the edited table
+    changed: fn(&ChorusParams) -> &AtomicBool,
+    // draws each point as a flat step instead of connecting them with lines
+    stepped: bool,
+    drawing: bool,
+    // table point and value of the previous mouse position while drawing, fast strokes are
+    // filled in between so they don't leave gaps
+    last_point: Option<(usize, f32)>,
+}
+
+impl TableEditor {
+    pub fn new(
+        cx: &mut Context,
+        params: Arc<ChorusParams>,
+        table: fn(&ChorusParams) -> &RwLock<Vec<f32>>,
+        changed: fn(&ChorusParams) -> &AtomicBool,
+        stepped: bool,
+    ) -> Handle<Self> {
+        Self {
+            params,
+            table,
+            changed,
+            stepped,
+            drawing: false,
+            last_point: None,
+        }
+        .build(cx, |_| {})
+    }
+
+    /// table point and value under the given window coordinates
+    fn point_at(&self, cx: &EventContext, x: f32, y: f32) -> (usize, f32) {
+        let bounds = cx.cache.get_bounds(cx.current());
+        let len = (self.table)(&self.params).read().map(|table| table.len()).unwrap_or(1).max(1);
+
+        let t = ((x - bounds.x) / bounds.w).clamp(0.0, 1.0);
+        let index = ((t * len as f32) as usize).min(len - 1);
+        let value = (1.0 - 2.0 * (y - bounds.y) / bounds.h).clamp(-1.0, 1.0);
+        (index, value)
+    }
+
+    fn draw_to(&mut self, cx: &mut EventContext, x: f32, y: f32) {
+        let (index, value) = self.point_at(cx, x, y);
+        let (from_index, from_value) = self.last_point.unwrap_or((index, value));
+
+        if let Ok(mut table) = (self.table)(&self.params).write() {
+            let steps = from_index.abs_diff(index);
+            for step in 0..=steps {
+                let t = if steps == 0 { 1.0 } else { step as f32 / steps as f32 };
+                let i = if index >= from_index { from_index + step } else { from_index - step };
+                if let Some(point) = table.get_mut(i) {
+                    *point = from_value + (value - from_value) * t;
+                }
+            }
+        }
+        (self.changed)(&self.params).store(true, Ordering::Relaxed);
+
+        self.last_point = Some((index, value));
+        cx.needs_redraw();
+    }
+}
+
+impl View for TableEditor {
+    fn element(&self) -> Option<&'static str> {
+        Some("lfo-table")
+    }
+
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event, meta| match *window_event {
+            WindowEvent::MouseDown(MouseButton::Left) => {
+                self.drawing = true;
+                self.last_point = None;
+                cx.capture();
+                cx.set_active(true);
+                let (x, y) = (cx.mouse.cursorx, cx.mouse.cursory);
+                self.draw_to(cx, x, y);
+
+                meta.consume();
+            }
+            WindowEvent::MouseUp(MouseButton::Left) => {
+                if self.drawing {
+                    self.drawing = false;
+                    cx.release();
+                    cx.set_active(false);
+
+                    meta.consume();
+                }
+            }
+            WindowEvent::MouseMove(x, y) => {
+                if self.drawing {
+                    self.draw_to(cx, x, y);
+
+                    meta.consume();
+                }
+            }
+            _ => {}
+        });
+    }
+
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+        if bounds.w == 0.0 || bounds.h == 0.0 {
+            return;
+        }
+
+        let mut background = vg::Path::new();
+        background.rect(bounds.x, bounds.y, bounds.w, bounds.h);
+        canvas.fill_path(&mut background, &vg::Paint::color(vg::Color::rgbf(0.15, 0.15, 0.15)));
+
+        let mut center = vg::Path::new();
+        center.move_to(bounds.x, bounds.y + bounds.h / 2.0);
+        center.line_to(bounds.x + bounds.w, bounds.y + bounds.h / 2.0);
+        let mut center_paint = vg::Paint::color(vg::Color::rgbf(0.3, 0.3, 0.3));
+        center_paint.set_line_width(1.0);
+        canvas.stroke_path(&mut center, &center_paint);
+
+        let Ok(table) = (self.table)(&self.params).read() else {
+            return;
+        };
+        if table.is_empty() {
+            return;
+        }
+
+        let mut line = vg::Path::new();
+        let point_width = bounds.w / table.len() as f32;
+        for (i, value) in table.iter().enumerate() {
+            let x = bounds.x + point_width * i as f32;
+            let y = bounds.y + bounds.h * (1.0 - value.clamp(-1.0, 1.0)) / 2.0;
+            if self.stepped {
+                line.move_to(x + 1.0, y);
+                line.line_to(x + point_width - 1.0, y);
+            } else if i == 0 {
+                line.move_to(x + point_width / 2.0, y);
+            } else {
+                line.line_to(x + point_width / 2.0, y);
+            }
+        }
+        let mut line_paint = vg::Paint::color(vg::Color::rgbf(0.9, 0.9, 0.9));
+        line_paint.set_line_width(1.5);
+        canvas.stroke_path(&mut line, &line_paint);
+    }
+}
\ No newline at end of file